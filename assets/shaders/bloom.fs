@@ -0,0 +1,40 @@
+#version 330
+
+// Input vertex attributes (from vertex shader)
+in vec2 fragTexCoord;
+in vec4 fragColor;
+
+// Input uniform values
+uniform sampler2D texture0;
+uniform vec4 colDiffuse;
+
+// Set from postfx::Chain
+uniform vec2 resolution;
+uniform float intensity;
+
+// Output fragment color
+out vec4 finalColor;
+
+const float threshold = 0.6;
+const int radius = 4;
+
+void main()
+{
+    vec2 texel = 2.0/resolution;
+    vec3 texelColor = texture(texture0, fragTexCoord).rgb;
+    vec3 glow = vec3(0.0);
+
+    // box blur of everything brighter than the threshold
+    for (int x = -radius; x <= radius; x++)
+    {
+        for (int y = -radius; y <= radius; y++)
+        {
+            vec3 sampleColor = texture(texture0, fragTexCoord + vec2(x, y)*texel).rgb;
+            glow += max(sampleColor - threshold, 0.0);
+        }
+    }
+
+    glow /= float((2*radius + 1)*(2*radius + 1));
+
+    finalColor = vec4(texelColor + glow*intensity*4.0, 1.0);
+}
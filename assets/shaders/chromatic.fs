@@ -0,0 +1,27 @@
+#version 330
+
+// Input vertex attributes (from vertex shader)
+in vec2 fragTexCoord;
+in vec4 fragColor;
+
+// Input uniform values
+uniform sampler2D texture0;
+uniform vec4 colDiffuse;
+
+// Set from postfx::Chain
+uniform float intensity;
+
+// Output fragment color
+out vec4 finalColor;
+
+void main()
+{
+    // red and blue drift apart towards the edges
+    vec2 offset = (fragTexCoord - 0.5)*0.012*intensity;
+
+    float red = texture(texture0, fragTexCoord + offset).r;
+    float green = texture(texture0, fragTexCoord).g;
+    float blue = texture(texture0, fragTexCoord - offset).b;
+
+    finalColor = vec4(red, green, blue, 1.0);
+}
@@ -0,0 +1,41 @@
+#version 330
+
+// Input vertex attributes (from vertex shader)
+in vec2 fragTexCoord;
+in vec4 fragColor;
+
+// Input uniform values
+uniform sampler2D texture0;
+uniform vec4 colDiffuse;
+
+// Set from postfx::Chain
+uniform vec2 resolution;
+uniform float intensity;
+
+// Output fragment color
+out vec4 finalColor;
+
+void main()
+{
+    // barrel distortion around the center of the screen
+    vec2 uv = fragTexCoord*2.0 - 1.0;
+    uv *= 1.0 + 0.12*intensity*dot(uv, uv);
+    uv = uv*0.5 + 0.5;
+
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0)
+    {
+        finalColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec3 texelColor = texture(texture0, uv).rgb;
+
+    // one dark line every other pixel row
+    float scanline = 0.5 + 0.5*sin(uv.y*resolution.y*3.14159);
+    texelColor *= mix(1.0, 0.65 + 0.35*scanline, intensity);
+
+    float vignette = 16.0*uv.x*uv.y*(1.0 - uv.x)*(1.0 - uv.y);
+    texelColor *= mix(1.0, pow(vignette, 0.3), intensity);
+
+    finalColor = vec4(texelColor, 1.0);
+}
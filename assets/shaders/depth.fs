@@ -0,0 +1,15 @@
+#version 330
+
+// Output fragment color
+out vec4 finalColor;
+
+// The shadow map is a plain color texture, so the depth is spread over the rgb channels,
+// alpha stays opaque so blending can't mix it with the clear color
+void main()
+{
+    vec3 packed = fract(vec3(1.0, 255.0, 65025.0)*gl_FragCoord.z);
+
+    packed -= packed.yzz*vec3(1.0/255.0, 1.0/255.0, 0.0);
+
+    finalColor = vec4(packed, 1.0);
+}
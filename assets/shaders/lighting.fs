@@ -0,0 +1,143 @@
+#version 330
+
+#define MAX_LIGHTS 4
+
+// map bits, set for the maps the model actually has
+#define MAP_METALNESS 1
+#define MAP_NORMAL 2
+#define MAP_ROUGHNESS 4
+#define MAP_OCCLUSION 8
+
+// Input vertex attributes (from vertex shader)
+in vec3 fragPosition;
+in vec2 fragTexCoord;
+in vec3 fragNormal;
+in vec4 fragLightPosition;
+
+// Input uniform values
+uniform sampler2D albedoMap;
+uniform sampler2D metalnessMap;
+uniform sampler2D normalMap;
+uniform sampler2D roughnessMap;
+uniform sampler2D occlusionMap;
+uniform sampler2D shadowMap;
+uniform vec4 colDiffuse;
+
+// Set from lighting::Lighting
+uniform vec3 viewPos;
+uniform vec3 ambient;
+uniform int quality;
+uniform int maps;
+uniform int lightCount;
+uniform vec4 lightPositions[MAX_LIGHTS];
+uniform vec3 lightColors[MAX_LIGHTS];
+uniform int shadowLight;
+uniform int shadowSize;
+
+// Output fragment color
+out vec4 finalColor;
+
+// The meshes come without tangents, so the frame is rebuilt from screen space derivatives
+vec3 perturbNormal(vec3 normal)
+{
+    vec3 dp1 = dFdx(fragPosition);
+    vec3 dp2 = dFdy(fragPosition);
+    vec2 duv1 = dFdx(fragTexCoord);
+    vec2 duv2 = dFdy(fragTexCoord);
+
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 tangent = dp2perp*duv1.x + dp1perp*duv2.x;
+    vec3 bitangent = dp2perp*duv1.y + dp1perp*duv2.y;
+
+    float scale = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+    mat3 tbn = mat3(tangent*scale, bitangent*scale, normal);
+
+    vec3 mapped = texture(normalMap, fragTexCoord).xyz*2.0 - 1.0;
+
+    return normalize(tbn*mapped);
+}
+
+// Depth is packed into the rgb channels by depth.fs
+float unpackDepth(vec4 color)
+{
+    return dot(color.rgb, vec3(1.0, 1.0/255.0, 1.0/65025.0));
+}
+
+float shadow(vec3 normal, vec3 toLight)
+{
+    vec3 coords = (fragLightPosition.xyz/fragLightPosition.w)*0.5 + 0.5;
+
+    if (coords.z > 1.0) return 1.0;
+
+    float bias = max(0.002*(1.0 - dot(normal, toLight)), 0.0005);
+    float texel = 1.0/float(shadowSize);
+
+    // high quality softens the edges with a 3x3 filter
+    int radius = (quality >= 3) ? 1 : 0;
+
+    float lit = 0.0;
+    float samples = 0.0;
+
+    for (int x = -radius; x <= radius; x++)
+    {
+        for (int y = -radius; y <= radius; y++)
+        {
+            float depth = unpackDepth(texture(shadowMap, coords.xy + vec2(x, y)*texel));
+
+            lit += (coords.z - bias > depth) ? 0.0 : 1.0;
+            samples += 1.0;
+        }
+    }
+
+    return lit/samples;
+}
+
+void main()
+{
+    vec4 albedo = texture(albedoMap, fragTexCoord)*colDiffuse;
+
+    float metalness = ((maps & MAP_METALNESS) != 0) ? texture(metalnessMap, fragTexCoord).r : 0.0;
+    float roughness = ((maps & MAP_ROUGHNESS) != 0) ? texture(roughnessMap, fragTexCoord).r : 0.8;
+    float occlusion = ((maps & MAP_OCCLUSION) != 0) ? texture(occlusionMap, fragTexCoord).r : 1.0;
+
+    vec3 normal = normalize(fragNormal);
+
+    if (quality >= 2 && (maps & MAP_NORMAL) != 0) normal = perturbNormal(normal);
+
+    vec3 view = normalize(viewPos - fragPosition);
+    vec3 light = vec3(0.0);
+
+    for (int i = 0; i < lightCount; i++)
+    {
+        vec3 toLight;
+        float attenuation = 1.0;
+
+        // w is 0 for directional lights, their xyz points towards the light
+        if (lightPositions[i].w == 0.0)
+        {
+            toLight = normalize(lightPositions[i].xyz);
+        }
+        else
+        {
+            vec3 offset = lightPositions[i].xyz - fragPosition;
+            float distance = length(offset);
+
+            toLight = offset/distance;
+            attenuation = 1.0/(1.0 + 0.0005*distance*distance);
+        }
+
+        float diffuse = max(dot(normal, toLight), 0.0);
+
+        vec3 halfway = normalize(toLight + view);
+        float shininess = mix(96.0, 4.0, roughness);
+        float specular = pow(max(dot(normal, halfway), 0.0), shininess)*(1.0 - roughness);
+        vec3 reflection = mix(vec3(0.04), albedo.rgb, metalness);
+
+        float visible = (quality >= 2 && i == shadowLight) ? shadow(normal, toLight) : 1.0;
+
+        light += lightColors[i]*attenuation*visible*(diffuse*albedo.rgb*(1.0 - metalness) + specular*reflection);
+    }
+
+    finalColor = vec4(ambient*albedo.rgb*occlusion + light*mix(1.0, occlusion, 0.5), albedo.a);
+}
@@ -0,0 +1,31 @@
+#version 330
+
+// Input vertex attributes
+in vec3 vertexPosition;
+in vec2 vertexTexCoord;
+in vec3 vertexNormal;
+
+// Input uniform values
+uniform mat4 mvp;
+uniform mat4 matModel;
+
+// Set from lighting::Lighting
+uniform mat4 lightVP;
+
+// Output vertex attributes (to fragment shader)
+out vec3 fragPosition;
+out vec2 fragTexCoord;
+out vec3 fragNormal;
+out vec4 fragLightPosition;
+
+void main()
+{
+    vec4 world = matModel*vec4(vertexPosition, 1.0);
+
+    fragPosition = world.xyz;
+    fragTexCoord = vertexTexCoord;
+    fragNormal = normalize(mat3(transpose(inverse(matModel)))*vertexNormal);
+    fragLightPosition = lightVP*world;
+
+    gl_Position = mvp*vec4(vertexPosition, 1.0);
+}
@@ -8,16 +8,19 @@ in vec4 fragColor;
 uniform sampler2D texture0;
 uniform vec4 colDiffuse;
 
+// Set from postfx::Chain
+uniform float intensity;
+
 // Output fragment color
 out vec4 finalColor;
 
-// NOTE: Add here your custom variables
-
 float gamma = 0.6;
-float numColors = 8.0;
 
 void main()
 {
+    // 0.5 gives the original 8 colors per channel
+    float numColors = 12.0 - 8.0*intensity;
+
     // Texel color fetching from texture sampler
     vec3 texelColor = texture(texture0, fragTexCoord.xy).rgb;
 
@@ -29,4 +32,3 @@ void main()
 
     finalColor = vec4(texelColor, 1.0);
 }
-
@@ -0,0 +1,90 @@
+use super::{cache_home, APP_NAME};
+use crate::error::{Error, Result};
+
+use std::path::PathBuf;
+use std::fs;
+
+macro_rules! embed {
+    ($($name:literal),* $(,)?) => {
+        &[$(($name, include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/", $name)))),*]
+    };
+}
+
+/// Default assets compiled into the binary, the `.mtl` files have to sit next to their `.obj`.
+static ASSETS: &[(&str, &[u8])] = embed![
+    "box.obj",
+    "box.mtl",
+    "table.obj",
+    "table.mtl",
+    "shapes.tshape",
+    "lights.txt",
+    "puzzles/pack.txt",
+    "shaders/posterize.fs",
+    "shaders/crt.fs",
+    "shaders/bloom.fs",
+    "shaders/chromatic.fs",
+    "shaders/lighting.vs",
+    "shaders/lighting.fs",
+    "shaders/depth.vs",
+    "shaders/depth.fs",
+    "sounds/theme.mp3",
+    "sounds/thump.mp3",
+    "sounds/move.wav",
+    "sounds/rotate.wav",
+    "sounds/rotate_fail.wav",
+    "sounds/hold.wav",
+    "sounds/hard_drop.wav",
+    "sounds/clear1.wav",
+    "sounds/clear2.wav",
+    "sounds/clear3.wav",
+    "sounds/clear4.wav",
+    "sounds/spin.wav",
+    "sounds/combo.wav",
+    "sounds/level_up.wav",
+    "sounds/game_over.wav",
+    "textures/box_AO.png",
+    "textures/box_Metallic.png",
+    "textures/box_Roughness.png",
+    "textures/tbox.png",
+    "textures/table/Table_Base_Color.png",
+    "textures/table/Table_Metallic.png",
+    "textures/table/Table_Mixed_AO.png",
+    "textures/table/Table_Normal_OpenGL.png",
+    "textures/table/Table_Roughness.png",
+    "ui/InriaSerif-Regular.ttf",
+    "ui/bg.png",
+];
+
+/// raylib only loads models and sounds from disk, so embedded assets are
+/// written to the cache directory the first time they are requested.
+pub fn extract(name: &str) -> Result<Option<PathBuf>> {
+    if !ASSETS.iter().any(|(asset, _)| *asset == name) {
+        return Ok(None);
+    }
+
+    let root = cache_home()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_NAME)
+        .join(concat!("assets-", env!("CARGO_PKG_VERSION")));
+
+    // obj files reference their materials by relative path
+    for (asset, bytes) in ASSETS.iter().filter(|(asset, _)| *asset == name || is_companion(name, asset)) {
+        let path = root.join(asset);
+
+        if fs::metadata(&path).map(|meta| meta.len() != bytes.len() as u64).unwrap_or(true) {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+            }
+
+            fs::write(&path, bytes).map_err(|err| Error::io(&path, err))?;
+        }
+    }
+
+    Ok(Some(root.join(name)))
+}
+
+fn is_companion(name: &str, asset: &str) -> bool {
+    name.strip_suffix(".obj")
+        .map(|stem| asset.strip_suffix(".mtl") == Some(stem))
+        .unwrap_or(false)
+}
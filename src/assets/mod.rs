@@ -0,0 +1,130 @@
+#[cfg(feature = "embed-assets")]
+mod embedded;
+
+use crate::error::{self, Error, Result};
+
+use std::path::{Path, PathBuf};
+use std::env;
+use std::fs;
+
+const ASSETS_ENV: &str = "TETRIS_ASSETS";
+const APP_NAME: &str = "tetris";
+/// The one user file older versions kept in the working directory.
+const LEGACY_CONFIG: &str = "config.tr";
+
+pub struct Resolver {
+    roots: Vec<PathBuf>,
+}
+
+impl Resolver {
+    /// Builds the search path, in order of priority:
+    /// `$TETRIS_ASSETS`, `<exe dir>/assets`, `$XDG_DATA_HOME/tetris/assets`,
+    /// every `$XDG_DATA_DIRS/tetris/assets` and finally `./assets`.
+    pub fn new() -> Resolver {
+        let mut roots = Vec::new();
+
+        if let Some(dir) = env::var_os(ASSETS_ENV) {
+            roots.push(PathBuf::from(dir));
+        }
+
+        if let Some(dir) = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+            roots.push(dir.join("assets"));
+        }
+
+        if let Some(dir) = data_home() {
+            roots.push(dir.join(APP_NAME).join("assets"));
+        }
+
+        let data_dirs = env::var("XDG_DATA_DIRS")
+            .ok()
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| String::from("/usr/local/share:/usr/share"));
+
+        for dir in data_dirs.split(':').filter(|dir| !dir.is_empty()) {
+            roots.push(Path::new(dir).join(APP_NAME).join("assets"));
+        }
+
+        roots.push(PathBuf::from("assets"));
+
+        Resolver {
+            roots,
+        }
+    }
+
+    /// Searches `skins/<name>` inside every root before the root itself,
+    /// so a skin only has to ship the files it replaces.
+    pub fn with_skin(self, name: &str) -> Resolver {
+        let roots = self.roots
+            .into_iter()
+            .flat_map(|root| [root.join("skins").join(name), root])
+            .collect();
+
+        Resolver {
+            roots,
+        }
+    }
+
+    /// Resolves an asset such as `textures/tbox.png` to a path that exists on disk.
+    pub fn resolve(&self, name: &str) -> Result<String> {
+        for root in &self.roots {
+            let path = root.join(name);
+
+            if path.is_file() {
+                return Ok(path.to_string_lossy().into_owned());
+            }
+        }
+
+        #[cfg(feature = "embed-assets")]
+        if let Some(path) = embedded::extract(name)? {
+            return Ok(path.to_string_lossy().into_owned());
+        }
+
+        Err(Error::AssetNotFound {
+            name: name.to_string(),
+            searched: self.roots.clone(),
+        })
+    }
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME").filter(|home| !home.is_empty()).map(PathBuf::from)
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home().map(|home| home.join(fallback)))
+}
+
+fn data_home() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+#[cfg_attr(not(feature = "embed-assets"), allow(dead_code))]
+fn cache_home() -> Option<PathBuf> {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// Path of a user writable file such as `config.tr`, the parent directory is created if needed.
+/// The `config.tr` older versions left in the working directory is copied over the first time,
+/// no other file was ever kept there.
+pub fn config_path(name: &str) -> Result<PathBuf> {
+    let dir = xdg_dir("XDG_CONFIG_HOME", ".config")
+        .map(|dir| dir.join(APP_NAME))
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    fs::create_dir_all(&dir).map_err(|err| Error::io(&dir, err))?;
+
+    let path = dir.join(name);
+    let legacy = Path::new(name);
+
+    if name == LEGACY_CONFIG && dir != Path::new(".") && !path.exists() && legacy.is_file() {
+        match fs::copy(legacy, &path) {
+            Ok(_) => eprintln!("[INFO] copied {} to {}", legacy.display(), path.display()),
+            Err(err) => error::warn(&Error::io(legacy, err)),
+        }
+    }
+
+    Ok(path)
+}
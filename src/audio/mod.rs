@@ -0,0 +1,422 @@
+use crate::assets::Resolver;
+use crate::game::Event;
+use crate::error::{self, Error, Result};
+
+use raylib::prelude::*;
+
+/// How far the music drops while the game is paused.
+const DUCK_VOLUME: f32 = 0.3;
+
+/// Seconds it takes to cross-fade between two stems.
+const FADE_TIME: f32 = 1.5;
+
+/// Extra playback speed in danger when there is no intense stem to fade to.
+const TEMPO_BOOST: f32 = 0.12;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SoundId(usize);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MusicId(usize);
+
+/// Everything the game needs from a sound device, so gameplay never touches raylib audio directly.
+pub trait Backend {
+    fn load_sound(&mut self, path: &str) -> Result<SoundId>;
+
+    fn play_sound(&mut self, sound: SoundId);
+
+    fn set_sound_volume(&mut self, sound: SoundId, volume: f32);
+
+    fn set_sound_pitch(&mut self, sound: SoundId, pitch: f32);
+
+    /// Music is streamed from disk and loops on its own as long as it is updated every frame.
+    fn load_music(&mut self, thread: &RaylibThread, path: &str) -> Result<MusicId>;
+
+    fn play_music(&mut self, music: MusicId);
+
+    fn stop_music(&mut self, music: MusicId);
+
+    fn update_music(&mut self, music: MusicId);
+
+    fn set_music_volume(&mut self, music: MusicId, volume: f32);
+
+    fn set_music_pitch(&mut self, music: MusicId, pitch: f32);
+
+    fn set_master_volume(&mut self, volume: f32);
+}
+
+// sounds have to be unloaded before the device is closed, keep them first
+pub struct RaylibBackend {
+    sounds: Vec<Sound>,
+    music: Vec<Music>,
+    audio: RaylibAudio,
+}
+
+impl Backend for RaylibBackend {
+    fn load_sound(&mut self, path: &str) -> Result<SoundId> {
+        let sound = Sound::load_sound(path).map_err(|err| Error::Audio(format!("failed to load {}: {}", path, err)))?;
+
+        self.sounds.push(sound);
+
+        Ok(SoundId(self.sounds.len() - 1))
+    }
+
+    fn play_sound(&mut self, sound: SoundId) {
+        self.audio.play_sound(&self.sounds[sound.0]);
+    }
+
+    fn set_sound_volume(&mut self, sound: SoundId, volume: f32) {
+        self.audio.set_sound_volume(&self.sounds[sound.0], volume);
+    }
+
+    fn set_sound_pitch(&mut self, sound: SoundId, pitch: f32) {
+        self.audio.set_sound_pitch(&self.sounds[sound.0], pitch);
+    }
+
+    fn load_music(&mut self, thread: &RaylibThread, path: &str) -> Result<MusicId> {
+        let mut music = Music::load_music_stream(thread, path).map_err(|err| Error::Audio(format!("failed to load {}: {}", path, err)))?;
+        music.looping = true;
+
+        self.music.push(music);
+
+        Ok(MusicId(self.music.len() - 1))
+    }
+
+    fn play_music(&mut self, music: MusicId) {
+        self.audio.play_music_stream(&mut self.music[music.0]);
+    }
+
+    fn stop_music(&mut self, music: MusicId) {
+        self.audio.stop_music_stream(&mut self.music[music.0]);
+    }
+
+    fn update_music(&mut self, music: MusicId) {
+        self.audio.update_music_stream(&mut self.music[music.0]);
+    }
+
+    fn set_music_volume(&mut self, music: MusicId, volume: f32) {
+        self.audio.set_music_volume(&mut self.music[music.0], volume);
+    }
+
+    fn set_music_pitch(&mut self, music: MusicId, pitch: f32) {
+        self.audio.set_music_pitch(&mut self.music[music.0], pitch);
+    }
+
+    fn set_master_volume(&mut self, volume: f32) {
+        self.audio.set_master_volume(volume);
+    }
+}
+
+/// Used when there is no sound hardware, everything loads and nothing ever plays.
+pub struct NullBackend {
+    sounds: usize,
+    music: usize,
+}
+
+impl Backend for NullBackend {
+    fn load_sound(&mut self, _path: &str) -> Result<SoundId> {
+        self.sounds += 1;
+
+        Ok(SoundId(self.sounds - 1))
+    }
+
+    fn play_sound(&mut self, _sound: SoundId) {}
+
+    fn set_sound_volume(&mut self, _sound: SoundId, _volume: f32) {}
+
+    fn set_sound_pitch(&mut self, _sound: SoundId, _pitch: f32) {}
+
+    fn load_music(&mut self, _thread: &RaylibThread, _path: &str) -> Result<MusicId> {
+        self.music += 1;
+
+        Ok(MusicId(self.music - 1))
+    }
+
+    fn play_music(&mut self, _music: MusicId) {}
+
+    fn stop_music(&mut self, _music: MusicId) {}
+
+    fn update_music(&mut self, _music: MusicId) {}
+
+    fn set_music_volume(&mut self, _music: MusicId, _volume: f32) {}
+
+    fn set_music_pitch(&mut self, _music: MusicId, _pitch: f32) {}
+
+    fn set_master_volume(&mut self, _volume: f32) {}
+}
+
+/// Volumes are in the 0.0..=1.0 range and persisted in the config.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Mixer {
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+    pub muted: bool,
+}
+
+impl Default for Mixer {
+    fn default() -> Mixer {
+        Mixer {
+            master: 1.0,
+            music: 0.7,
+            sfx: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl Mixer {
+    pub fn master_volume(&self) -> f32 {
+        if self.muted { 0.0 } else { self.master }
+    }
+
+    pub fn music_volume(&self, ducked: bool) -> f32 {
+        if ducked { self.music * DUCK_VOLUME } else { self.music }
+    }
+}
+
+/// Every sound effect and the file it is loaded from, skins replace them by shipping the same path.
+const EFFECTS: &[(&str, &str)] = &[
+    ("move", "sounds/move.wav"),
+    ("rotate", "sounds/rotate.wav"),
+    ("rotate_fail", "sounds/rotate_fail.wav"),
+    ("hold", "sounds/hold.wav"),
+    ("hard_drop", "sounds/hard_drop.wav"),
+    ("lock", "sounds/thump.mp3"),
+    ("clear1", "sounds/clear1.wav"),
+    ("clear2", "sounds/clear2.wav"),
+    ("clear3", "sounds/clear3.wav"),
+    ("clear4", "sounds/clear4.wav"),
+    ("spin", "sounds/spin.wav"),
+    ("combo", "sounds/combo.wav"),
+    ("level_up", "sounds/level_up.wav"),
+    ("game_over", "sounds/game_over.wav"),
+];
+
+pub struct Sfx {
+    sounds: Vec<(&'static str, SoundId)>,
+}
+
+impl Sfx {
+    /// Effects that fail to load are skipped with a warning.
+    pub fn load(audio: &mut dyn Backend, resolver: &Resolver) -> Sfx {
+        let sounds = EFFECTS
+            .iter()
+            .filter_map(|(name, path)| {
+                error::recover(resolver.resolve(path).and_then(|path| audio.load_sound(&path)))
+                    .map(|sound| (*name, sound))
+            })
+            .collect();
+
+        Sfx {
+            sounds,
+        }
+    }
+
+    /// None for events that make no sound of their own.
+    fn effect(event: Event) -> Option<(&'static str, f32)> {
+        let effect = match event {
+            Event::Move => ("move", 1.0),
+            Event::Rotate => ("rotate", 1.0),
+            Event::RotateFail => ("rotate_fail", 1.0),
+            Event::Hold => ("hold", 1.0),
+            Event::HardDrop => ("hard_drop", 1.0),
+            Event::Lock => ("lock", 1.0),
+            Event::Clear(1) => ("clear1", 1.0),
+            Event::Clear(2) => ("clear2", 1.0),
+            Event::Clear(3) => ("clear3", 1.0),
+            Event::Clear(_) => ("clear4", 1.0),
+            Event::Spin(_) => ("spin", 1.0),
+            Event::Combo(combo) => ("combo", (1.0 + (combo - 1) as f32 * 0.1).min(2.0)),
+            Event::LevelUp(_) => ("level_up", 1.0),
+            Event::Fault(_) => ("rotate_fail", 0.7),
+            Event::Garbage(_) => ("lock", 0.6),
+            Event::GameOver => ("game_over", 1.0),
+            Event::ClearedRows(_) => return None,
+        };
+
+        Some(effect)
+    }
+
+    pub fn play(&self, audio: &mut dyn Backend, mixer: &Mixer, event: Event) {
+        let Some((name, pitch)) = Self::effect(event) else {
+            return;
+        };
+
+        if let Some((_, sound)) = self.sounds.iter().find(|(effect, _)| *effect == name) {
+            audio.set_sound_volume(*sound, mixer.sfx);
+            audio.set_sound_pitch(*sound, pitch);
+            audio.play_sound(*sound);
+        }
+    }
+}
+
+struct Stem {
+    music: MusicId,
+    volume: f32,
+    playing: bool,
+}
+
+impl Stem {
+    fn new(music: MusicId) -> Stem {
+        Stem {
+            music,
+            volume: 0.0,
+            playing: false,
+        }
+    }
+
+    fn fade(&mut self, audio: &mut dyn Backend, target: f32, step: f32, gain: f32, pitch: f32) {
+        self.volume += (target - self.volume).clamp(-step, step);
+
+        if !self.playing && self.volume > 0.0 {
+            audio.play_music(self.music);
+            self.playing = true;
+        }
+
+        if self.playing {
+            audio.set_music_volume(self.music, self.volume * gain);
+            audio.set_music_pitch(self.music, pitch);
+            audio.update_music(self.music);
+
+            // restart from the top the next time this stem fades in
+            if self.volume <= 0.0 {
+                audio.stop_music(self.music);
+                self.playing = false;
+            }
+        }
+    }
+}
+
+/// Layers the theme with optional stems from the skin: `theme_intense.mp3` is
+/// faded in while the stack is in danger and `theme_level<n>.mp3` replaces the
+/// theme from level `n` on. Without an intense stem the theme speeds up instead.
+pub struct MusicController {
+    tracks: Vec<(u32, Stem)>,
+    intense: Option<Stem>,
+    current: usize,
+    intensity: f32,
+    gain: f32,
+}
+
+impl MusicController {
+    pub fn load(audio: &mut dyn Backend, thread: &RaylibThread, resolver: &Resolver) -> MusicController {
+        let mut load = |name: &str| resolver.resolve(name).and_then(|path| audio.load_music(thread, &path));
+
+        let mut tracks = Vec::new();
+
+        if let Some(theme) = error::recover(load("sounds/theme.mp3")) {
+            tracks.push((1, Stem::new(theme)));
+        }
+
+        // level tracks are optional, the first gap ends the list
+        for level in 2.. {
+            match load(&format!("sounds/theme_level{}.mp3", level)) {
+                Ok(music) => tracks.push((level, Stem::new(music))),
+                Err(_) => break,
+            }
+        }
+
+        let intense = load("sounds/theme_intense.mp3").ok().map(Stem::new);
+
+        MusicController {
+            tracks,
+            intense,
+            current: 0,
+            intensity: 0.0,
+            gain: 0.0,
+        }
+    }
+
+    /// Stops every stem and starts over from the theme, streams left playing without updates stutter.
+    pub fn stop(&mut self, audio: &mut dyn Backend) {
+        let stems = self.tracks.iter_mut().map(|(_, stem)| stem).chain(self.intense.as_mut());
+
+        for stem in stems.filter(|stem| stem.playing) {
+            audio.stop_music(stem.music);
+
+            stem.volume = 0.0;
+            stem.playing = false;
+        }
+
+        self.current = 0;
+        self.intensity = 0.0;
+        self.gain = 0.0;
+    }
+
+    pub fn handle(&mut self, event: Event) {
+        if let Event::LevelUp(level) = event {
+            if let Some(index) = self.tracks.iter().rposition(|(from, _)| *from <= level) {
+                self.current = index;
+            }
+        }
+    }
+
+    pub fn update(&mut self, audio: &mut dyn Backend, mixer: &Mixer, paused: bool, danger: bool, delta: f32) {
+        let step = delta / FADE_TIME;
+
+        self.intensity = (self.intensity + if danger { step } else { -step }).clamp(0.0, 1.0);
+
+        // ease towards the ducked volume instead of cutting the music abruptly
+        let target = mixer.music_volume(paused);
+        self.gain += (target - self.gain) * (delta * 8.0).min(1.0);
+
+        let (base, pitch) = match self.intense {
+            Some(_) => (1.0 - self.intensity, 1.0),
+            None => (1.0, 1.0 + TEMPO_BOOST * self.intensity),
+        };
+
+        for (index, (_, stem)) in self.tracks.iter_mut().enumerate() {
+            let target = if index == self.current { base } else { 0.0 };
+
+            stem.fade(audio, target, step, self.gain, pitch);
+        }
+
+        if let Some(stem) = &mut self.intense {
+            stem.fade(audio, self.intensity, step, self.gain, 1.0);
+        }
+    }
+}
+
+/// The device with the effects and music loaded on it once, every game borrows it in turn.
+pub struct Audio {
+    pub backend: Box<dyn Backend>,
+    pub sfx: Sfx,
+    pub music: MusicController,
+}
+
+impl Audio {
+    pub fn load(mut backend: Box<dyn Backend>, thread: &RaylibThread, resolver: &Resolver) -> Audio {
+        Audio {
+            sfx: Sfx::load(backend.as_mut(), resolver),
+            music: MusicController::load(backend.as_mut(), thread, resolver),
+            backend,
+        }
+    }
+}
+
+/// Opens the default audio device, falling back to the null backend if it is disabled or unavailable.
+pub fn init(enabled: bool) -> Box<dyn Backend> {
+    let null = NullBackend {
+        sounds: 0,
+        music: 0,
+    };
+
+    if !enabled {
+        return Box::new(null);
+    }
+
+    let audio = RaylibAudio::init_audio_device();
+
+    if !audio.is_audio_device_ready() {
+        error::warn(&Error::Audio(String::from("no audio device available, running without sound")));
+
+        return Box::new(null);
+    }
+
+    Box::new(RaylibBackend {
+        sounds: Vec::new(),
+        music: Vec::new(),
+        audio,
+    })
+}
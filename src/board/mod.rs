@@ -0,0 +1,308 @@
+use crate::game::{COLUMNS, ROWS};
+use crate::error::{Error, Result};
+use crate::TShape;
+
+use std::fs;
+
+/// Fumen's base64 alphabet, values are written least significant digit first.
+const FUMEN_DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A fumen field is 10 wide with 23 rows above the floor and one garbage row below it.
+const FUMEN_WIDTH: usize = 10;
+const FUMEN_TOP: usize = 23;
+const FUMEN_CELLS: usize = FUMEN_WIDTH * (FUMEN_TOP + 1);
+
+/// Gray garbage, what our blocks are exported as since the shapes aren't tetrominoes.
+const FUMEN_GRAY: usize = 8;
+
+/// The page that follows the field, no piece, locked and guideline colors like fumen's editor writes it.
+const FUMEN_PAGE: &str = "AgH";
+
+/// A starting position, the locked blocks and the pieces to deal before the random ones.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Board {
+    pub lines: Vec<Vec<bool>>,
+    pub queue: Vec<usize>,
+}
+
+impl Board {
+    pub fn empty() -> Board {
+        Board {
+            lines: vec![vec![false; COLUMNS]; ROWS],
+            queue: Vec::new(),
+        }
+    }
+
+    /// Reads either format, fumen data is recognised by its version prefix.
+    pub fn load(file: &str, shapes: &TShape) -> Result<Board> {
+        let source = fs::read_to_string(file).map_err(|err| Error::io(file, err))?;
+
+        Self::decode(file, &source, shapes)
+    }
+
+    pub fn decode(file: &str, source: &str, shapes: &TShape) -> Result<Board> {
+        if Self::fumen_data(source).is_some() {
+            Self::from_fumen(source)
+        } else {
+            Self::parse(file, source, shapes)
+        }
+    }
+
+    pub fn save(&self, file: &str, shapes: &TShape) -> Result<()> {
+        fs::write(file, self.to_text(shapes)).map_err(|err| Error::io(file, err))
+    }
+
+    /// The board drawn as it shows on screen, top row first, `#` for blocks and `.` for empty cells.
+    /// An optional `next:` line lists the queue by shape name, `;` starts a comment and rows
+    /// missing at the top are empty.
+    pub fn parse(file: &str, source: &str, shapes: &TShape) -> Result<Board> {
+        Self::parse_lines(file, source.lines().enumerate(), shapes)
+    }
+
+    /// Same as `parse` for lines picked out of a bigger file, numbered from 0 for errors.
+    pub fn parse_lines<'a>(file: &str, lines: impl Iterator<Item = (usize, &'a str)>, shapes: &TShape) -> Result<Board> {
+        let mut rows = Vec::new();
+        let mut queue = Vec::new();
+
+        for (index, line) in lines {
+            let error = |reason: String| Error::BoardParse {
+                path: file.to_string(),
+                line: index + 1,
+                reason,
+            };
+
+            let line = line.split(';').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(names) = line.strip_prefix("next:") {
+                for name in names.split_whitespace() {
+                    queue.push(shapes.find(name).ok_or_else(|| error(format!("unknown shape `{}`", name)))?);
+                }
+
+                continue;
+            }
+
+            let cells = line
+                .chars()
+                .map(|cell| match cell {
+                    '#' | 'X' | 'x' => Ok(true),
+                    '.' | '_' => Ok(false),
+                    other => Err(error(format!("unexpected `{}`", other))),
+                })
+                .collect::<Result<Vec<bool>>>()?;
+
+            if cells.len() != COLUMNS {
+                return Err(error(format!("row should be {} cells wide, found {}", COLUMNS, cells.len())));
+            }
+
+            if rows.len() == ROWS {
+                return Err(error(format!("more than {} rows", ROWS)));
+            }
+
+            rows.push(cells);
+        }
+
+        let mut board = Board::empty();
+
+        // x runs right to left on screen
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (column, cell) in row.iter().enumerate() {
+                board.lines[y][COLUMNS - 1 - column] = *cell;
+            }
+        }
+
+        board.queue = queue;
+
+        Ok(board)
+    }
+
+    pub fn to_text(&self, shapes: &TShape) -> String {
+        let mut text = String::new();
+
+        if !self.queue.is_empty() {
+            let names: Vec<&str> = self.queue.iter().map(|piece| shapes.name(*piece)).collect();
+
+            text += &format!("next: {}\n", names.join(" "));
+        }
+
+        for line in self.lines.iter().rev() {
+            text.extend(line.iter().rev().map(|block| if *block { '#' } else { '.' }));
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /// Where a cell of our board sits in a fumen field, bottom left aligned.
+    fn fumen_index(x: usize, y: usize) -> usize {
+        (FUMEN_TOP - 1 - y) * FUMEN_WIDTH + (COLUMNS - 1 - x)
+    }
+
+    /// What follows the `v115@` prefix, at the start of the text or after the `?` of a URL.
+    fn fumen_data(source: &str) -> Option<&str> {
+        let source = source.trim();
+
+        source.strip_prefix("v115@").or_else(|| source.split_once("?v115@").map(|(_, data)| data))
+    }
+
+    /// Reads the field of the first page of v115 fumen data, a full URL works too.
+    /// Fumen only knows tetrominoes, so pieces and the queue aren't imported.
+    pub fn from_fumen(source: &str) -> Result<Board> {
+        let error = |reason: &str| Error::decode("fumen", reason);
+
+        let data = Self::fumen_data(source).ok_or_else(|| error("only v115 data is supported"))?;
+
+        let mut values = data
+            .bytes()
+            .filter(|byte| *byte != b'?')
+            .map(|byte| FUMEN_DIGITS.iter().position(|digit| *digit == byte).ok_or_else(|| error("invalid character")));
+
+        let mut poke = |digits: usize| -> Result<usize> {
+            let mut value = 0;
+
+            for digit in 0..digits {
+                value += values.next().ok_or_else(|| error("data ends early"))?? * 64usize.pow(digit as u32);
+            }
+
+            Ok(value)
+        };
+
+        let mut board = Board::empty();
+        let mut cell = 0;
+
+        while cell < FUMEN_CELLS {
+            let value = poke(2)?;
+
+            // blocks are stored as a difference to the previous page, which for the first one is empty
+            let block = (value / FUMEN_CELLS).checked_sub(8).filter(|block| *block <= FUMEN_GRAY).ok_or_else(|| error("invalid block"))?;
+            let count = value % FUMEN_CELLS + 1;
+
+            if cell + count > FUMEN_CELLS {
+                return Err(error("field overflows"));
+            }
+
+            if block != 0 {
+                for index in cell..cell + count {
+                    let (row, column) = (index / FUMEN_WIDTH, index % FUMEN_WIDTH);
+
+                    if column >= COLUMNS || row >= FUMEN_TOP || FUMEN_TOP - 1 - row >= ROWS {
+                        return Err(error(&format!("blocks outside the {}x{} board", COLUMNS, ROWS)));
+                    }
+
+                    board.lines[FUMEN_TOP - 1 - row][COLUMNS - 1 - column] = true;
+                }
+            }
+
+            cell += count;
+        }
+
+        Ok(board)
+    }
+
+    pub fn to_fumen(&self) -> String {
+        let mut field = [0; FUMEN_CELLS];
+
+        for (y, line) in self.lines.iter().enumerate() {
+            for (x, block) in line.iter().enumerate() {
+                if *block {
+                    field[Self::fumen_index(x, y)] = FUMEN_GRAY;
+                }
+            }
+        }
+
+        let mut data = String::from("v115@");
+        let mut poke = |mut value: usize, digits: usize| {
+            for _ in 0..digits {
+                data.push(FUMEN_DIGITS[value % 64] as char);
+                value /= 64;
+            }
+        };
+
+        let mut runs = Vec::new();
+
+        for block in field {
+            match runs.last_mut() {
+                Some((last, count)) if *last == block => *count += 1,
+                _ => runs.push((block, 1)),
+            }
+        }
+
+        for (block, count) in &runs {
+            poke((block + 8) * FUMEN_CELLS + count - 1, 2);
+        }
+
+        // an empty field is followed by the number of pages repeating it
+        if runs.len() == 1 && runs[0].0 == 0 {
+            poke(0, 1);
+        }
+
+        data + FUMEN_PAGE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Board {
+        let mut board = Board::empty();
+
+        board.lines[0] = vec![true, true, false, true, true];
+        board.lines[1] = vec![false, true, false, true, false];
+        board.lines[4][4] = true;
+        board.queue = vec![2, 0, 1];
+
+        board
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let shapes = TShape::fixture();
+        let board = sample();
+
+        assert_eq!(Board::parse("board", &board.to_text(&shapes), &shapes).unwrap(), board);
+    }
+
+    #[test]
+    fn text_rejects_wrong_width() {
+        let shapes = TShape::fixture();
+
+        assert!(Board::parse("board", "#.#\n", &shapes).is_err());
+    }
+
+    #[test]
+    fn empty_fumen() {
+        assert_eq!(Board::empty().to_fumen(), "v115@vhAAgH");
+        assert_eq!(Board::from_fumen("v115@vhAAgH").unwrap(), Board::empty());
+    }
+
+    #[test]
+    fn fumen_round_trip() {
+        // fumen has no queue
+        let board = Board {
+            queue: Vec::new(),
+            ..sample()
+        };
+
+        assert_eq!(Board::from_fumen(&board.to_fumen()).unwrap(), board);
+    }
+
+    #[test]
+    fn fumen_detection() {
+        let shapes = TShape::fixture();
+        let board = sample();
+        let fumen = board.to_fumen();
+
+        for source in [fumen.clone(), format!("  {}\n", fumen), format!("https://fumen.zui.jp/?{}", fumen)] {
+            assert_eq!(Board::decode("board", &source, &shapes).unwrap().lines, board.lines);
+        }
+
+        // a comment mentioning fumen is still a text board
+        let text = format!("; from {}\n{}", fumen, board.to_text(&shapes));
+
+        assert_eq!(Board::decode("board", &text, &shapes).unwrap(), board);
+    }
+}
@@ -0,0 +1,349 @@
+use crate::game::{Action, Game, Position, COLUMNS};
+use crate::search;
+use crate::tbp::Client;
+
+/// Weights of the board features, tuned for the classic 10 wide game and good enough on ours.
+const AGGREGATE_HEIGHT: f32 = -0.51;
+const LINES: f32 = 0.76;
+const HOLES: f32 = -0.36;
+const BUMPINESS: f32 = -0.18;
+
+/// How many actions the instant bot may take in one frame, always enough for a whole piece.
+const INSTANT_ACTIONS: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Speed {
+    Slow,
+    #[default]
+    Normal,
+    Fast,
+    /// A whole piece every frame.
+    Instant,
+}
+
+impl Speed {
+    pub const ALL: [Speed; 4] = [Speed::Slow, Speed::Normal, Speed::Fast, Speed::Instant];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Speed::Slow => "Slow",
+            Speed::Normal => "Normal",
+            Speed::Fast => "Fast",
+            Speed::Instant => "Instant",
+        }
+    }
+
+    pub fn next(&self) -> Speed {
+        let index = Speed::ALL.iter().position(|speed| speed == self).unwrap_or(0);
+
+        Speed::ALL[(index + 1) % Speed::ALL.len()]
+    }
+
+    pub fn from_index(index: u8) -> Option<Speed> {
+        Speed::ALL.get(index as usize).copied()
+    }
+
+    pub fn index(&self) -> u8 {
+        Speed::ALL.iter().position(|speed| speed == self).unwrap_or(0) as u8
+    }
+
+    /// Seconds between two actions.
+    fn interval(&self) -> Option<f32> {
+        match self {
+            Speed::Slow => Some(0.4),
+            Speed::Normal => Some(0.15),
+            Speed::Fast => Some(0.04),
+            Speed::Instant => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BotSettings {
+    pub speed: Speed,
+    /// Shows where the bot would put the piece while a human plays.
+    pub hints: bool,
+    /// The bot plays a demo when the menu sits idle.
+    pub demo: bool,
+}
+
+impl Default for BotSettings {
+    fn default() -> BotSettings {
+        BotSettings {
+            speed: Speed::default(),
+            hints: false,
+            demo: true,
+        }
+    }
+}
+
+/// The stack after locking `cells`, and how many lines that cleared.
+pub fn simulate(lines: &[Vec<bool>], cells: &[Position]) -> (Vec<Vec<bool>>, u32) {
+    let mut lines = lines.to_vec();
+
+    for position in cells {
+        lines[position.y][position.x] = true;
+    }
+
+    let rows = lines.len();
+
+    lines.retain(|line| !line.iter().all(|block| *block));
+
+    let cleared = rows - lines.len();
+
+    lines.resize(rows, vec![false; COLUMNS]);
+
+    (lines, cleared as u32)
+}
+
+fn evaluate(lines: &[Vec<bool>], cleared: u32) -> f32 {
+    let heights: Vec<usize> = (0..COLUMNS)
+        .map(|x| lines.iter().rposition(|line| line[x]).map_or(0, |y| y + 1))
+        .collect();
+
+    let holes: usize = (0..COLUMNS)
+        .map(|x| (0..heights[x]).filter(|y| !lines[*y][x]).count())
+        .sum();
+
+    let bumpiness: usize = heights.windows(2).map(|pair| pair[0].abs_diff(pair[1])).sum();
+
+    AGGREGATE_HEIGHT * heights.iter().sum::<usize>() as f32
+        + LINES * cleared as f32
+        + HOLES * holes as f32
+        + BUMPINESS * bumpiness as f32
+}
+
+/// Where the bot would lock the falling piece, None if it can't move at all.
+pub fn suggest(game: &Game) -> Option<search::Placement> {
+    search::placements(game, game.shape())
+        .into_iter()
+        .map(|placement| {
+            let (lines, cleared) = simulate(game.lines(), &placement.cells);
+
+            (evaluate(&lines, cleared), placement)
+        })
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, placement)| placement)
+}
+
+/// Who picks the spots.
+enum Brain {
+    Builtin,
+    /// An external bot speaking the bot protocol.
+    External(Client),
+}
+
+/// A spot picked for a piece.
+struct Target {
+    cells: Vec<Position>,
+    /// The piece to put there, an external bot may want the held one.
+    piece: usize,
+    /// The piece count it was picked at.
+    pieces: u32,
+}
+
+/// Plays through `Game::apply` like a human, one action at a time.
+pub struct Bot {
+    pub speed: Speed,
+    brain: Brain,
+    target: Option<Target>,
+    timer: f32,
+}
+
+impl Bot {
+    pub fn new(speed: Speed) -> Bot {
+        Bot {
+            speed,
+            brain: Brain::Builtin,
+            target: None,
+            timer: 0.0,
+        }
+    }
+
+    /// Steers the pieces wherever the external bot says.
+    pub fn external(speed: Speed, client: Client) -> Bot {
+        Bot {
+            brain: Brain::External(client),
+            ..Bot::new(speed)
+        }
+    }
+
+    /// What the external bot calls itself, once it said so.
+    pub fn name(&self) -> Option<&str> {
+        match &self.brain {
+            Brain::Builtin => None,
+            Brain::External(client) => client.name.as_deref(),
+        }
+    }
+
+    /// Forgets the target, for a new game.
+    pub fn reset(&mut self) {
+        self.target = None;
+        self.timer = 0.0;
+    }
+
+    /// An external bot is starting up or working out a move, the game should wait if it can.
+    pub fn thinking(&self) -> bool {
+        match &self.brain {
+            Brain::Builtin => false,
+            Brain::External(client) => client.thinking(),
+        }
+    }
+
+    fn pick(&mut self, game: &Game) -> Option<Target> {
+        let (cells, piece) = match &mut self.brain {
+            Brain::Builtin => (suggest(game)?.cells, game.piece_index()),
+            Brain::External(client) => {
+                let mv = client.poll(game)?;
+
+                (mv.cells(game.shapes())?, mv.piece)
+            },
+        };
+
+        Some(Target {
+            cells,
+            piece,
+            pieces: game.stats.pieces,
+        })
+    }
+
+    /// Searches again from wherever the piece is now, gravity may have moved it since the last action.
+    fn next_action(&mut self, game: &Game) -> Option<Action> {
+        self.path(game).map(|path| Self::first(&path))
+    }
+
+    /// Every action for the current piece at once, for when they can't be sent one by one. A hold
+    /// comes on its own, the held piece's path depends on what comes out.
+    pub fn plan(&mut self, game: &Game) -> Option<Vec<Action>> {
+        let mut path = self.path(game)?;
+
+        if path != [Action::Hold] {
+            while path.last() == Some(&Action::SoftDrop) {
+                path.pop();
+            }
+
+            path.push(Action::HardDrop);
+        }
+
+        Some(path)
+    }
+
+    fn path(&mut self, game: &Game) -> Option<Vec<Action>> {
+        if game.over {
+            return None;
+        }
+
+        if self.target.as_ref().map(|target| target.pieces) != Some(game.stats.pieces) {
+            self.target = Some(self.pick(game)?);
+        }
+
+        let target = self.target.as_ref()?;
+
+        if target.piece != game.piece_index() && game.can_hold() {
+            return Some(vec![Action::Hold]);
+        }
+
+        let path = search::placements(game, game.shape())
+            .into_iter()
+            .find(|placement| placement.cells == target.cells)
+            .map(|placement| placement.path);
+
+        let path = match path {
+            Some(path) => path,
+            None => {
+                // gravity took the target away, settle for the best spot left
+                let placement = suggest(game)?;
+
+                self.target = Some(Target {
+                    cells: placement.cells,
+                    piece: game.piece_index(),
+                    pieces: game.stats.pieces,
+                });
+
+                placement.path
+            },
+        };
+
+        Some(path)
+    }
+
+    /// Only falling left to do is a hard drop.
+    fn first(path: &[Action]) -> Action {
+        match path.iter().find(|action| **action != Action::SoftDrop) {
+            Some(_) => path[0],
+            None => Action::HardDrop,
+        }
+    }
+
+    pub fn update(&mut self, game: &mut Game, delta: f32) {
+        let actions = match self.speed.interval() {
+            Some(interval) => {
+                // after a hitch it catches up a little, not all at once
+                self.timer = (self.timer + delta).min(interval * 4.0);
+
+                let actions = (self.timer / interval) as usize;
+                self.timer -= actions as f32 * interval;
+
+                actions
+            },
+            None => INSTANT_ACTIONS,
+        };
+
+        for _ in 0..actions {
+            let Some(action) = self.next_action(game) else {
+                break;
+            };
+
+            game.apply(action);
+
+            if action == Action::HardDrop && self.speed == Speed::Instant {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::rng::Rng;
+    use crate::TShape;
+
+    /// Two rows filled but for a gap the square fits, every other spot leaves it stacked up.
+    fn well() -> Game {
+        let mut game = Game::new(TShape::fixture(), Rng::new(1));
+        let mut board = Board::empty();
+
+        board.lines[0] = vec![true, true, true, false, false];
+        board.lines[1] = vec![true, true, true, false, false];
+        board.queue = vec![1];
+
+        game.load_board(&board);
+        game
+    }
+
+    #[test]
+    fn suggests_the_double() {
+        let placement = suggest(&well()).unwrap();
+
+        assert_eq!(placement.cells, [(3, 0), (4, 0), (3, 1), (4, 1)].map(|(x, y)| Position { x, y }));
+        assert_eq!(placement.inputs, 1);
+    }
+
+    #[test]
+    fn plan_shifts_once_and_drops() {
+        let mut game = well();
+        let plan = Bot::new(Speed::Instant).plan(&game).unwrap();
+
+        assert_eq!(plan.iter().filter(|action| **action != Action::SoftDrop).count(), 2);
+        assert_eq!(plan.last(), Some(&Action::HardDrop));
+
+        for action in plan {
+            game.apply(action);
+        }
+
+        assert_eq!(game.score.lines, 2);
+        assert_eq!(game.stack_height(), 0);
+    }
+}
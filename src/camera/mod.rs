@@ -0,0 +1,241 @@
+use crate::game::Event;
+
+use raylib::prelude::*;
+
+use std::f32::consts::PI;
+
+/// Point the camera looks at when it isn't following the piece, the middle of the board.
+const TARGET: Vector3 = Vector3::new(0.0, 30.0, 0.0);
+
+const FOV: f32 = 60.0;
+
+/// The view the game always had, from (70, 35, 0).
+const HOME: Shot = Shot { yaw: 0.0, pitch: 0.071, distance: 70.2 };
+
+/// Orbit limits, pitch in radians.
+const MIN_PITCH: f32 = -0.2;
+const MAX_PITCH: f32 = 1.2;
+const MIN_DISTANCE: f32 = 40.0;
+const MAX_DISTANCE: f32 = 150.0;
+
+/// Radians per pixel of mouse drag and per second of full stick.
+const DRAG_SPEED: f32 = 0.008;
+const STICK_SPEED: f32 = 2.0;
+const ZOOM_STEP: f32 = 5.0;
+
+/// How fast the follow camera catches up with the piece, per second.
+const FOLLOW_RATE: f32 = 3.0;
+
+/// Seconds each cinematic shot is held, and how long the blend into the next one takes.
+const SHOT_TIME: f32 = 8.0;
+const BLEND_TIME: f32 = 2.5;
+
+const INTRO_TIME: f32 = 2.0;
+const INTRO: Shot = Shot { yaw: 2.2, pitch: 0.9, distance: 190.0 };
+
+/// Trauma lost per second, the offset is proportional to its square so small hits stay subtle.
+const SHAKE_DECAY: f32 = 1.5;
+const SHAKE_AMPLITUDE: f32 = 1.2;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Mode {
+    #[default]
+    Fixed,
+    Orbit,
+    Follow,
+    Cinematic,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 4] = [Mode::Fixed, Mode::Orbit, Mode::Follow, Mode::Cinematic];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Fixed => "Fixed",
+            Mode::Orbit => "Orbit",
+            Mode::Follow => "Follow",
+            Mode::Cinematic => "Cinematic",
+        }
+    }
+
+    pub fn next(&self) -> Mode {
+        let index = Mode::ALL.iter().position(|mode| mode == self).unwrap_or(0);
+
+        Mode::ALL[(index + 1) % Mode::ALL.len()]
+    }
+
+    pub fn from_index(index: u8) -> Option<Mode> {
+        Mode::ALL.get(index as usize).copied()
+    }
+
+    pub fn index(&self) -> u8 {
+        Mode::ALL.iter().position(|mode| mode == self).unwrap_or(0) as u8
+    }
+}
+
+/// Camera position around the target, yaw 0 looks along -x.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Shot {
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+impl Shot {
+    fn lerp(self, other: Shot, t: f32) -> Shot {
+        Shot {
+            yaw: self.yaw + (other.yaw - self.yaw) * t,
+            pitch: self.pitch + (other.pitch - self.pitch) * t,
+            distance: self.distance + (other.distance - self.distance) * t,
+        }
+    }
+
+    fn offset(&self) -> Vector3 {
+        Vector3::new(
+            self.yaw.cos() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.sin() * self.pitch.cos(),
+        )
+        .scale_by(self.distance)
+    }
+}
+
+/// The shots the cinematic mode cycles through.
+const CINEMATIC: [Shot; 4] = [
+    Shot { yaw: 0.5, pitch: 0.15, distance: 75.0 },
+    Shot { yaw: -0.4, pitch: 0.7, distance: 90.0 },
+    Shot { yaw: -0.9, pitch: -0.1, distance: 60.0 },
+    Shot { yaw: 0.9, pitch: 0.35, distance: 65.0 },
+];
+
+fn ease(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+
+    1.0 - (1.0 - t).powi(3)
+}
+
+pub struct CameraController {
+    pub mode: Mode,
+    /// Scales every shot's distance, versus pulls back to fit both stacks.
+    pub zoom: f32,
+    orbit: Shot,
+    height: f32,
+    time: f32,
+    intro: f32,
+    trauma: f32,
+    mouse: Option<Vector2>,
+}
+
+impl CameraController {
+    pub fn new(mode: Mode) -> CameraController {
+        CameraController {
+            mode,
+            zoom: 1.0,
+            orbit: HOME,
+            height: TARGET.y,
+            time: 0.0,
+            intro: INTRO_TIME,
+            trauma: 0.0,
+            mouse: None,
+        }
+    }
+
+    pub fn handle(&mut self, event: Event) {
+        match event {
+            Event::HardDrop => self.shake(0.3),
+            Event::Clear(4) => self.shake(0.6),
+            Event::Garbage(rows) => self.shake(0.15 * rows as f32),
+            _ => {},
+        }
+    }
+
+    fn shake(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+
+    /// Mouse drag, wheel and the right stick only move the camera in orbit mode.
+    fn orbit_input(&mut self, rl: &RaylibHandle, delta: f32) {
+        let mouse = rl.get_mouse_position();
+
+        if rl.is_mouse_button_down(MouseButton::MOUSE_LEFT_BUTTON) {
+            if let Some(last) = self.mouse {
+                self.orbit.yaw += (mouse.x - last.x) * DRAG_SPEED;
+                self.orbit.pitch += (mouse.y - last.y) * DRAG_SPEED;
+            }
+
+            self.mouse = Some(mouse);
+        } else {
+            self.mouse = None;
+        }
+
+        if rl.is_gamepad_available(0) {
+            self.orbit.yaw += rl.get_gamepad_axis_movement(0, GamepadAxis::GAMEPAD_AXIS_RIGHT_X) * STICK_SPEED * delta;
+            self.orbit.pitch += rl.get_gamepad_axis_movement(0, GamepadAxis::GAMEPAD_AXIS_RIGHT_Y) * STICK_SPEED * delta;
+        }
+
+        self.orbit.distance -= rl.get_mouse_wheel_move() * ZOOM_STEP;
+
+        self.orbit.pitch = self.orbit.pitch.clamp(MIN_PITCH, MAX_PITCH);
+        self.orbit.distance = self.orbit.distance.clamp(MIN_DISTANCE, MAX_DISTANCE);
+        self.orbit.yaw %= 2.0 * PI;
+    }
+
+    fn cinematic(&self) -> Shot {
+        let index = (self.time / SHOT_TIME) as usize;
+        let into = self.time % SHOT_TIME;
+
+        let from = CINEMATIC[index % CINEMATIC.len()];
+        let to = CINEMATIC[(index + 1) % CINEMATIC.len()];
+
+        // hold, then blend into the next shot at the end of this one
+        let blend = ease((into - (SHOT_TIME - BLEND_TIME)) / BLEND_TIME);
+
+        // a slow drift keeps the held shots from looking frozen
+        let drift = (self.time * 0.2).sin() * 0.08;
+
+        let mut shot = from.lerp(to, blend);
+        shot.yaw += drift;
+        shot
+    }
+
+    /// `height` is the world height of the falling piece, used by the follow mode.
+    pub fn update(&mut self, rl: &RaylibHandle, delta: f32, height: f32) -> Camera3D {
+        self.time += delta;
+        self.intro = (self.intro - delta).max(0.0);
+        self.trauma = (self.trauma - SHAKE_DECAY * delta).max(0.0);
+
+        if self.mode == Mode::Orbit {
+            self.orbit_input(rl, delta);
+        }
+
+        let target_height = if self.mode == Mode::Follow { height } else { TARGET.y };
+
+        self.height += (target_height - self.height) * (FOLLOW_RATE * delta).min(1.0);
+
+        let shot = match self.mode {
+            Mode::Fixed | Mode::Follow => HOME,
+            Mode::Orbit => self.orbit,
+            Mode::Cinematic => self.cinematic(),
+        };
+
+        let mut shot = INTRO.lerp(shot, ease(1.0 - self.intro / INTRO_TIME));
+        shot.distance *= self.zoom;
+        let target = Vector3::new(TARGET.x, self.height, TARGET.z);
+
+        // cheap noise, a few unrelated sines are enough for a shake this short
+        let strength = self.trauma * self.trauma * SHAKE_AMPLITUDE;
+        let shake = Vector3::new(
+            (self.time * 41.0).sin(),
+            (self.time * 53.0 + 1.3).sin(),
+            (self.time * 47.0 + 2.1).sin(),
+        )
+        .scale_by(strength);
+
+        Camera3D::perspective(
+            target + shot.offset() + shake,
+            target + shake.scale_by(0.5),
+            Vector3::new(0.0, 1.0, 0.0),
+            FOV,
+        )
+    }
+}
@@ -0,0 +1,256 @@
+use crate::game::{Event, Game, COLUMNS};
+use crate::rng::Rng;
+
+/// Survival starts with this many rows and one more every few seconds, a little sooner each time.
+const SURVIVAL_ROWS: u32 = 2;
+const SURVIVAL_INTERVAL: f32 = 4.0;
+const SURVIVAL_SPEEDUP: f32 = 0.97;
+const SURVIVAL_FASTEST: f32 = 1.0;
+
+/// Cheese rows on the board during a race, dug ones are refilled from below until none are left.
+const RACE_VISIBLE: u32 = 3;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum RaceLength {
+    #[default]
+    Ten,
+    Eighteen,
+    Forty,
+    Hundred,
+}
+
+impl RaceLength {
+    pub const ALL: [RaceLength; 4] = [RaceLength::Ten, RaceLength::Eighteen, RaceLength::Forty, RaceLength::Hundred];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RaceLength::Ten => "10 rows",
+            RaceLength::Eighteen => "18 rows",
+            RaceLength::Forty => "40 rows",
+            RaceLength::Hundred => "100 rows",
+        }
+    }
+
+    pub fn next(&self) -> RaceLength {
+        let index = RaceLength::ALL.iter().position(|length| length == self).unwrap_or(0);
+
+        RaceLength::ALL[(index + 1) % RaceLength::ALL.len()]
+    }
+
+    pub fn from_index(index: u8) -> Option<RaceLength> {
+        RaceLength::ALL.get(index as usize).copied()
+    }
+
+    pub fn index(&self) -> u8 {
+        RaceLength::ALL.iter().position(|length| length == self).unwrap_or(0) as u8
+    }
+
+    pub fn rows(&self) -> u32 {
+        match self {
+            RaceLength::Ten => 10,
+            RaceLength::Eighteen => 18,
+            RaceLength::Forty => 40,
+            RaceLength::Hundred => 100,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    /// Rows keep rising until the stack tops out.
+    Survival,
+    /// Dig through the rows as fast as possible.
+    Race(RaceLength),
+}
+
+/// Messy garbage under the renderer's game, every row has its hole somewhere else.
+pub struct Cheese {
+    pub mode: Mode,
+    rng: Rng,
+    /// Hole of the row that rose last.
+    hole: usize,
+    /// Cheese rows still on the board, they're always the bottom ones.
+    on_board: u32,
+    risen: u32,
+    pub dug: u32,
+    /// Seconds until the next survival row, and the wait after that one.
+    timer: f32,
+    interval: f32,
+}
+
+impl Cheese {
+    /// Fills the bottom of `game` with the first rows, the holes follow from the game's seed.
+    pub fn new(mode: Mode, game: &mut Game) -> Cheese {
+        let mut rng = Rng::new(game.rng().clone().next_u64());
+
+        let mut cheese = Cheese {
+            mode,
+            hole: rng.below(COLUMNS),
+            rng,
+            on_board: 0,
+            risen: 0,
+            dug: 0,
+            timer: SURVIVAL_INTERVAL,
+            interval: SURVIVAL_INTERVAL,
+        };
+
+        let rows = match mode {
+            Mode::Survival => SURVIVAL_ROWS,
+            Mode::Race(length) => RACE_VISIBLE.min(length.rows()),
+        };
+
+        for _ in 0..rows {
+            cheese.rise(game);
+        }
+
+        cheese
+    }
+
+    fn rise(&mut self, game: &mut Game) {
+        self.hole = (self.hole + 1 + self.rng.below(COLUMNS - 1)) % COLUMNS;
+        self.on_board += 1;
+        self.risen += 1;
+
+        game.insert_garbage(1, self.hole);
+    }
+
+    /// Rows left to dig in a race.
+    pub fn left(&self) -> Option<u32> {
+        match self.mode {
+            Mode::Survival => None,
+            Mode::Race(length) => Some(length.rows().saturating_sub(self.dug)),
+        }
+    }
+
+    pub fn won(&self) -> bool {
+        self.left() == Some(0)
+    }
+
+    /// Counts the cheese rows cleared this frame and raises new ones, on the timer in survival or
+    /// to replace the dug ones in a race.
+    pub fn update(&mut self, game: &mut Game, events: &[Event], delta: f32) {
+        if game.over || self.won() {
+            return;
+        }
+
+        for event in events {
+            if let Event::ClearedRows(rows) = event {
+                let dug = (rows & ((1 << self.on_board) - 1)).count_ones();
+
+                self.on_board -= dug;
+                self.dug += dug;
+            }
+        }
+
+        match self.mode {
+            Mode::Survival => {
+                self.timer -= delta;
+
+                if self.timer <= 0.0 {
+                    self.rise(game);
+
+                    self.interval = (self.interval * SURVIVAL_SPEEDUP).max(SURVIVAL_FASTEST);
+                    self.timer = self.interval;
+                }
+            },
+            Mode::Race(length) => {
+                while self.on_board < RACE_VISIBLE && self.risen < length.rows() && !game.over {
+                    self.rise(game);
+                }
+            },
+        }
+    }
+
+    /// Shown over the board while playing.
+    pub fn describe(&self) -> String {
+        match self.mode {
+            Mode::Survival => format!("Survival  {} dug  next row in {:.1}s", self.dug, self.timer.max(0.0)),
+            Mode::Race(length) => format!("Dig race  {} of {} left", self.left().unwrap_or(0), length.rows()),
+        }
+    }
+
+    /// What the end says, None while it's going on.
+    pub fn result(&self, game: &Game) -> Option<String> {
+        match self.mode {
+            _ if self.won() => Some(format!("Dug out in {:.2}s", game.stats.elapsed)),
+            Mode::Survival if game.over => Some(format!("Survived {:.1}s", game.stats.elapsed)),
+            Mode::Race(_) if game.over => Some(String::from("Topped out")),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TShape;
+
+    fn race(length: RaceLength) -> (Game, Cheese) {
+        let mut game = Game::new(TShape::fixture(), Rng::new(1));
+        let cheese = Cheese::new(Mode::Race(length), &mut game);
+
+        (game, cheese)
+    }
+
+    #[test]
+    fn the_same_seed_gets_the_same_cheese() {
+        let (first, _) = race(RaceLength::Ten);
+        let (second, _) = race(RaceLength::Ten);
+
+        assert_eq!(first.lines(), second.lines());
+    }
+
+    #[test]
+    fn only_cleared_cheese_rows_count() {
+        let (mut game, mut cheese) = race(RaceLength::Ten);
+
+        assert_eq!(game.stack_height(), 3);
+
+        // the fourth row up is the stack above the cheese
+        cheese.update(&mut game, &[Event::ClearedRows(0b1101)], 0.0);
+
+        assert_eq!(cheese.dug, 2);
+        assert_eq!(cheese.left(), Some(8));
+    }
+
+    #[test]
+    fn a_race_refills_dug_rows_until_the_last() {
+        let (mut game, mut cheese) = race(RaceLength::Ten);
+
+        cheese.update(&mut game, &[Event::ClearedRows(0b101)], 0.0);
+
+        // the rows weren't taken off this board, so the new ones land on top of them
+        assert_eq!((cheese.on_board, cheese.risen), (3, 5));
+        assert_eq!(game.stack_height(), 5);
+
+        // as if the dug rows had been cleared, before the stack reaches the piece
+        for _ in 0..2 {
+            game.clear_board();
+            cheese.update(&mut game, &[Event::ClearedRows(0b111)], 0.0);
+        }
+
+        assert!(!game.over);
+        assert_eq!((cheese.on_board, cheese.risen), (2, 10));
+        assert_eq!(cheese.result(&game), None);
+
+        cheese.update(&mut game, &[Event::ClearedRows(0b11)], 0.0);
+
+        assert!(cheese.won());
+        assert!(cheese.result(&game).is_some_and(|result| result.starts_with("Dug out")));
+    }
+
+    #[test]
+    fn survival_rows_rise_on_the_timer() {
+        let mut game = Game::new(TShape::fixture(), Rng::new(1));
+        let mut cheese = Cheese::new(Mode::Survival, &mut game);
+
+        cheese.update(&mut game, &[], SURVIVAL_INTERVAL - 0.5);
+
+        assert_eq!(game.stack_height(), 2);
+
+        cheese.update(&mut game, &[], 0.5);
+
+        assert_eq!(game.stack_height(), 3);
+        assert_eq!(cheese.interval, SURVIVAL_INTERVAL * SURVIVAL_SPEEDUP);
+    }
+}
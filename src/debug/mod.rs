@@ -0,0 +1,335 @@
+use crate::board::Board;
+use crate::game::{Game, COLUMNS, ROWS};
+use crate::layout::WIDTH;
+
+use raylib::prelude::*;
+
+use std::ffi::CString;
+
+/// Frames kept for the frame time graph.
+const FRAMES: usize = 120;
+
+/// Milliseconds the top of the graph stands for, and where the 60 fps line sits.
+const GRAPH_MAX: f32 = 50.0;
+const GRAPH_TARGET: f32 = 1000.0 / 60.0;
+
+const PANEL: Rectangle = Rectangle { x: 10.0, y: 10.0, width: 250.0, height: 470.0 };
+const GRAPH_HEIGHT: f32 = 60.0;
+const LINE_HEIGHT: f32 = 18.0;
+
+/// Pixel size of a cell in the grid view.
+const CELL: i32 = 14;
+
+const CONSOLE_HEIGHT: i32 = 260;
+const CONSOLE_FONT: i32 = 20;
+/// Lines of output kept, older ones scroll away.
+const CONSOLE_LINES: usize = 10;
+
+const BACKDROP: Color = Color::new(0x00, 0x00, 0x00, 0xB0);
+const GRID: Color = Color::new(0x40, 0x40, 0x40, 0xFF);
+
+/// Frame times and everything about the game that isn't on screen otherwise.
+pub struct Overlay {
+    frames: [f32; FRAMES],
+    next: usize,
+}
+
+impl Overlay {
+    pub fn new() -> Overlay {
+        Overlay {
+            frames: [0.0; FRAMES],
+            next: 0,
+        }
+    }
+
+    pub fn record(&mut self, delta: f32) {
+        self.frames[self.next] = delta * 1000.0;
+        self.next = (self.next + 1) % FRAMES;
+    }
+
+    fn labels(&self, game: &Game, fps: u32) -> Vec<String> {
+        let worst = self.frames.iter().copied().fold(0.0, f32::max);
+
+        let lock = match game.lock_timer() {
+            Some(left) => format!("{:.2}s", left),
+            None => String::from("-"),
+        };
+
+        vec![
+            format!("FPS: {} (worst {:.1}ms)", fps, worst),
+            format!("Tick: {}", game.ticks()),
+            format!("Piece: {} ({})", game.shapes().name(game.piece_index()), game.piece_index()),
+            format!("Rotation: {}", game.rotation() as u32 * 90),
+            format!("Gravity: {:.3}s", game.gravity()),
+            format!("Lock: {}", lock),
+            format!("RNG: {:016x}", game.rng().state()),
+        ]
+    }
+
+    /// Oldest frame on the left, the red line is 60 fps.
+    fn graph(&self, drawer: &mut impl RaylibDraw, bounds: Rectangle) {
+        drawer.draw_rectangle_rec(bounds, BACKDROP);
+
+        let width = bounds.width / FRAMES as f32;
+
+        for index in 0..FRAMES {
+            let time = self.frames[(self.next + index) % FRAMES];
+            let height = (time / GRAPH_MAX).min(1.0) * bounds.height;
+
+            drawer.draw_rectangle_rec(
+                Rectangle::new(bounds.x + index as f32 * width, bounds.y + bounds.height - height, width.max(1.0), height),
+                if time > GRAPH_TARGET * 1.5 { Color::ORANGE } else { Color::LIME },
+            );
+        }
+
+        let target = bounds.y + bounds.height - GRAPH_TARGET / GRAPH_MAX * bounds.height;
+
+        drawer.draw_line_v(Vector2::new(bounds.x, target), Vector2::new(bounds.x + bounds.width, target), Color::RED);
+    }
+
+    /// The board as the game sees it, mirrored like the screen, the falling piece in yellow.
+    fn grid(drawer: &mut impl RaylibDraw, game: &Game, x: i32, y: i32) {
+        let shape = game.shape();
+
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                let piece = shape.iter().any(|position| position.x == column && position.y == row);
+
+                let color = if piece {
+                    Color::YELLOW
+                } else if game.lines()[row][column] {
+                    Color::WHITE
+                } else {
+                    GRID
+                };
+
+                drawer.draw_rectangle(
+                    x + (COLUMNS - 1 - column) as i32 * CELL,
+                    y + (ROWS - 1 - row) as i32 * CELL,
+                    CELL - 2,
+                    CELL - 2,
+                    color,
+                );
+            }
+        }
+    }
+
+    pub fn draw(&self, drawer: &mut (impl RaylibDraw + RaylibDrawGui), game: &Game, fps: u32) {
+        let labels = self.labels(game, fps);
+
+        drawer.draw_rectangle_rec(PANEL, BACKDROP);
+
+        drawer.gui_set_style(GuiControl::DEFAULT, 16, 16);
+        drawer.gui_group_box(PANEL, Some(c"Debug Menu"));
+
+        let mut y = PANEL.y + 10.0;
+
+        for label in &labels {
+            drawer.gui_set_style(GuiControl::DEFAULT, 16, 15);
+
+            drawer.gui_label(
+                Rectangle::new(PANEL.x + 10.0, y, PANEL.width - 20.0, LINE_HEIGHT),
+                Some(&CString::new(label.as_str()).unwrap_or_default()),
+            );
+
+            y += LINE_HEIGHT;
+        }
+
+        self.graph(drawer, Rectangle::new(PANEL.x + 10.0, y + 5.0, PANEL.width - 20.0, GRAPH_HEIGHT));
+
+        Self::grid(drawer, game, PANEL.x as i32 + 10, (y + GRAPH_HEIGHT + 15.0) as i32);
+    }
+}
+
+/// Drop-down command line, opened with the key left of 1.
+pub struct Console {
+    pub open: bool,
+    input: String,
+    output: Vec<String>,
+}
+
+impl Console {
+    pub fn new() -> Console {
+        Console {
+            open: false,
+            input: String::new(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.input.clear();
+    }
+
+    fn print(&mut self, line: impl Into<String>) {
+        self.output.push(line.into());
+
+        if self.output.len() > CONSOLE_LINES {
+            self.output.remove(0);
+        }
+    }
+
+    /// Takes all keyboard input while open, the game doesn't see any of it.
+    pub fn handle_input(&mut self, rl: &mut RaylibHandle, game: &mut Game) {
+        // raylib-rs doesn't wrap the character queue
+        loop {
+            let char = unsafe { raylib::ffi::GetCharPressed() };
+
+            match char::from_u32(char as u32) {
+                Some('`') | Some('~') => {},
+                Some(char) if char != '\0' => self.input.push(char),
+                _ => break,
+            }
+        }
+
+        while let Some(key) = rl.get_key_pressed() {
+            match key {
+                KeyboardKey::KEY_GRAVE => self.toggle(),
+                KeyboardKey::KEY_BACKSPACE => {
+                    self.input.pop();
+                },
+                KeyboardKey::KEY_ENTER => {
+                    let command = std::mem::take(&mut self.input);
+
+                    self.print(format!("> {}", command));
+
+                    match Self::run(game, &command) {
+                        Ok(Some(reply)) => self.print(reply),
+                        Ok(None) => {},
+                        Err(err) => self.print(format!("error: {}", err)),
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn argument<T: std::str::FromStr>(argument: Option<&str>, name: &str) -> Result<T, String> {
+        let argument = argument.ok_or_else(|| format!("missing {}", name))?;
+
+        argument.parse().map_err(|_| format!("bad {} `{}`", name, argument))
+    }
+
+    /// The names `spawn` takes.
+    fn pieces(game: &Game, separator: &str) -> String {
+        (0..game.shapes().count()).map(|index| game.shapes().name(index)).collect::<Vec<_>>().join(separator)
+    }
+
+    fn run(game: &mut Game, command: &str) -> Result<Option<String>, String> {
+        let mut words = command.split_whitespace();
+
+        match words.next() {
+            None => Ok(None),
+            Some("spawn") => {
+                let name = words.next().ok_or_else(|| format!("missing piece, one of {}", Self::pieces(game, ", ")))?;
+
+                // numbered from 1 like the shapes without a name
+                let piece = game.shapes().find(name)
+                    .or_else(|| name.parse::<usize>().ok().filter(|number| (1..=game.shapes().count()).contains(number)).map(|number| number - 1))
+                    .ok_or_else(|| format!("no piece `{}`, pick one of {}", name, Self::pieces(game, ", ")))?;
+
+                game.force_spawn(piece);
+
+                Ok(None)
+            },
+            Some("setcell") => {
+                let x = Self::argument::<usize>(words.next(), "x")?;
+                let y = Self::argument::<usize>(words.next(), "y")?;
+                let filled = words.next().map(|value| value != "0").unwrap_or(true);
+
+                match game.set_cell(x, y, filled) {
+                    true => Ok(None),
+                    false => Err(format!("{} {} is off the board", x, y)),
+                }
+            },
+            Some("gravity") => match words.next() {
+                Some("off") => {
+                    game.set_gravity(None);
+
+                    Ok(Some(format!("gravity back to {:.3}s", game.gravity())))
+                },
+                argument => {
+                    let gravity = Self::argument::<f32>(argument, "gravity")?;
+
+                    if gravity <= 0.0 {
+                        return Err(String::from("gravity has to be above 0"));
+                    }
+
+                    game.set_gravity(Some(gravity));
+
+                    Ok(None)
+                },
+            },
+            Some("level") => {
+                game.set_level(Self::argument(words.next(), "level")?);
+
+                Ok(None)
+            },
+            Some("clear") => {
+                game.clear_board();
+
+                Ok(None)
+            },
+            Some("load") => {
+                let path = words.next().ok_or("missing path")?;
+                let board = Board::load(path, game.shapes()).map_err(|err| err.to_string())?;
+
+                game.load_board(&board);
+
+                Ok(Some(format!("loaded {}", path)))
+            },
+            Some("save") => {
+                let path = words.next().ok_or("missing path")?;
+
+                game.board().save(path, game.shapes()).map_err(|err| err.to_string())?;
+
+                Ok(Some(format!("saved {}", path)))
+            },
+            Some("help") => Ok(Some(format!("spawn <{}|n>, setcell x y [0|1], gravity <secs|off>, level n, clear, load <file>, save <file>", Self::pieces(game, "|")))),
+            Some(other) => Err(format!("unknown command `{}`", other)),
+        }
+    }
+
+    pub fn draw(&self, drawer: &mut impl RaylibDraw) {
+        if !self.open {
+            return;
+        }
+
+        drawer.draw_rectangle(0, 0, WIDTH, CONSOLE_HEIGHT, BACKDROP);
+        drawer.draw_line(0, CONSOLE_HEIGHT, WIDTH, CONSOLE_HEIGHT, Color::GRAY);
+
+        let prompt = CONSOLE_HEIGHT - CONSOLE_FONT - 8;
+
+        for (index, line) in self.output.iter().rev().enumerate() {
+            let y = prompt - (index as i32 + 1) * (CONSOLE_FONT + 2);
+
+            if y < 0 {
+                break;
+            }
+
+            drawer.draw_text(line, 10, y, CONSOLE_FONT, Color::LIGHTGRAY);
+        }
+
+        drawer.draw_text(&format!("] {}_", self.input), 10, prompt, CONSOLE_FONT, Color::WHITE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+    use crate::TShape;
+
+    #[test]
+    fn spawn_takes_a_name_or_a_number() {
+        let mut game = Game::new(TShape::fixture(), Rng::new(1));
+
+        assert_eq!(Console::run(&mut game, "spawn i"), Ok(None));
+        assert_eq!(game.piece_index(), 2);
+        assert_eq!(Console::run(&mut game, "spawn 1"), Ok(None));
+        assert_eq!(game.piece_index(), 0);
+        assert_eq!(Console::run(&mut game, "spawn T"), Err(String::from("no piece `T`, pick one of L, O, I, S")));
+        assert_eq!(Console::run(&mut game, "spawn 5"), Err(String::from("no piece `5`, pick one of L, O, I, S")));
+    }
+}
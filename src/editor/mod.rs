@@ -0,0 +1,218 @@
+use crate::assets;
+use crate::board::Board;
+use crate::error::Result;
+use crate::game::{COLUMNS, ROWS};
+use crate::layout::{Layout, HEIGHT, WIDTH};
+use crate::TShape;
+
+use raylib::prelude::*;
+
+const BACKGROUND: Color = Color::new(0x0B, 0x0D, 0x13, 0xFF);
+const EMPTY: Color = Color::new(0x1A, 0x22, 0x2E, 0xFF);
+const BLOCK: Color = Color::new(0xC8, 0xA0, 0x64, 0xFF);
+const TEXT: Color = Color::new(0x9A, 0xA4, 0xB8, 0xFF);
+
+/// The board is drawn the way the game shows it, x = 0 on the right.
+const CELL: i32 = 70;
+const LEFT: i32 = (WIDTH - CELL * COLUMNS as i32) / 2;
+const TOP: i32 = 80;
+
+const FONT_SIZE: i32 = 20;
+
+/// Saved next to the config.
+const FILE: &str = "board.txt";
+
+const HELP: [&str; 5] = [
+    "Left click: place   Right click: erase   C: clear",
+    "1-9: add to queue   Backspace: remove last",
+    "Ctrl+S: save   Ctrl+O: load",
+    "Ctrl+C: copy fumen   Ctrl+Shift+C: copy text   Ctrl+V: paste",
+    "Enter: play   Q: back",
+];
+
+/// Sandbox for building a board and its piece sequence, to practice on or to share.
+pub struct Editor {
+    board: Board,
+    shapes: TShape,
+    status: String,
+}
+
+impl Editor {
+    pub fn new(shapes: TShape) -> Editor {
+        Editor {
+            board: Board::empty(),
+            shapes,
+            status: String::new(),
+        }
+    }
+
+    fn path() -> Result<String> {
+        Ok(assets::config_path(FILE)?.to_string_lossy().into_owned())
+    }
+
+    /// Cell under the mouse, in board coordinates.
+    fn cell_at(point: Vector2) -> Option<(usize, usize)> {
+        let column = ((point.x as i32 - LEFT) as f32 / CELL as f32).floor();
+        let row = ((point.y as i32 - TOP) as f32 / CELL as f32).floor();
+
+        if column < 0.0 || row < 0.0 || column >= COLUMNS as f32 || row >= ROWS as f32 {
+            return None;
+        }
+
+        Some((COLUMNS - 1 - column as usize, ROWS - 1 - row as usize))
+    }
+
+    fn handle_mouse(&mut self, rl: &RaylibHandle) {
+        let mouse = Layout::new(rl).to_virtual(rl.get_mouse_position());
+
+        let paint = if rl.is_mouse_button_down(MouseButton::MOUSE_LEFT_BUTTON) {
+            Some(true)
+        } else if rl.is_mouse_button_down(MouseButton::MOUSE_RIGHT_BUTTON) {
+            Some(false)
+        } else {
+            None
+        };
+
+        // holding a button down paints every cell the mouse passes over
+        if let (Some(filled), Some((x, y))) = (paint, Self::cell_at(mouse)) {
+            self.board.lines[y][x] = filled;
+        }
+    }
+
+    fn save(&mut self) {
+        let saved = Self::path().and_then(|path| {
+            self.board.save(&path, &self.shapes)?;
+
+            Ok(path)
+        });
+
+        self.status = match saved {
+            Ok(path) => format!("saved {}", path),
+            Err(err) => err.to_string(),
+        };
+    }
+
+    fn load(&mut self) {
+        match Self::path().and_then(|path| Board::load(&path, &self.shapes)) {
+            Ok(board) => {
+                self.board = board;
+                self.status = String::from("loaded");
+            },
+            Err(err) => self.status = err.to_string(),
+        }
+    }
+
+    fn copy(&mut self, rl: &mut RaylibHandle, text: String, name: &str) {
+        self.status = match rl.set_clipboard_text(&text) {
+            Ok(()) => format!("copied {}", name),
+            Err(err) => err.to_string(),
+        };
+    }
+
+    fn paste(&mut self, rl: &RaylibHandle) {
+        let pasted = rl
+            .get_clipboard_text()
+            .map_err(|err| err.to_string())
+            .and_then(|text| Board::decode("clipboard", &text, &self.shapes).map_err(|err| err.to_string()));
+
+        match pasted {
+            Ok(board) => {
+                self.board = board;
+                self.status = String::from("pasted");
+            },
+            Err(err) => self.status = err,
+        }
+    }
+
+    /// Returns what the menu should do once the editor closes.
+    fn handle_input(&mut self, rl: &mut RaylibHandle) -> Option<Option<Board>> {
+        let control = rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL) || rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        let shift = rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) || rl.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+
+        if let Some(key) = rl.get_key_pressed() {
+            match key {
+                KeyboardKey::KEY_S if control => self.save(),
+                KeyboardKey::KEY_O if control => self.load(),
+                KeyboardKey::KEY_C if control && shift => self.copy(rl, self.board.to_text(&self.shapes), "text"),
+                KeyboardKey::KEY_C if control => self.copy(rl, self.board.to_fumen(), "fumen"),
+                KeyboardKey::KEY_V if control => self.paste(rl),
+                KeyboardKey::KEY_C => {
+                    self.board.lines = Board::empty().lines;
+                },
+                KeyboardKey::KEY_BACKSPACE => {
+                    self.board.queue.pop();
+                },
+                KeyboardKey::KEY_ENTER => return Some(Some(self.board.clone())),
+                KeyboardKey::KEY_Q => return Some(None),
+                key => {
+                    let digit = (key as u32).wrapping_sub(KeyboardKey::KEY_ONE as u32) as usize;
+
+                    if digit < 9 && digit < self.shapes.count() {
+                        self.board.queue.push(digit);
+                    }
+                },
+            }
+        }
+
+        None
+    }
+
+    fn draw(&self, rl: &mut RaylibHandle, thread: &RaylibThread) {
+        let layout = Layout::new(rl);
+        let mut drawer = rl.begin_drawing(thread);
+
+        drawer.clear_background(Color::BLACK);
+
+        let mut drawer = drawer.begin_mode2D(layout.camera());
+
+        drawer.draw_rectangle(0, 0, WIDTH, HEIGHT, BACKGROUND);
+        drawer.draw_text("Board Editor", LEFT, 30, 30, Color::WHITE);
+
+        for (y, line) in self.board.lines.iter().enumerate() {
+            for (x, block) in line.iter().enumerate() {
+                drawer.draw_rectangle(
+                    LEFT + (COLUMNS - 1 - x) as i32 * CELL + 2,
+                    TOP + (ROWS - 1 - y) as i32 * CELL + 2,
+                    CELL - 4,
+                    CELL - 4,
+                    if *block { BLOCK } else { EMPTY },
+                );
+            }
+        }
+
+        let mut y = TOP + ROWS as i32 * CELL + 15;
+
+        let shapes: Vec<String> = (0..self.shapes.count().min(9)).map(|index| format!("{}: {}", index + 1, self.shapes.name(index))).collect();
+        let queue: Vec<&str> = self.board.queue.iter().map(|piece| self.shapes.name(*piece)).collect();
+
+        drawer.draw_text(&format!("Shapes  {}", shapes.join("  ")), 20, y, FONT_SIZE, TEXT);
+        y += FONT_SIZE + 4;
+
+        drawer.draw_text(&format!("Queue  {}", if queue.is_empty() { "random" } else { "" }), 20, y, FONT_SIZE, TEXT);
+        drawer.draw_text(&queue.join(" "), 100, y, FONT_SIZE, Color::WHITE);
+        y += FONT_SIZE + 12;
+
+        for line in HELP {
+            drawer.draw_text(line, 20, y, FONT_SIZE, TEXT);
+            y += FONT_SIZE + 2;
+        }
+
+        drawer.draw_text(&self.status, 20, HEIGHT - FONT_SIZE - 10, FONT_SIZE, Color::WHITE);
+    }
+
+    /// Runs until the player leaves, returns the board to play if they asked to.
+    pub fn run(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread) -> Result<Option<Board>> {
+        rl.set_window_title(thread, "Tetris Board Editor");
+
+        while !rl.window_should_close() {
+            self.draw(rl, thread);
+            self.handle_mouse(rl);
+
+            if let Some(action) = self.handle_input(rl) {
+                return Ok(action);
+            }
+        }
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    AssetNotFound {
+        name: String,
+        searched: Vec<PathBuf>,
+    },
+    AssetDecode {
+        path: String,
+        reason: String,
+    },
+    ShapeParse {
+        path: String,
+        line: usize,
+        column: usize,
+        reason: String,
+    },
+    BoardParse {
+        path: String,
+        line: usize,
+        reason: String,
+    },
+    ReplayParse {
+        path: String,
+        line: usize,
+        reason: String,
+    },
+    ConfigCorrupt {
+        path: PathBuf,
+        reason: String,
+    },
+    /// A saved game from another version or one that doesn't make sense.
+    SaveRejected {
+        path: PathBuf,
+        reason: String,
+    },
+    Audio(String),
+    Device(String),
+    /// An external bot broke the protocol or went away.
+    Protocol(String),
+    /// The versus server or the other player went away or sent something unexpected.
+    Network(String),
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl Error {
+    pub fn decode(path: &str, reason: impl ToString) -> Error {
+        Error::AssetDecode {
+            path: path.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Error {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::AssetNotFound { name, searched } => {
+                let searched = searched
+                    .iter()
+                    .map(|root| root.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+
+                write!(f, "missing asset `{}` (searched: {})", name, searched)
+            },
+            Error::AssetDecode { path, reason } => write!(f, "failed to decode {}: {}", path, reason),
+            Error::ShapeParse { path, line, column, reason } => write!(f, "{}:{}:{}: {}", path, line, column, reason),
+            Error::BoardParse { path, line, reason } => write!(f, "{}:{}: {}", path, line, reason),
+            Error::ReplayParse { path, line, reason } => write!(f, "{}:{}: {}", path, line, reason),
+            Error::ConfigCorrupt { path, reason } => write!(f, "corrupt config {}: {}", path.display(), reason),
+            Error::SaveRejected { path, reason } => write!(f, "can't continue from {}: {}", path.display(), reason),
+            Error::Audio(reason) => write!(f, "audio error: {}", reason),
+            Error::Device(reason) => write!(f, "device error: {}", reason),
+            Error::Protocol(reason) => write!(f, "bot protocol error: {}", reason),
+            Error::Network(reason) => write!(f, "network error: {}", reason),
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// To stderr like every diagnostic, stdout may be carrying JSON.
+pub fn warn(err: &Error) {
+    eprintln!("[WARNING] {}", err);
+}
+
+/// Logs a recoverable error and carries on without the value.
+pub fn recover<T>(result: Result<T>) -> Option<T> {
+    result.map_err(|err| warn(&err)).ok()
+}
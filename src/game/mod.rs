@@ -1,103 +1,1194 @@
 use crate::TShape;
+use crate::board::Board;
+use crate::rng::Rng;
+use crate::search;
+use crate::assets::Resolver;
+use crate::audio::{Audio, Mixer};
+use crate::bot::{self, Bot, BotSettings};
+use crate::camera::{self, CameraController};
+use crate::cheese::{Cheese, Mode, RaceLength};
+use crate::debug::{Console, Overlay};
+use crate::error::{self, Error, Result};
+use crate::hud::{Hud, HudMode};
+use crate::json::Value;
+use crate::layout::{self, FrameRate, Layout, HEIGHT, WIDTH};
+use crate::lighting::{Lighting, Quality};
+use crate::particles::Particles;
+use crate::postfx::{Chain, Effects, Preset};
+use crate::puzzle::{Attempt, Outcome, Puzzle};
+use crate::save;
+use crate::tbp::Client;
+use crate::net::{Session, Start};
+use crate::spectate::{Broadcast, Viewer};
+use crate::versus::{Round, Versus, VersusSettings};
 
 use raylib::prelude::*;
 
-use std::time::{Instant, Duration};
-use std::ffi::CStr;
+use std::collections::VecDeque;
 
-const HEIGHT: i32 = 1000;
-const WIDTH: i32 = 800;
+const BACKGROUND: Color = Color::new(0x0B, 0x0D, 0x13, 0xFF);
 
+pub const COLUMNS: usize = 5;
+pub const ROWS: usize = 10;
+
+const LINES_PER_LEVEL: u32 = 10;
+
+/// Seconds a shift, rotation or soft drop takes to slide into place when drawing smoothly.
+const SLIDE_TIME: f32 = 0.06;
+
+/// Stack height at which the music switches to its intense stem.
+const DANGER_HEIGHT: usize = 6;
+
+const CRATE_SCALE: f32 = 16.0;
+
+/// Middle of the board in world space, where the shadow map is centered.
+const BOARD_CENTER: Vector3 = Vector3::new(0.0, 30.0, 0.0);
+
+/// Versus moves the stacks apart along z, screen left is +z, and pulls the camera back to fit them.
+const VERSUS_SPREAD: f32 = 20.0;
+const VERSUS_ZOOM: f32 = 1.6;
+
+/// Smaller cells in 2D versus so two boards fit, and where cell (0, 0) of each goes.
+const VERSUS_CELL: f32 = 64.0;
+const VERSUS_ORIGINS: [Vector2; 2] = [Vector2::new(296.0, 920.0), Vector2::new(696.0, 920.0)];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Direction {
     Right,
     Left,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
+/// Everything a player can do with the piece, keys and bots both go through `Game::apply`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    Shift(Direction),
+    Rotate,
+    SoftDrop,
+    HardDrop,
+    Hold,
+}
+
+impl Action {
+    pub const ALL: [Action; 6] = [
+        Action::Shift(Direction::Right),
+        Action::Shift(Direction::Left),
+        Action::Rotate,
+        Action::SoftDrop,
+        Action::HardDrop,
+        Action::Hold,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Shift(Direction::Right) => "right",
+            Action::Shift(Direction::Left) => "left",
+            Action::Rotate => "rotate",
+            Action::SoftDrop => "soft_drop",
+            Action::HardDrop => "hard_drop",
+            Action::Hold => "hold",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().find(|action| action.name() == name).copied()
+    }
+}
+
+/// Everything noteworthy that happened during an update, drained by the renderer every frame.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Event {
+    Move,
+    Rotate,
+    RotateFail,
+    Hold,
+    HardDrop,
+    Lock,
+    Clear(u32),
+    /// Bit per row removed by a clear, numbered before the rows above fell down.
+    ClearedRows(u16),
+    /// Locked right after a rotation into a spot the piece can't move out of,
+    /// the shapes come from a file so this isn't limited to the T piece.
+    Spin(u32),
+    /// Number of line clears in a row, starting at 2.
+    Combo(u32),
+    LevelUp(u32),
+    /// The piece locked with this many more shifts and rotations than it needed.
+    Fault(u32),
+    /// Rows of garbage pushed up from the floor in versus.
+    Garbage(u32),
+    GameOver,
+}
+
+impl Event {
+    /// `[name]`, or `[name, number]` for the events that carry one.
+    pub fn encode(&self) -> Value {
+        let (name, number) = match *self {
+            Event::Move => ("move", None),
+            Event::Rotate => ("rotate", None),
+            Event::RotateFail => ("rotate_fail", None),
+            Event::Hold => ("hold", None),
+            Event::HardDrop => ("hard_drop", None),
+            Event::Lock => ("lock", None),
+            Event::Clear(lines) => ("clear", Some(lines)),
+            Event::ClearedRows(rows) => ("cleared_rows", Some(rows as u32)),
+            Event::Spin(lines) => ("spin", Some(lines)),
+            Event::Combo(combo) => ("combo", Some(combo)),
+            Event::LevelUp(level) => ("level_up", Some(level)),
+            Event::Fault(wasted) => ("fault", Some(wasted)),
+            Event::Garbage(rows) => ("garbage", Some(rows)),
+            Event::GameOver => ("game_over", None),
+        };
+
+        Value::Array(std::iter::once(name.into()).chain(number.map(Value::from)).collect())
+    }
+
+    pub fn decode(value: &Value) -> Option<Event> {
+        let fields = value.as_array()?;
+        let number = || fields.get(1)?.as_i64().and_then(|number| u32::try_from(number).ok());
+
+        Some(match fields.first()?.as_str()? {
+            "move" => Event::Move,
+            "rotate" => Event::Rotate,
+            "rotate_fail" => Event::RotateFail,
+            "hold" => Event::Hold,
+            "hard_drop" => Event::HardDrop,
+            "lock" => Event::Lock,
+            "clear" => Event::Clear(number()?),
+            "cleared_rows" => Event::ClearedRows(u16::try_from(number()?).ok()?),
+            "spin" => Event::Spin(number()?),
+            "combo" => Event::Combo(number()?),
+            "level_up" => Event::LevelUp(number()?),
+            "fault" => Event::Fault(number()?),
+            "garbage" => Event::Garbage(number()?),
+            "game_over" => Event::GameOver,
+            _ => return None,
+        })
+    }
+}
+
+/// Base points for clearing 1 to 4 lines, and for spins clearing 0 to 3, multiplied by the level.
+const CLEAR_POINTS: [u32; 5] = [0, 100, 300, 500, 800];
+const SPIN_POINTS: [u32; 4] = [400, 800, 1200, 1600];
+
 pub struct Score {
+    pub points: u32,
     pub lines: u32,
+    pub level: u32,
+    pub combo: u32,
+}
+
+/// Numbers for the HUD, only counted while the game runs.
+pub struct Stats {
+    pub elapsed: f32,
+    pub pieces: u32,
+    pub inputs: u32,
+    /// Locked pieces per shape index.
+    pub histogram: Vec<u32>,
+}
+
+impl Stats {
+    /// Pieces per second.
+    pub fn pps(&self) -> f32 {
+        if self.elapsed > 0.0 { self.pieces as f32 / self.elapsed } else { 0.0 }
+    }
+
+    /// Keys per piece.
+    pub fn kpp(&self) -> f32 {
+        if self.pieces > 0 { self.inputs as f32 / self.pieces as f32 } else { 0.0 }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct PieceFinesse {
+    pub placed: u32,
+    pub faults: u32,
+    /// Inputs spent beyond the fewest possible.
+    pub wasted: u32,
+}
+
+/// Shifts and rotations spent on each piece against the fewest that reach the same spot.
+pub struct Finesse {
+    /// Spent on the falling piece so far.
+    inputs: u32,
+    pub faults: u32,
+    /// Per shape index.
+    pub pieces: Vec<PieceFinesse>,
+    /// Seconds since the last fault and how many inputs it wasted, for the HUD to flash it.
+    pub since_fault: f32,
+    pub last_wasted: u32,
+}
+
+impl Finesse {
+    fn new(count: usize) -> Finesse {
+        Finesse {
+            inputs: 0,
+            faults: 0,
+            pieces: vec![PieceFinesse::default(); count],
+            since_fault: f32::MAX,
+            last_wasted: 0,
+        }
+    }
 }
 
 pub struct Game {
     lines: Vec<Vec<bool>>,
 
-    delta: Instant,
+    timer: f32,
+    piece: usize,
     shape: Vec<Position>,
+    /// Where each block of the piece was drawn when it last moved, and the seconds since.
+    slide: Vec<(f32, f32)>,
+    sliding: f32,
     shapes: TShape,
+    hold: Option<usize>,
+    /// Pieces dealt before the random ones, set up by a loaded board.
+    queue: VecDeque<usize>,
+    held: bool,
+    spun: bool,
+    /// Quarter turns since the piece spawned.
+    rotation: u8,
+    events: Vec<Event>,
+    rng: Rng,
+    /// Updates run so far, paused time doesn't count.
+    ticks: u64,
+    /// Set from the debug console, replaces the level's gravity.
+    gravity_override: Option<f32>,
+    /// Every action applied and the tick it happened at, while recording.
+    recording: Option<Vec<(u64, Action)>>,
 
     pub score: Score,
+    pub stats: Stats,
+    pub finesse: Finesse,
+    pub over: bool,
     debug: bool,
+    paused: bool,
+}
+
+impl Game {
+    pub fn new(shapes: TShape, mut rng: Rng) -> Game {
+        let piece = shapes.rand_index(&mut rng);
+        let count = shapes.count();
+
+        Game {
+            lines: vec![vec![false; COLUMNS]; ROWS],
+
+            timer: 0.0,
+            piece,
+            shape: shapes.get(piece),
+            slide: Vec::new(),
+            sliding: SLIDE_TIME,
+            shapes,
+            hold: None,
+            queue: VecDeque::new(),
+            held: false,
+            spun: false,
+            rotation: 0,
+            events: Vec::new(),
+            rng,
+            ticks: 0,
+            gravity_override: None,
+            recording: None,
+
+            score: Score {
+                points: 0,
+                lines: 0,
+                level: 1,
+                combo: 0,
+            },
+            stats: Stats {
+                elapsed: 0.0,
+                pieces: 0,
+                inputs: 0,
+                histogram: vec![0; count],
+            },
+            finesse: Finesse::new(count),
+            over: false,
+            debug: false,
+            paused: false,
+        }
+    }
+
+    /// Seconds between two gravity steps at the current level.
+    pub fn gravity(&self) -> f32 {
+        if let Some(gravity) = self.gravity_override {
+            return gravity;
+        }
+
+        (0.5 * 0.85_f32.powi(self.score.level as i32 - 1)).max(0.05)
+    }
+
+    /// Number of rows from the floor up to the highest filled cell.
+    pub fn stack_height(&self) -> usize {
+        self.lines
+            .iter()
+            .rposition(|line| line.iter().any(|block| *block))
+            .map_or(0, |row| row + 1)
+    }
+
+    pub fn shapes(&self) -> &TShape {
+        &self.shapes
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        if self.over || self.paused {
+            return;
+        }
+
+        self.ticks += 1;
+        self.timer += delta;
+        self.sliding += delta;
+        self.stats.elapsed += delta;
+        self.finesse.since_fault += delta;
+
+        if self.timer >= self.gravity() {
+            self.move_down();
+
+            if self.is_collision() {
+                self.lock();
+            }
+
+            self.timer = 0.0;
+        }
+    }
+
+    /// The piece's blocks in fractional cells, sliding from where they were drawn before the last
+    /// move and falling towards the next row as the gravity timer runs, if that row is free.
+    pub fn piece(&self, smooth: bool) -> Vec<(f32, f32)> {
+        let cells = self.shape.iter().map(|position| (position.x as f32, position.y as f32));
+
+        if !smooth {
+            return cells.collect();
+        }
+
+        let fall = if self.is_collision() { 0.0 } else { (self.timer / self.gravity()).min(1.0) };
+        let t = (self.sliding / SLIDE_TIME).min(1.0);
+
+        cells
+            .enumerate()
+            .map(|(index, (x, y))| {
+                let (x, y) = (x, y - fall);
+
+                match self.slide.get(index) {
+                    Some((from_x, from_y)) => (from_x + (x - from_x) * t, from_y + (y - from_y) * t),
+                    None => (x, y),
+                }
+            })
+            .collect()
+    }
+
+    /// Call before the piece moves so it slides from where it is drawn now.
+    fn start_slide(&mut self) {
+        self.slide = self.piece(true);
+        self.sliding = 0.0;
+    }
+
+    pub fn fits(&self, shape: &[Position]) -> bool {
+        shape.iter().all(|position| position.x < COLUMNS && position.y < ROWS && !self.lines[position.y][position.x])
+    }
+
+    /// The shape moved by the offset, None if that goes past the bottom or the right edge.
+    pub fn translated(shape: &[Position], x: isize, y: isize) -> Option<Vec<Position>> {
+        shape
+            .iter()
+            .map(|position| Some(Position {
+                x: position.x.checked_add_signed(x)?,
+                y: position.y.checked_add_signed(y)?,
+            }))
+            .collect()
+    }
+
+    /// Moves the piece by the offset if it fits there.
+    fn translate(&mut self, x: isize, y: isize) -> bool {
+        match Self::translated(&self.shape, x, y) {
+            Some(moved) if self.fits(&moved) => {
+                self.shape = moved;
+                self.spun = false;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn shift(&mut self, direction: Direction) {
+        let offset = match direction {
+            Direction::Right => -1,
+            Direction::Left => 1,
+        };
+
+        let slide = self.piece(true);
+
+        self.stats.inputs += 1;
+        self.finesse.inputs += 1;
+
+        if self.translate(offset, 0) {
+            self.slide = slide;
+            self.sliding = 0.0;
+            self.events.push(Event::Move);
+        }
+    }
+
+    fn get_top_position(shape: &[Position]) -> Position {
+        let mut top = shape[0];
+
+        shape
+            .iter()
+            .for_each(|pos| if top.y > pos.y { top = *pos });
+
+        top
+    }
+
+    fn get_corner_position(shape: &[Position]) -> Position {
+        let mut corner = shape[0];
+
+        shape
+            .iter()
+            .for_each(|pos| if corner.x > pos.x { corner = *pos });
+
+        corner
+    }
+
+    /// The shape turned inside its square bounding box, it may not fit the board.
+    pub fn rotated(shape: &[Position]) -> Vec<Position> {
+        let top = Self::get_top_position(shape);
+        let left_corner = Self::get_corner_position(shape);
+
+        let size = shape
+            .iter()
+            .map(|position| (position.x - left_corner.x).max(position.y - top.y) + 1)
+            .max()
+            .unwrap_or(1);
+
+        shape
+            .iter()
+            .map(|position| Position {
+                x: left_corner.x + (size - 1) - (position.y - top.y),
+                y: top.y + (position.x - left_corner.x),
+            })
+            .collect()
+    }
+
+    /// Rotates the piece inside its square bounding box, fails if the result would overlap.
+    pub fn rotate(&mut self) {
+        self.stats.inputs += 1;
+        self.finesse.inputs += 1;
+
+        let rotated = Self::rotated(&self.shape);
+
+        if self.fits(&rotated) {
+            self.start_slide();
+            self.shape = rotated;
+            self.rotation = (self.rotation + 1) % 4;
+            self.spun = true;
+            self.events.push(Event::Rotate);
+        } else {
+            self.events.push(Event::RotateFail);
+        }
+    }
+
+    pub fn soft_drop(&mut self) {
+        self.stats.inputs += 1;
+
+        if !self.is_collision() {
+            self.start_slide();
+            self.move_down();
+            self.score.points += 1;
+            self.events.push(Event::Move);
+        }
+    }
+
+    pub fn hard_drop(&mut self) {
+        self.stats.inputs += 1;
+
+        while self.translate(0, -1) {
+            self.score.points += 2;
+        }
+
+        self.events.push(Event::HardDrop);
+        self.lock();
+        self.timer = 0.0;
+    }
+
+    pub fn apply(&mut self, action: Action) {
+        if let Some(recording) = &mut self.recording {
+            recording.push((self.ticks, action));
+        }
+
+        match action {
+            Action::Shift(direction) => self.shift(direction),
+            Action::Rotate => self.rotate(),
+            Action::SoftDrop => self.soft_drop(),
+            Action::HardDrop => self.hard_drop(),
+            Action::Hold => self.hold(),
+        }
+    }
+
+    /// Swaps the piece with the held one, once per piece.
+    pub fn hold(&mut self) {
+        self.stats.inputs += 1;
+
+        if self.held {
+            return;
+        }
+
+        let next = match self.hold {
+            Some(piece) => piece,
+            None => self.next_piece(),
+        };
+
+        self.hold = Some(self.piece);
+        self.events.push(Event::Hold);
+        self.spawn(next);
+        self.held = true;
+    }
+
+    fn is_collision(&self) -> bool {
+        self.is_resting(&self.shape)
+    }
+
+    /// Whether a shape at this spot would lock, it sits on the floor or the stack.
+    pub fn is_resting(&self, shape: &[Position]) -> bool {
+        for position in shape {
+            if position.y == 0 {
+                return true;
+            }
+
+            if self.lines[position.y - 1][position.x] || self.lines[position.y][position.x] {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn move_down(&mut self) {
+        self.translate(0, -1);
+    }
+
+    fn is_immobile(&mut self) -> bool {
+        let shape = self.shape.clone();
+        let mobile = [(1, 0), (-1, 0), (0, 1)].iter().any(|(x, y)| self.translate(*x, *y));
+
+        self.shape = shape;
+
+        !mobile
+    }
+
+    /// Compares the inputs spent with a search from the spawn, the stack hasn't changed since.
+    fn check_finesse(&mut self) {
+        let mut cells = self.shape.clone();
+        cells.sort_by_key(|position| (position.y, position.x));
+
+        let fewest = search::placements(self, &self.shapes.get(self.piece))
+            .into_iter()
+            .find(|placement| placement.cells == cells)
+            .map(|placement| placement.inputs);
+
+        let piece = &mut self.finesse.pieces[self.piece];
+        piece.placed += 1;
+
+        // a placement the search can't reach was rotated in some way it doesn't model, so it's not judged
+        if let Some(wasted) = fewest.and_then(|fewest| self.finesse.inputs.checked_sub(fewest)).filter(|wasted| *wasted > 0) {
+            piece.faults += 1;
+            piece.wasted += wasted;
+
+            self.finesse.faults += 1;
+            self.finesse.since_fault = 0.0;
+            self.finesse.last_wasted = wasted;
+            self.events.push(Event::Fault(wasted));
+        }
+    }
+
+    fn lock(&mut self) {
+        let spin = self.spun && self.is_immobile();
+
+        self.check_finesse();
+
+        for position in &self.shape {
+            self.lines[position.y][position.x] = true;
+        }
+
+        self.events.push(Event::Lock);
+
+        self.stats.pieces += 1;
+        self.stats.histogram[self.piece] += 1;
+
+        let cleared = self.update_lines();
+
+        if spin {
+            self.events.push(Event::Spin(cleared));
+            self.score.points += SPIN_POINTS[(cleared as usize).min(3)] * self.score.level;
+        } else {
+            self.score.points += CLEAR_POINTS[(cleared as usize).min(4)] * self.score.level;
+        }
+
+        if cleared > 0 {
+            self.events.push(Event::Clear(cleared));
+
+            self.score.combo += 1;
+
+            if self.score.combo > 1 {
+                self.events.push(Event::Combo(self.score.combo));
+                self.score.points += 50 * (self.score.combo - 1) * self.score.level;
+            }
+        } else {
+            self.score.combo = 0;
+        }
+
+        let level = self.score.lines / LINES_PER_LEVEL + 1;
+
+        if level > self.score.level {
+            self.score.level = level;
+            self.events.push(Event::LevelUp(level));
+        }
+
+        self.held = false;
+        let next = self.next_piece();
+
+        self.spawn(next);
+    }
+
+    fn next_piece(&mut self) -> usize {
+        match self.queue.pop_front() {
+            Some(piece) => piece,
+            None => self.shapes.rand_index(&mut self.rng),
+        }
+    }
+
+    fn spawn(&mut self, piece: usize) {
+        self.piece = piece;
+        self.shape = self.shapes.get(piece);
+        self.slide.clear();
+        self.spun = false;
+        self.rotation = 0;
+        self.finesse.inputs = 0;
+
+        if !self.fits(&self.shape) {
+            self.over = true;
+            self.events.push(Event::GameOver);
+        }
+    }
+
+    fn is_valid_point(line: &[bool]) -> bool {
+        line.iter().all(|block| *block)
+    }
+
+    fn update_lines(&mut self) -> u32 {
+        let rows = self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| Self::is_valid_point(line))
+            .fold(0, |rows, (row, _)| rows | 1 << row);
+
+        if rows != 0 {
+            self.events.push(Event::ClearedRows(rows));
+        }
+
+        self.lines.retain(|line| !Self::is_valid_point(line));
+
+        let cleared = ROWS - self.lines.len();
+
+        self.lines.resize(ROWS, vec![false; COLUMNS]);
+        self.score.lines += cleared as u32;
+
+        cleared as u32
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn piece_index(&self) -> usize {
+        self.piece
+    }
+
+    pub fn rotation(&self) -> u8 {
+        self.rotation
+    }
+
+    pub fn hold_piece(&self) -> Option<usize> {
+        self.hold
+    }
+
+    /// Holding is allowed once per piece.
+    pub fn can_hold(&self) -> bool {
+        !self.held
+    }
+
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    pub fn lines(&self) -> &[Vec<bool>] {
+        &self.lines
+    }
+
+    pub fn shape(&self) -> &[Position] {
+        &self.shape
+    }
+
+    /// Seconds until a piece resting on the stack locks, None while it can still fall.
+    pub fn lock_timer(&self) -> Option<f32> {
+        self.is_collision().then(|| (self.gravity() - self.timer).max(0.0))
+    }
+
+    /// Replaces the falling piece, for the debug console.
+    pub fn force_spawn(&mut self, piece: usize) {
+        self.over = false;
+        self.spawn(piece);
+    }
+
+    pub fn set_cell(&mut self, x: usize, y: usize, filled: bool) -> bool {
+        match self.lines.get_mut(y).and_then(|line| line.get_mut(x)) {
+            Some(cell) => {
+                *cell = filled;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Pushes `rows` of garbage up from the floor with the gap at `hole`. The piece only rises when
+    /// the stack reaches it, blocks pushed off the top or a piece with nowhere to go end the game.
+    pub fn insert_garbage(&mut self, rows: u32, hole: usize) {
+        let rows = (rows as usize).min(ROWS);
+
+        if self.over || rows == 0 {
+            return;
+        }
+
+        let overflow = self.lines[ROWS - rows..].iter().any(|line| line.iter().any(|block| *block));
+
+        self.lines.truncate(ROWS - rows);
+
+        for _ in 0..rows {
+            let mut line = vec![true; COLUMNS];
+
+            if let Some(cell) = line.get_mut(hole) {
+                *cell = false;
+            }
+
+            self.lines.insert(0, line);
+        }
+
+        self.events.push(Event::Garbage(rows as u32));
+
+        if overflow || !(0..=rows).any(|offset| self.translate(0, offset as isize)) {
+            self.over = true;
+            self.events.push(Event::GameOver);
+        }
+    }
+
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    pub fn take_recording(&mut self) -> Vec<(u64, Action)> {
+        self.recording.take().unwrap_or_default()
+    }
+
+    pub fn set_gravity(&mut self, gravity: Option<f32>) {
+        self.gravity_override = gravity;
+    }
+
+    pub fn set_level(&mut self, level: u32) {
+        self.score.level = level.max(1);
+    }
+
+    pub fn clear_board(&mut self) {
+        self.lines = vec![vec![false; COLUMNS]; ROWS];
+    }
+
+    /// Replaces the stack and deals the board's queue, starting with a fresh piece.
+    pub fn load_board(&mut self, board: &Board) {
+        self.lines = board.lines.clone();
+        self.queue = board.queue.iter().copied().filter(|piece| *piece < self.shapes.count()).collect();
+        self.hold = None;
+        self.held = false;
+        self.over = false;
+
+        let next = self.next_piece();
+
+        self.spawn(next);
+    }
+
+    /// Starts over on `board` as if it were a new game, for retrying a puzzle.
+    pub fn restart(&mut self, board: &Board) {
+        self.score = Score {
+            points: 0,
+            lines: 0,
+            level: 1,
+            combo: 0,
+        };
+
+        self.stats = Stats {
+            elapsed: 0.0,
+            pieces: 0,
+            inputs: 0,
+            histogram: vec![0; self.shapes.count()],
+        };
+
+        self.finesse = Finesse::new(self.shapes.count());
+
+        self.events.clear();
+        self.timer = 0.0;
+        self.paused = false;
+        self.load_board(board);
+    }
+
+    /// Everything that's drawn, the stack as one string per row from the floor up with `#` for a
+    /// block. It's enough to show the game elsewhere but not to play it on.
+    pub fn snapshot(&self) -> Value {
+        let board = self.lines
+            .iter()
+            .map(|line| Value::String(line.iter().map(|block| if *block { '#' } else { '.' }).collect()))
+            .collect();
+
+        let shape = self.shape
+            .iter()
+            .map(|position| Value::from(vec![position.x as u32, position.y as u32]))
+            .collect();
+
+        Value::object([
+            ("board", Value::Array(board)),
+            ("piece", (self.piece as u32).into()),
+            ("shape", Value::Array(shape)),
+            ("rotation", (self.rotation as u32).into()),
+            ("hold", self.hold.map(|piece| piece as u32).into()),
+            ("held", self.held.into()),
+            ("queue", self.queue.iter().map(|piece| *piece as u32).collect::<Vec<u32>>().into()),
+            ("timer", self.timer.into()),
+            ("score", Value::object([
+                ("points", self.score.points.into()),
+                ("lines", self.score.lines.into()),
+                ("level", self.score.level.into()),
+                ("combo", self.score.combo.into()),
+            ])),
+            ("stats", Value::object([
+                ("elapsed", self.stats.elapsed.into()),
+                ("pieces", self.stats.pieces.into()),
+                ("inputs", self.stats.inputs.into()),
+                ("histogram", self.stats.histogram.clone().into()),
+            ])),
+            ("over", self.over.into()),
+            ("paused", self.paused.into()),
+        ])
+    }
+
+    /// Takes on a snapshot, the piece slides over when it only moved. Nothing changes unless
+    /// the whole snapshot is valid for these shapes.
+    pub fn restore(&mut self, snapshot: &Value) -> std::result::Result<(), String> {
+        let count = self.shapes.count();
+
+        let field = |value: &Value, name: &str| value.get(name).ok_or_else(|| format!("missing {}", name)).cloned();
+        let number = |value: &Value, name: &str| field(value, name)?.as_i64().and_then(|number| u32::try_from(number).ok()).ok_or_else(|| format!("bad {}", name));
+        let seconds = |value: &Value, name: &str| field(value, name)?.as_f64().filter(|seconds| seconds.is_finite()).map(|seconds| seconds as f32).ok_or_else(|| format!("bad {}", name));
+        let flag = |value: &Value, name: &str| field(value, name)?.as_bool().ok_or_else(|| format!("bad {}", name));
+        let piece = |value: &Value| value.as_i64().map(|piece| piece as usize).filter(|piece| *piece < count);
+        let list = |value: &Value, name: &str| field(value, name)?.as_array().map(<[Value]>::to_vec).ok_or_else(|| format!("bad {}", name));
+
+        let lines = list(snapshot, "board")?
+            .iter()
+            .map(|line| {
+                let line = line.as_str().filter(|line| line.chars().count() == COLUMNS).ok_or("bad board row")?;
+
+                line.chars()
+                    .map(|block| match block {
+                        '#' => Ok(true),
+                        '.' => Ok(false),
+                        _ => Err("bad board row"),
+                    })
+                    .collect::<std::result::Result<Vec<bool>, &str>>()
+            })
+            .collect::<std::result::Result<Vec<Vec<bool>>, &str>>()?;
+
+        if lines.len() != ROWS {
+            return Err(format!("{} board rows instead of {}", lines.len(), ROWS));
+        }
+
+        let shape = list(snapshot, "shape")?
+            .iter()
+            .map(|cell| match cell.as_array()?.iter().map(Value::as_i64).collect::<Option<Vec<i64>>>()?.as_slice() {
+                [x, y] if (0..COLUMNS as i64).contains(x) && (0..ROWS as i64).contains(y) => Some(Position { x: *x as usize, y: *y as usize }),
+                _ => None,
+            })
+            .collect::<Option<Vec<Position>>>()
+            .filter(|shape| !shape.is_empty())
+            .ok_or("bad shape")?;
+
+        let hold = match field(snapshot, "hold")? {
+            Value::Null => None,
+            hold => Some(piece(&hold).ok_or("bad hold")?),
+        };
+
+        let queue = list(snapshot, "queue")?.iter().map(piece).collect::<Option<VecDeque<usize>>>().ok_or("bad queue")?;
+        let next = field(snapshot, "piece")?;
+        let next = piece(&next).ok_or("bad piece")?;
+
+        let score = field(snapshot, "score")?;
+        let stats = field(snapshot, "stats")?;
+
+        let histogram = list(&stats, "histogram")?
+            .iter()
+            .map(|pieces| pieces.as_i64().and_then(|pieces| u32::try_from(pieces).ok()))
+            .collect::<Option<Vec<u32>>>()
+            .filter(|histogram| histogram.len() == count)
+            .ok_or("bad histogram")?;
+
+        let score = Score {
+            points: number(&score, "points")?,
+            lines: number(&score, "lines")?,
+            level: number(&score, "level")?.max(1),
+            combo: number(&score, "combo")?,
+        };
+
+        let stats = Stats {
+            elapsed: seconds(&stats, "elapsed")?,
+            pieces: number(&stats, "pieces")?,
+            inputs: number(&stats, "inputs")?,
+            histogram,
+        };
+
+        let rotation = (number(snapshot, "rotation")? % 4) as u8;
+        let timer = seconds(snapshot, "timer")?;
+        let held = flag(snapshot, "held")?;
+        let over = flag(snapshot, "over")?;
+        let paused = flag(snapshot, "paused")?;
+
+        if next != self.piece || shape.len() != self.shape.len() {
+            self.slide.clear();
+        } else if shape != self.shape {
+            self.start_slide();
+        }
+
+        self.lines = lines;
+        self.piece = next;
+        self.shape = shape;
+        self.rotation = rotation;
+        self.hold = hold;
+        self.held = held;
+        self.queue = queue;
+        self.timer = timer;
+        self.score = score;
+        self.stats = stats;
+        self.over = over;
+        self.paused = paused;
+
+        Ok(())
+    }
+
+    /// A snapshot with what it takes to play on from it, the random state deals the same pieces
+    /// that would have come next.
+    pub fn save_state(&self) -> Value {
+        let pieces = self.finesse.pieces
+            .iter()
+            .map(|piece| Value::from(vec![piece.placed, piece.faults, piece.wasted]))
+            .collect();
+
+        Value::object([
+            ("game", self.snapshot()),
+            // neither fits in a JSON number
+            ("rng", Value::String(self.rng.state().to_string())),
+            ("ticks", Value::String(self.ticks.to_string())),
+            ("spun", self.spun.into()),
+            ("finesse", Value::object([
+                ("inputs", self.finesse.inputs.into()),
+                ("faults", self.finesse.faults.into()),
+                ("pieces", Value::Array(pieces)),
+            ])),
+        ])
+    }
+
+    /// Takes on a saved state, like `restore` nothing changes unless all of it is valid.
+    pub fn load_state(&mut self, state: &Value) -> std::result::Result<(), String> {
+        let field = |value: &Value, name: &str| value.get(name).cloned().ok_or_else(|| format!("missing {}", name));
+        let number = |value: &Value, name: &str| field(value, name)?.as_i64().and_then(|number| u32::try_from(number).ok()).ok_or_else(|| format!("bad {}", name));
+        let big = |name: &str| field(state, name)?.as_str().and_then(|number| number.parse::<u64>().ok()).ok_or_else(|| format!("bad {}", name));
+
+        let rng = Rng::new(big("rng")?);
+        let ticks = big("ticks")?;
+        let spun = field(state, "spun")?.as_bool().ok_or("bad spun")?;
+        let finesse = field(state, "finesse")?;
+
+        let pieces = field(&finesse, "pieces")?
+            .as_array()
+            .ok_or("bad finesse pieces")?
+            .iter()
+            .map(|piece| match piece.as_array()?.iter().map(|count| count.as_i64().and_then(|count| u32::try_from(count).ok())).collect::<Option<Vec<u32>>>()?.as_slice() {
+                [placed, faults, wasted] => Some(PieceFinesse { placed: *placed, faults: *faults, wasted: *wasted }),
+                _ => None,
+            })
+            .collect::<Option<Vec<PieceFinesse>>>()
+            .filter(|pieces| pieces.len() == self.shapes.count())
+            .ok_or("bad finesse pieces")?;
+
+        let inputs = number(&finesse, "inputs")?;
+        let faults = number(&finesse, "faults")?;
+
+        self.restore(&field(state, "game")?)?;
+
+        self.rng = rng;
+        self.ticks = ticks;
+        self.spun = spun;
+        self.finesse = Finesse {
+            inputs,
+            faults,
+            pieces,
+            ..Finesse::new(self.shapes.count())
+        };
+
+        Ok(())
+    }
+
+    /// The stack with the falling piece first in the queue, so loading it again deals the same pieces.
+    pub fn board(&self) -> Board {
+        Board {
+            lines: self.lines.clone(),
+            queue: std::iter::once(self.piece).chain(self.queue.iter().copied()).collect(),
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct Settings {
     pub smooth: bool,
     pub mode3d: bool,
+    pub mixer: Mixer,
+    pub fullscreen: bool,
+    pub effects: Effects,
+    pub lighting: Quality,
+    pub camera: camera::Mode,
+    pub low_effects: bool,
+    pub frame_rate: FrameRate,
+    pub hud: HudMode,
+    /// Flags finesse faults as they happen and breaks them down at the end.
+    pub finesse: bool,
+    pub bot: BotSettings,
+    pub versus: VersusSettings,
+    /// Rows to dig in a cheese race.
+    pub race: RaceLength,
 }
 
 pub struct Assets {
-    theme: Sound,
-    thump: Sound,
     metal_crate: Model,
     table: Model,
-    shader: Shader,
+    postfx: Chain,
+    /// None when lighting is off or its shaders failed to load, the scene is drawn unlit then.
+    lighting: Option<Lighting>,
+    hud: Hud,
     tbox: Texture2D,
 }
 
 pub struct Renderer<'a> {
     rl: &'a mut RaylibHandle,
     thread: &'a RaylibThread,
-    audio: &'a mut RaylibAudio,
+    audio: &'a mut Audio,
     framebuffer: RenderTexture2D,
     camera: Camera3D,
+    camera_controller: CameraController,
+    particles: Particles,
+    rival_particles: Particles,
+    overlay: Overlay,
+    console: Console,
     pub game: Game,
+    /// Set when playing a puzzle.
+    pub attempt: Option<Attempt>,
+    /// Plays instead of the keyboard when set.
+    pub bot: Option<Bot>,
+    /// Command line of an external bot, it takes the built-in one's place.
+    tbp: Option<String>,
+    /// The menu's attract mode, any key ends it.
+    pub demo: bool,
+    /// Set for a versus match, the game is the left board.
+    pub versus: Option<Versus>,
+    /// Set when the versus match is played over the network.
+    pub online: Option<Session>,
+    /// Saved on pause and on the way out so it can be continued, only for plain games.
+    pub saving: bool,
+    /// Set once the save on disk is this game's, loaded by `resume` or written by `save`.
+    owns_save: bool,
+    /// Set for the garbage survival and dig race modes.
+    pub cheese: Option<Cheese>,
+    /// Streams the game to spectators when set.
+    pub broadcast: Option<Broadcast>,
+    /// Set when watching someone else's game, it's only drawn.
+    viewer: Option<Viewer>,
     assets: Assets,
-    settings: Settings,
+    pub settings: Settings,
+    finished: bool,
 }
 
 impl<'a> Renderer<'a> {
-    pub fn new(rl: &'a mut RaylibHandle, thread: &'a RaylibThread, audio: &'a mut RaylibAudio, settings: Settings) -> Result<Renderer<'a>, Box<dyn std::error::Error>> {
+    pub fn new(rl: &'a mut RaylibHandle, thread: &'a RaylibThread, audio: &'a mut Audio, resolver: &Resolver, settings: Settings) -> Result<Renderer<'a>> {
         rl.set_window_title(thread, "Playing Tetris");
 
-        let mut tbox = Image::load_image("assets/textures/tbox.png")?;
+        // a game that failed midway never stopped its stems
+        audio.music.stop(audio.backend.as_mut());
+
+        let tbox_path = resolver.resolve("textures/tbox.png")?;
+        let mut tbox = Image::load_image(&tbox_path).map_err(|err| Error::decode(&tbox_path, err))?;
         tbox.resize(90, 90);
 
+        let box_path = resolver.resolve("box.obj")?;
+        let table_path = resolver.resolve("table.obj")?;
+
         let mut assets = Assets {
-            theme: Sound::load_sound("assets/sounds/theme.mp3")?,
-            thump: Sound::load_sound("assets/sounds/thump.mp3")?,
-            metal_crate: rl.load_model(&thread, "assets/box.obj")?,
-            table: rl.load_model(&thread, "assets/table.obj")?,
-            shader: rl.load_shader(&thread, None, Some("assets/shaders/shader.fs"))?,
-            tbox: rl.load_texture_from_image(&thread, &tbox)?,
+            metal_crate: rl.load_model(thread, &box_path).map_err(|err| Error::decode(&box_path, err))?,
+            table: rl.load_model(thread, &table_path).map_err(|err| Error::decode(&table_path, err))?,
+            postfx: match error::recover(Chain::load(rl, thread, resolver, settings.effects.preset)) {
+                Some(postfx) => postfx,
+                None => Chain::load(rl, thread, resolver, Preset::None)?,
+            },
+            lighting: match settings.lighting {
+                Quality::Off => None,
+                quality => error::recover(Lighting::load(rl, thread, resolver, quality)),
+            },
+            hud: Hud::load(rl, thread, resolver)?,
+            tbox: rl.load_texture_from_image(thread, &tbox).map_err(|err| Error::decode(&tbox_path, err))?,
         };
 
         Self::apply_texture(
             rl,
             thread,
+            resolver,
             &mut assets.metal_crate,
-            "assets/textures/box_Albedo.png",
-            "assets/textures/box_Metallic.png",
-            "assets/textures/box_Normal.png",
-            "assets/textures/box_Roughness.png",
-        )?;
+            [
+                "textures/box_Albedo.png",
+                "textures/box_Metallic.png",
+                "textures/box_Normal.png",
+                "textures/box_Roughness.png",
+                "textures/box_AO.png",
+            ],
+        );
 
         Self::apply_texture(
             rl,
             thread,
+            resolver,
             &mut assets.table,
-            "assets/textures/table/Table_Base_Color.png",
-            "assets/textures/table/Table_Metallic.png",
-            "assets/textures/table/Table_Normal_OpenGL.png",
-            "assets/textures/table/Table_Roughness.png",
-        )?;
+            [
+                "textures/table/Table_Base_Color.png",
+                "textures/table/Table_Metallic.png",
+                "textures/table/Table_Normal_OpenGL.png",
+                "textures/table/Table_Roughness.png",
+                "textures/table/Table_Mixed_AO.png",
+            ],
+        );
 
-        let framebuffer = rl.load_render_texture(&thread, WIDTH as u32, HEIGHT as u32)?;
-        let shapes = TShape::load("assets/shapes.tshape")?;
+        let (width, height) = Layout::new(rl).pixel_size();
+        let framebuffer = rl.load_render_texture(thread, width, height).map_err(Error::Device)?;
+
+        error::recover(assets.postfx.resize(rl, thread, width, height));
+        let shapes = TShape::load(&resolver.resolve("shapes.tshape")?)?;
 
         Ok(Renderer {
             rl,
@@ -110,26 +1201,34 @@ impl<'a> Renderer<'a> {
                 Vector3::new(0.0, 1.0, 0.0),
                 60.0,
             ),
-            game: Game {
-                lines: vec![vec![false; 5]; 10],
-
-                delta: Instant::now(),
-                shape: shapes.rand_shape(),
-                shapes,
-
-                score: Score {
-                    lines: 0,
-                },
-                debug: false,
-            },
+            camera_controller: CameraController::new(settings.camera),
+            particles: Particles::new(!settings.low_effects),
+            rival_particles: Particles::new(!settings.low_effects),
+            overlay: Overlay::new(),
+            console: Console::new(),
+            game: Game::new(shapes, Rng::from_entropy()),
+            attempt: None,
+            bot: None,
+            tbp: None,
+            demo: false,
+            versus: None,
+            online: None,
+            saving: false,
+            owns_save: false,
+            cheese: None,
+            broadcast: None,
+            viewer: None,
             assets,
             settings,
+            finished: false,
         })
     }
 
-    fn load_texture(rl: &mut RaylibHandle, thread: &RaylibThread, texture: &str) -> Result<raylib::ffi::Texture, Box<dyn std::error::Error>> {
+    fn load_texture(rl: &mut RaylibHandle, thread: &RaylibThread, resolver: &Resolver, name: &str) -> Result<raylib::ffi::Texture> {
+        let path = resolver.resolve(name)?;
+
         let texture = unsafe {
-            let mut t = rl.load_texture(&thread, texture)?;
+            let mut t = rl.load_texture(thread, &path).map_err(|err| Error::decode(&path, err))?;
             t.gen_texture_mipmaps();
             t.unwrap()
         };
@@ -137,45 +1236,58 @@ impl<'a> Renderer<'a> {
         Ok(texture)
     }
 
+    /// `textures` are the albedo, metallic, normal, roughness and ambient occlusion maps,
+    /// missing ones are not fatal, the model just keeps its default material for them.
     fn apply_texture(
         rl: &mut RaylibHandle,
         thread: &RaylibThread,
+        resolver: &Resolver,
         model: &mut Model,
-        albedo: &str,
-        metallic: &str,
-        normal: &str,
-        rough: &str
-    ) -> Result<(), Box<dyn std::error::Error>> {
-
-        let albedo = Self::load_texture(rl, thread, albedo)?;
-        let metallic = Self::load_texture(rl, thread, metallic)?;
-        let normal = Self::load_texture(rl, thread, normal)?;
-        let rough = Self::load_texture(rl, thread, rough)?;
-
-        let material = &mut model.materials_mut()[0];
-        let maps = material.maps_mut();
-
-        maps[MaterialMapIndex::MATERIAL_MAP_ALBEDO as usize].texture = albedo;
-        maps[MaterialMapIndex::MATERIAL_MAP_METALNESS as usize].texture = metallic;
-        maps[MaterialMapIndex::MATERIAL_MAP_NORMAL as usize].texture = normal;
-        maps[MaterialMapIndex::MATERIAL_MAP_ROUGHNESS as usize].texture = rough;
+        textures: [&str; 5],
+    ) {
+        let [albedo, metallic, normal, rough, occlusion] = textures;
+
+        let maps = [
+            (MaterialMapIndex::MATERIAL_MAP_ALBEDO, albedo),
+            (MaterialMapIndex::MATERIAL_MAP_METALNESS, metallic),
+            (MaterialMapIndex::MATERIAL_MAP_NORMAL, normal),
+            (MaterialMapIndex::MATERIAL_MAP_ROUGHNESS, rough),
+            (MaterialMapIndex::MATERIAL_MAP_OCCLUSION, occlusion),
+        ];
 
-        Ok(())
+        for (index, name) in maps {
+            if let Some(texture) = error::recover(Self::load_texture(rl, thread, resolver, name)) {
+                model.materials_mut()[0].maps_mut()[index as usize].texture = texture;
+            }
+        }
     }
 
-    fn draw(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    fn draw(&mut self) {
         let fps = self.rl.get_fps();
-        let mut drawer = self.rl.begin_drawing(&self.thread);
+        let layout = Layout::new(self.rl);
+        let crates = self.crate_positions();
+        let debris: Vec<Vector3> = self.particles.bodies().iter().chain(self.rival_particles.bodies()).map(|body| body.position).collect();
+        let hint = self.hint();
+        let offset = self.board_offset(0);
+        let mut drawer = self.rl.begin_drawing(self.thread);
 
         drawer.clear_background(Color::BLACK);
 
         if self.settings.mode3d {
-            let mut texture_drawer = drawer.begin_texture_mode(&self.thread, &mut self.framebuffer);
-            texture_drawer.clear_background(Color::from_hex("0B0D13")?);
+            if let Some(lighting) = &mut self.assets.lighting {
+                lighting.render_shadows(&mut drawer, self.thread, &mut self.assets.metal_crate, &[crates.as_slice(), &debris].concat(), CRATE_SCALE, BOARD_CENTER);
+            }
+
+            let mut texture_drawer = drawer.begin_texture_mode(self.thread, &mut self.framebuffer);
+            texture_drawer.clear_background(BACKGROUND);
 
             // render 3d
             {
-                let mut render3d = texture_drawer.begin_mode3D(&self.camera);
+                let mut render3d = texture_drawer.begin_mode3D(self.camera);
+
+                if let Some(lighting) = &mut self.assets.lighting {
+                    lighting.bind(&mut self.assets.table, &self.camera);
+                }
 
                 // table
                 render3d.draw_model_ex(
@@ -199,322 +1311,813 @@ impl<'a> Renderer<'a> {
                     Color::WHITE,
                 );
 
-                for (y, line) in self.game.lines.iter().enumerate() {
-                    for (x, block) in line.iter().enumerate() {
-                        let mut position = Vector3::new(
-                            0.0,
-                            (y as f32 * 5.5) + 5.0,
-                            (x as f32 * 5.5) - (22.0 / 2.0)
-                        );
+                if let Some(lighting) = &mut self.assets.lighting {
+                    lighting.bind(&mut self.assets.metal_crate, &self.camera);
+                }
 
-                        if *block {
-                            render3d.draw_model(&self.assets.metal_crate, position, 16.0, Color::WHITE);
-                        } else if self.game.shape.contains(&Position { x, y }) {
-                            if self.settings.smooth {
-                                position.y -= 11.0 * self.game.delta.elapsed().as_secs_f32();
-                            }
+                for position in &crates {
+                    render3d.draw_model(&self.assets.metal_crate, *position, CRATE_SCALE, Color::WHITE);
+                }
 
-                            render3d.draw_model(&self.assets.metal_crate, position, 16.0, Color::WHITE);
-                        }
+                for body in self.particles.bodies().iter().chain(self.rival_particles.bodies()) {
+                    render3d.draw_model_ex(
+                        &self.assets.metal_crate,
+                        body.position,
+                        body.axis,
+                        body.angle,
+                        Vector3::new(CRATE_SCALE, CRATE_SCALE, CRATE_SCALE),
+                        Color::WHITE,
+                    );
+                }
+
+                for position in &hint {
+                    render3d.draw_cube_wires(Self::cell_position(position.x as f32, position.y as f32) + offset, 5.0, 5.0, 5.0, Color::LIME);
+                }
 
-                        if self.game.debug {
-                            render3d.draw_cube_wires(
-                                position,
-                                5.0,
-                                5.0,
-                                5.0,
-                                Color::RED,
-                            );
+                if self.game.debug {
+                    for y in 0..ROWS {
+                        for x in 0..COLUMNS {
+                            render3d.draw_cube_wires(Self::cell_position(x as f32, y as f32) + offset, 5.0, 5.0, 5.0, Color::RED);
                         }
                     }
                 }
             }
+
+            if let Some(lighting) = &self.assets.lighting {
+                lighting.unbind(&mut self.assets.table);
+                lighting.unbind(&mut self.assets.metal_crate);
+            }
         } else {
-            // render 2d
-            for (y, line) in self.game.lines.iter().enumerate() {
-                for (x, block) in line.iter().enumerate() {
-                    let mut position = Vector2::new(
-                        (x as f32 * -90.0) + 530.0,
-                        (y as f32 * -90.0) + 900.0,
-                    );
+            // render 2d, at the framebuffer's resolution so the shaders see it the same way
+            let zoom = self.framebuffer.texture.width as f32 / WIDTH as f32;
+            let mut texture_drawer = drawer.begin_texture_mode(self.thread, &mut self.framebuffer);
+            texture_drawer.clear_background(Color::BLACK);
+
+            let mut render2d = texture_drawer.begin_mode2D(Camera2D {
+                offset: Vector2::zero(),
+                target: Vector2::zero(),
+                rotation: 0.0,
+                zoom,
+            });
+
+            match &self.versus {
+                Some(versus) => {
+                    let (width, height) = (COLUMNS as f32 * VERSUS_CELL, ROWS as f32 * VERSUS_CELL);
+
+                    for (game, origin) in [&self.game, &versus.rival].into_iter().zip(VERSUS_ORIGINS) {
+                        Self::draw_board_2d(&mut render2d, &self.assets.tbox, game, self.settings.smooth, origin, VERSUS_CELL);
+
+                        render2d.draw_rectangle_lines_ex(
+                            Rectangle::new(origin.x + VERSUS_CELL - width, origin.y + VERSUS_CELL - height, width, height),
+                            2,
+                            Color::DARKGRAY,
+                        );
+                    }
+                },
+                None => {
+                    let origin = Vector2::new(530.0, 900.0);
 
-                    if *block {
-                        drawer.draw_texture(&self.assets.tbox, position.x as i32, position.y as i32, Color::WHITE);
-                    } else if self.game.shape.contains(&Position { x, y }) {
-                        if self.settings.smooth {
-                            position.y += 180.0 * self.game.delta.elapsed().as_secs_f32();
-                        }
+                    Self::draw_board_2d(&mut render2d, &self.assets.tbox, &self.game, self.settings.smooth, origin, 90.0);
+
+                    for cell in &hint {
+                        let corner = Vector2::new(origin.x - cell.x as f32 * 90.0, origin.y - cell.y as f32 * 90.0);
 
-                        drawer.draw_texture(&self.assets.tbox, position.x as i32, position.y as i32, Color::WHITE);
+                        render2d.draw_rectangle_lines_ex(Rectangle::new(corner.x, corner.y, 90.0, 90.0), 4, Color::LIME);
                     }
-                }
+                },
             }
         }
 
-        if self.settings.mode3d {
-            // shaders
-            {
-                let mut shader = drawer.begin_shader_mode(&self.assets.shader);
+        // shaders
+        self.assets.postfx.apply(&mut drawer, self.thread, &self.framebuffer, &self.settings.effects);
 
-                shader.draw_texture_rec(
-                    self.framebuffer.texture(),
-                    Rectangle::new(
-                        0.0,
-                        0.0,
-                        self.framebuffer.texture.width as f32,
-                        -self.framebuffer.texture.height as f32,
-                    ),
-                    Vector2::new(0.0, 0.0),
-                    Color::WHITE
-                );
-            }
+        // everything below is laid out on the virtual canvas
+        let mut drawer = drawer.begin_mode2D(layout.camera());
+
+        self.assets.postfx.draw(
+            &mut drawer,
+            &self.framebuffer,
+            &self.settings.effects,
+            Rectangle::new(0.0, 0.0, WIDTH as f32, HEIGHT as f32),
+        );
+
+        match &self.versus {
+            Some(versus) => self.assets.hud.draw_versus(&mut drawer, &self.game, versus),
+            None => self.assets.hud.draw(&mut drawer, &self.game, self.settings.hud, self.settings.finesse),
+        }
+
+        if let Some(attempt) = &self.attempt {
+            let goal = format!("{}  ({} left)", attempt.goal.describe(), attempt.pieces_left());
+
+            drawer.draw_text(&goal, (WIDTH / 2) - (text::measure_text(&goal, 30) / 2), 20, 30, Color::WHITE);
+        }
+
+        if let Some(cheese) = &self.cheese {
+            let text = cheese.describe();
+
+            drawer.draw_text(&text, (WIDTH / 2) - (text::measure_text(&text, 30) / 2), 20, 30, Color::WHITE);
         }
 
-        // Score
-        {
-            let score = format!("{}", self.game.score.lines);
+        if let Some(outcome) = self.attempt.as_ref().and_then(|attempt| attempt.outcome) {
+            let text = if outcome == Outcome::Solved { "Solved" } else { "Failed" };
+            let hint = "R: retry   Enter: back";
+
+            drawer.draw_text(text, (WIDTH / 2) - (text::measure_text(text, 60) / 2), (HEIGHT / 2) - 30, 60, Color::WHITE);
+            drawer.draw_text(hint, (WIDTH / 2) - (text::measure_text(hint, 30) / 2), (HEIGHT / 2) + 40, 30, Color::WHITE);
+        } else if let Some((text, hint)) = self.versus.as_ref().and_then(|versus| Self::versus_result(versus, self.online.as_ref())) {
+            drawer.draw_text(&text, (WIDTH / 2) - (text::measure_text(&text, 60) / 2), (HEIGHT / 2) - 30, 60, Color::WHITE);
+            drawer.draw_text(hint, (WIDTH / 2) - (text::measure_text(hint, 30) / 2), (HEIGHT / 2) + 40, 30, Color::WHITE);
+        } else if let Some(text) = self.cheese.as_ref().and_then(|cheese| cheese.result(&self.game)) {
+            let hint = "R: retry   Enter: back";
+
+            drawer.draw_text(&text, (WIDTH / 2) - (text::measure_text(&text, 60) / 2), (HEIGHT / 2) - 30, 60, Color::WHITE);
+            drawer.draw_text(hint, (WIDTH / 2) - (text::measure_text(hint, 30) / 2), (HEIGHT / 2) + 40, 30, Color::WHITE);
+        } else if let Some(text) = self.viewer.as_ref().and_then(Viewer::status) {
+            let hint = "Enter: back";
+
+            drawer.draw_text(text, (WIDTH / 2) - (text::measure_text(text, 60) / 2), (HEIGHT / 2) - 30, 60, Color::WHITE);
+            drawer.draw_text(hint, (WIDTH / 2) - (text::measure_text(hint, 30) / 2), (HEIGHT / 2) + 40, 30, Color::WHITE);
+        } else if self.game.paused || self.game.over {
+            let text = if self.game.over { "Game Over" } else { "Paused" };
 
             drawer.draw_text(
-                &score,
-                (WIDTH / 2) - (text::measure_text(&score, 30) / 2),
-                20,
-                30,
+                text,
+                (WIDTH / 2) - (text::measure_text(text, 60) / 2),
+                (HEIGHT / 2) - 30,
+                60,
                 Color::WHITE,
             );
         }
 
-        // Debug menu
+        if self.game.over && self.settings.finesse && self.attempt.is_none() && self.versus.is_none() {
+            self.assets.hud.draw_finesse(&mut drawer, &self.game);
+        }
+
+        if let Some(name) = self.bot.as_ref().and_then(Bot::name) {
+            drawer.draw_text(name, 20, HEIGHT - 40, 20, Color::WHITE);
+        }
+
+        if self.demo {
+            let text = "Demo - press any key";
+
+            drawer.draw_text(text, (WIDTH / 2) - (text::measure_text(text, 30) / 2), HEIGHT - 60, 30, Color::WHITE);
+        }
+
+        if self.viewer.is_some() {
+            let text = "Spectating - Enter: back";
+
+            drawer.draw_text(text, (WIDTH / 2) - (text::measure_text(text, 30) / 2), HEIGHT - 60, 30, Color::WHITE);
+        }
+
         if self.game.debug {
-            let labels: Vec<String> = vec![
-                format!("FPS: {}\0", fps),
-            ];
+            self.overlay.draw(&mut drawer, &self.game, fps);
+        }
+
+        self.console.draw(&mut drawer);
+    }
 
-            drawer.gui_set_style(GuiControl::DEFAULT, 16, 16);
+    /// What the end of a round says and what Enter does next, None while it's being played.
+    /// Online rounds follow each other on their own, both machines have to deal them together.
+    fn versus_result(versus: &Versus, online: Option<&Session>) -> Option<(String, &'static str)> {
+        let ended = online.and_then(|session| session.ended.as_deref()).filter(|_| versus.winner().is_none());
 
-            drawer.gui_group_box(
-                Rectangle::new(10.0, 10.0, 150.0, (labels.len() as f32 * 15.0) + 5.0),
-                Some(CStr::from_bytes_with_nul(b"Debug Menu\0")?)
-            );
+        if let Some(reason) = ended {
+            return Some((reason.to_string(), "Enter: back"));
+        }
+
+        let text = match (versus.round?, versus.winner()) {
+            (_, Some(side)) => format!("{} wins", versus.name(side)),
+            (Round::Won(side), None) => format!("Round to {}", versus.name(side)),
+            (Round::Draw, None) => String::from("Draw"),
+        };
 
-            for (index, label) in labels.iter().enumerate() {
-                drawer.gui_set_style(GuiControl::DEFAULT, 16, 15);
+        let hint = match (versus.winner(), online) {
+            (Some(_), _) => "Enter: back",
+            (None, Some(_)) => "Next round coming up",
+            (None, None) => "Enter: next round",
+        };
 
-                drawer.gui_label(
-                    Rectangle::new(10.0, (index as f32 * 15.0) + 15.0, 100.0, 15.0),
-                    Some(CStr::from_bytes_with_nul(label.as_bytes())?)
-                );
+        Some((text, hint))
+    }
+
+    /// Draws a stack and its falling piece with cell (0, 0) at `origin`, mirrored like the 3D board.
+    fn draw_board_2d(drawer: &mut impl RaylibDraw, tbox: &Texture2D, game: &Game, smooth: bool, origin: Vector2, cell: f32) {
+        let scale = cell / tbox.width as f32;
+        let position = |x: f32, y: f32| Vector2::new(origin.x - x * cell, origin.y - y * cell);
+
+        for (y, line) in game.lines.iter().enumerate() {
+            for (x, block) in line.iter().enumerate() {
+                if *block {
+                    drawer.draw_texture_ex(tbox, position(x as f32, y as f32), 0.0, scale, Color::WHITE);
+                }
             }
         }
 
-        Ok(())
+        for (x, y) in game.piece(smooth) {
+            drawer.draw_texture_ex(tbox, position(x, y), 0.0, scale, Color::WHITE);
+        }
     }
 
-    fn get_corner_position(&mut self, direction: Direction) -> Position {
-        let mut corner = self.game.shape[0];
+    /// Where the bot would put the piece, only while a human is playing with hints on.
+    fn hint(&self) -> Vec<Position> {
+        if !self.settings.bot.hints || self.bot.is_some() || self.versus.is_some() || self.game.over || self.game.paused {
+            return Vec::new();
+        }
 
-        for position in &self.game.shape {
-            match direction {
-                Direction::Right => {
-                    if position.x < corner.x {
-                        corner = *position;
-                    }
-                },
-                Direction::Left => {
-                    if position.x > corner.x {
-                        corner = *position;
-                    }
-                },
+        bot::suggest(&self.game).map(|placement| placement.cells).unwrap_or_default()
+    }
+
+    /// Takes fractional cells so the falling piece can be drawn in between them.
+    fn cell_position(x: f32, y: f32) -> Vector3 {
+        Vector3::new(
+            0.0,
+            (y * 5.5) + 5.0,
+            (x * 5.5) - (22.0 / 2.0)
+        )
+    }
+
+    /// Where a board's stack sits on the table, only versus moves them apart.
+    fn board_offset(&self, side: usize) -> Vector3 {
+        match (&self.versus, side) {
+            (None, _) => Vector3::zero(),
+            (Some(_), 0) => Vector3::new(0.0, 0.0, VERSUS_SPREAD),
+            (Some(_), _) => Vector3::new(0.0, 0.0, -VERSUS_SPREAD),
+        }
+    }
+
+    /// Where to draw a crate for every locked block and the falling piece of one board.
+    fn side_positions(&self, side: usize) -> Vec<Vector3> {
+        let (game, particles) = match &self.versus {
+            Some(versus) if side == 1 => (&versus.rival, &self.rival_particles),
+            _ => (&self.game, &self.particles),
+        };
+
+        let mut positions = Vec::new();
+
+        // the stack is debris now
+        if particles.collapsed {
+            return positions;
+        }
+
+        let offset = self.board_offset(side);
+
+        for (y, line) in game.lines.iter().enumerate() {
+            for (x, block) in line.iter().enumerate() {
+                if *block {
+                    positions.push(Self::cell_position(x as f32, y as f32) + offset);
+                }
             }
         }
 
-        corner
+        for (x, y) in game.piece(self.settings.smooth) {
+            positions.push(Self::cell_position(x, y) + offset);
+        }
+
+        positions
     }
 
-    fn shape_is_next_to_block(&mut self, direction: Direction) -> bool {
-        for position in &self.game.shape {
-            match direction {
-                Direction::Right => {
-                    if position.x > 0 {
-                        if self.game.lines[position.y][position.x - 1] {
-                            return true;
-                        }
-                    }
-                },
-                Direction::Left => {
-                    if position.x < 4 {
-                        if self.game.lines[position.y][position.x + 1] {
-                            return true;
-                        }
-                    }
-                },
-            }
+    fn crate_positions(&self) -> Vec<Vector3> {
+        let mut positions = self.side_positions(0);
+
+        if self.versus.is_some() {
+            positions.extend(self.side_positions(1));
         }
 
-        false
+        positions
     }
 
-    fn get_top_position(&mut self) -> Position {
-        let mut top = self.game.shape[0];
+    fn action(key: KeyboardKey) -> Option<Action> {
+        match key {
+            KeyboardKey::KEY_RIGHT => Some(Action::Shift(Direction::Right)),
+            KeyboardKey::KEY_LEFT => Some(Action::Shift(Direction::Left)),
+            KeyboardKey::KEY_UP => Some(Action::Rotate),
+            KeyboardKey::KEY_DOWN => Some(Action::SoftDrop),
+            KeyboardKey::KEY_SPACE => Some(Action::HardDrop),
+            KeyboardKey::KEY_C => Some(Action::Hold),
+            _ => None,
+        }
+    }
 
-        self.game.shape
-            .iter()
-            .for_each(|pos| if top.y > pos.y { top = *pos });
+    /// Left player on WASD, the right one on the arrows, with a hard drop and hold key each.
+    fn versus_action(key: KeyboardKey) -> Option<(usize, Action)> {
+        match key {
+            KeyboardKey::KEY_D => Some((0, Action::Shift(Direction::Right))),
+            KeyboardKey::KEY_A => Some((0, Action::Shift(Direction::Left))),
+            KeyboardKey::KEY_W => Some((0, Action::Rotate)),
+            KeyboardKey::KEY_S => Some((0, Action::SoftDrop)),
+            KeyboardKey::KEY_SPACE => Some((0, Action::HardDrop)),
+            KeyboardKey::KEY_Q => Some((0, Action::Hold)),
+            KeyboardKey::KEY_RIGHT => Some((1, Action::Shift(Direction::Right))),
+            KeyboardKey::KEY_LEFT => Some((1, Action::Shift(Direction::Left))),
+            KeyboardKey::KEY_UP => Some((1, Action::Rotate)),
+            KeyboardKey::KEY_DOWN => Some((1, Action::SoftDrop)),
+            KeyboardKey::KEY_ENTER => Some((1, Action::HardDrop)),
+            KeyboardKey::KEY_RIGHT_SHIFT => Some((1, Action::Hold)),
+            _ => None,
+        }
+    }
 
-        top
+    fn pad_action(rl: &RaylibHandle, pad: i32) -> Option<Action> {
+        let buttons = [
+            (GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_RIGHT, Action::Shift(Direction::Right)),
+            (GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_LEFT, Action::Shift(Direction::Left)),
+            (GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_DOWN, Action::Rotate),
+            (GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_DOWN, Action::SoftDrop),
+            (GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_UP, Action::HardDrop),
+            (GamepadButton::GAMEPAD_BUTTON_LEFT_TRIGGER_1, Action::Hold),
+        ];
+
+        if !rl.is_gamepad_available(pad) {
+            return None;
+        }
+
+        buttons.into_iter().find(|(button, _)| rl.is_gamepad_button_pressed(pad, *button)).map(|(_, action)| action)
     }
 
-    fn rotate_shape(&mut self) {
-        let top = self.get_top_position();
-        let left_corner = self.get_corner_position(Direction::Right);
+    /// Both players share the keyboard so every key pressed this frame counts, gamepad n plays
+    /// side n. Against the bot or online either key set moves our board.
+    fn versus_input(&mut self) {
+        let Some(versus) = &mut self.versus else {
+            return;
+        };
+
+        let over = versus.winner().is_some() || self.online.as_ref().is_some_and(|session| session.ended.is_some());
+        let mut actions = Vec::new();
+
+        while let Some(key) = self.rl.get_key_pressed() {
+            match key {
+                KeyboardKey::KEY_M => {
+                    self.settings.mixer.muted = !self.settings.mixer.muted;
+                },
+                KeyboardKey::KEY_F11 => {
+                    self.settings.fullscreen = !self.settings.fullscreen;
+
+                    layout::set_fullscreen(self.rl, self.settings.fullscreen);
+                },
+                KeyboardKey::KEY_V => {
+                    self.settings.camera = self.settings.camera.next();
+                    self.camera_controller.mode = self.settings.camera;
+                },
+                // the other machine can't be paused
+                KeyboardKey::KEY_P if versus.round.is_none() && self.online.is_none() => {
+                    self.game.paused = !self.game.paused;
+                    versus.rival.paused = self.game.paused;
+                },
+                KeyboardKey::KEY_ENTER if over => {
+                    self.finished = true;
+                },
+                KeyboardKey::KEY_ENTER if versus.round.is_some() && self.online.is_none() => {
+                    versus.next_round(&mut self.game);
 
-        let mut translated: [[bool; 2]; 2] = [[false; 2]; 2];
-        {
-            for position in &self.game.shape {
-                translated[position.y - top.y][position.x - left_corner.x] = true;
+                    self.particles = Particles::new(self.particles.enabled);
+                    self.rival_particles = Particles::new(self.rival_particles.enabled);
+                },
+                key => actions.extend(Self::versus_action(key)),
             }
         }
 
-        translated = [
-            [translated[1][0], translated[0][0]],
-            [translated[1][1], translated[0][1]]
-        ];
+        actions.extend((0..2).filter_map(|side| Self::pad_action(self.rl, side as i32).map(|action| (side, action))));
 
-        self.game.shape = Vec::new();
-        {
-            for (y, row) in translated.iter().enumerate() {
-                for (x, position) in row.iter().enumerate() {
-                    if *position {
-                        self.game.shape.push(Position { x: x + left_corner.x, y: y + top.y});
-                    }
-                }
+        if self.game.paused || versus.round.is_some() {
+            return;
+        }
+
+        for (side, action) in actions {
+            match (&mut self.online, side) {
+                (Some(session), _) => session.queue(action),
+                (None, 1) if versus.bot.is_none() => versus.rival.apply(action),
+                (None, _) => self.game.apply(action),
             }
         }
     }
 
     fn handle_input(&mut self) {
+        if self.demo {
+            if self.rl.get_key_pressed().is_some() || self.rl.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON) || self.game.over {
+                self.finished = true;
+            }
+
+            return;
+        }
+
+        if self.viewer.is_some() {
+            self.spectator_input();
+            return;
+        }
+
+        if self.console.open {
+            self.console.handle_input(self.rl, &mut self.game);
+            return;
+        }
+
+        if self.versus.is_some() {
+            self.versus_input();
+            return;
+        }
+
         if let Some(key) = self.rl.get_key_pressed() {
             match key {
+                KeyboardKey::KEY_GRAVE => {
+                    self.console.toggle();
+                },
                 KeyboardKey::KEY_D => {
                     self.game.debug = !self.game.debug;
                 },
-                KeyboardKey::KEY_RIGHT => {
-                    if self.get_corner_position(Direction::Right).x != 0 && !self.shape_is_next_to_block(Direction::Right) {
-                        for position in &mut self.game.shape {
-                            position.x -= 1;
-                        }
-                    }
+                KeyboardKey::KEY_M => {
+                    self.settings.mixer.muted = !self.settings.mixer.muted;
                 },
-                KeyboardKey::KEY_LEFT => {
-                    if self.get_corner_position(Direction::Left).x != 4 && !self.shape_is_next_to_block(Direction::Left) {
-                        for position in &mut self.game.shape {
-                            position.x += 1;
-                        }
+                KeyboardKey::KEY_F11 => {
+                    self.settings.fullscreen = !self.settings.fullscreen;
+
+                    layout::set_fullscreen(self.rl, self.settings.fullscreen);
+                },
+                KeyboardKey::KEY_V => {
+                    self.settings.camera = self.settings.camera.next();
+                    self.camera_controller.mode = self.settings.camera;
+                },
+                KeyboardKey::KEY_H => {
+                    self.settings.hud = self.settings.hud.next();
+                },
+                KeyboardKey::KEY_F => {
+                    self.settings.finesse = !self.settings.finesse;
+                },
+                KeyboardKey::KEY_G => {
+                    self.settings.bot.hints = !self.settings.bot.hints;
+                },
+                KeyboardKey::KEY_B => {
+                    self.bot = match self.bot {
+                        Some(_) => None,
+                        None => self.new_bot(),
+                    };
+                },
+                KeyboardKey::KEY_F5 => {
+                    self.assets.postfx.reload(self.rl, self.thread, true);
+                },
+                KeyboardKey::KEY_P => {
+                    self.game.paused = !self.game.paused;
+
+                    if self.game.paused {
+                        self.save();
                     }
                 },
-                KeyboardKey::KEY_UP => {
-                    self.rotate_shape();
+                KeyboardKey::KEY_R if self.attempt.is_some() || self.cheese.is_some() => {
+                    self.retry();
                 },
-                KeyboardKey::KEY_DOWN => {
-                    if !self.is_collision() {
-                        self.move_down();
+                KeyboardKey::KEY_ENTER if self.game.over || self.is_decided() => {
+                    self.finished = true;
+                },
+                _ if self.game.paused || self.game.over || self.is_decided() || self.bot.is_some() => {},
+                key => {
+                    if let Some(action) = Self::action(key) {
+                        self.game.apply(action);
                     }
                 },
+            }
+        }
+    }
+
+    /// Only the view can be changed, the game belongs to whoever is streaming it.
+    fn spectator_input(&mut self) {
+        while let Some(key) = self.rl.get_key_pressed() {
+            match key {
+                KeyboardKey::KEY_ENTER => {
+                    self.finished = true;
+                },
+                KeyboardKey::KEY_M => {
+                    self.settings.mixer.muted = !self.settings.mixer.muted;
+                },
+                KeyboardKey::KEY_F11 => {
+                    self.settings.fullscreen = !self.settings.fullscreen;
+
+                    layout::set_fullscreen(self.rl, self.settings.fullscreen);
+                },
+                KeyboardKey::KEY_V => {
+                    self.settings.camera = self.settings.camera.next();
+                    self.camera_controller.mode = self.settings.camera;
+                },
+                KeyboardKey::KEY_H => {
+                    self.settings.hud = self.settings.hud.next();
+                },
+                KeyboardKey::KEY_F => {
+                    self.settings.finesse = !self.settings.finesse;
+                },
+                KeyboardKey::KEY_G => {
+                    self.settings.bot.hints = !self.settings.bot.hints;
+                },
                 _ => {},
             }
         }
     }
 
-    fn lock_size(&mut self) {
-        if self.rl.is_window_resized() {
-            self.rl.set_window_size(WIDTH, HEIGHT);
+    pub fn start_puzzle(&mut self, puzzle: &Puzzle) {
+        self.attempt = Some(Attempt::new(puzzle));
+        self.retry();
+    }
+
+    /// None if the external bot failed to start, that's logged.
+    fn new_bot(&self) -> Option<Bot> {
+        match &self.tbp {
+            Some(command) => error::recover(Client::spawn(command)).map(|client| Bot::external(self.settings.bot.speed, client)),
+            None => Some(Bot::new(self.settings.bot.speed)),
         }
     }
 
-    fn is_collision(&mut self) -> bool {
-        for position in &self.game.shape {
-            if position.y == 0 {
-                return true;
-            }
+    /// Hands the game to an external bot, B switches it off and on again.
+    pub fn start_external(&mut self, command: &str) {
+        self.tbp = Some(command.to_string());
+        self.bot = self.new_bot();
+    }
 
-            if self.game.lines[position.y - 1][position.x] || self.game.lines[position.y][position.x] {
-                return true;
-            }
+    /// Puts a rival next to the game, the versus settings pick who plays it and for how many rounds.
+    pub fn start_versus(&mut self, settings: VersusSettings) {
+        self.versus = Some(Versus::new(&mut self.game, settings, self.settings.bot.speed));
+        self.camera_controller.zoom = VERSUS_ZOOM;
+    }
+
+    /// A versus match over the network, dealt from the server's start message.
+    pub fn start_online(&mut self, start: &Start, session: Session) {
+        self.versus = Some(Versus::seeded(&mut self.game, start.rounds, start.seed, start.names.clone()));
+        self.online = Some(session);
+        self.camera_controller.zoom = VERSUS_ZOOM;
+    }
+
+    /// Plays on from a saved game, it waits paused until the player is ready.
+    pub fn resume(&mut self, game: Game) {
+        self.game = game;
+        self.game.paused = true;
+        self.saving = true;
+        self.owns_save = true;
+    }
+
+    /// A game that's over has nothing left to continue, a save left by an earlier game isn't touched.
+    fn save(&mut self) {
+        if !self.saving || (!self.owns_save && save::exists()) {
+            return;
         }
 
-        false
+        if self.game.over {
+            error::recover(save::remove());
+        } else if error::recover(save::store(&self.game)).is_some() {
+            self.owns_save = true;
+        }
     }
 
-    fn update_position(&mut self) {
-        if self.game.delta.elapsed() >= Duration::from_secs_f64(0.5) {
-            self.move_down();
+    /// A fresh game over cheese rows, for survival or a dig race.
+    pub fn start_cheese(&mut self, mode: Mode) {
+        self.game = Game::new(self.game.shapes().clone(), Rng::from_entropy());
+        self.cheese = Some(Cheese::new(mode, &mut self.game));
+    }
 
-            if self.is_collision() {
-                self.audio.play_sound(&self.assets.thump);
+    /// Draws the game streamed to `viewer` instead of playing one.
+    pub fn start_spectating(&mut self, viewer: Viewer) {
+        self.viewer = Some(viewer);
+    }
 
-                for position in &self.game.shape {
-                    self.game.lines[position.y][position.x] = true;
-                }
+    /// The bot plays on its own until a key is pressed.
+    pub fn start_demo(&mut self) {
+        self.bot = Some(Bot::new(self.settings.bot.speed));
+        self.demo = true;
+    }
 
-                self.game.shape = self.game.shapes.rand_shape();
-            }
+    fn retry(&mut self) {
+        if let Some(attempt) = &mut self.attempt {
+            attempt.retry();
+
+            self.game.restart(&attempt.board);
+            self.particles = Particles::new(self.particles.enabled);
+        }
 
-            self.game.delta = Instant::now();
+        if let Some(mode) = self.cheese.as_ref().map(|cheese| cheese.mode) {
+            self.start_cheese(mode);
+            self.particles = Particles::new(self.particles.enabled);
         }
     }
 
-    fn move_down(&mut self) {
-        for index in 0..self.game.shape.len() {
-            if self.game.shape[index].y > 0 {
-                self.game.shape[index].y -= 1;
-            }
+    /// A puzzle was solved or failed, a versus round ended or a race was dug out, the game waits
+    /// for the player then.
+    fn is_decided(&self) -> bool {
+        self.attempt.as_ref().is_some_and(|attempt| attempt.outcome.is_some())
+            || self.versus.as_ref().is_some_and(|versus| versus.round.is_some())
+            || self.cheese.as_ref().is_some_and(Cheese::won)
+    }
+
+    /// Keeps the framebuffer at the resolution the canvas is shown at.
+    fn resize(&mut self) {
+        let (width, height) = Layout::new(self.rl).pixel_size();
+
+        if self.framebuffer.texture.width == width as i32 && self.framebuffer.texture.height == height as i32 {
+            return;
+        }
+
+        if let Some(framebuffer) = error::recover(self.rl.load_render_texture(self.thread, width, height).map_err(Error::Device)) {
+            self.framebuffer = framebuffer;
         }
+
+        error::recover(self.assets.postfx.resize(self.rl, self.thread, width, height));
     }
 
-    fn is_valid_point(&self, line: &Vec<bool>) -> bool {
-        for block in line {
-            if !block {
-                return false;
+    /// Moves the games on by a frame and returns what happened on each board.
+    fn simulate(&mut self) -> [Vec<Event>; 2] {
+        if let Some(viewer) = &mut self.viewer {
+            return [viewer.update(&mut self.game), Vec::new()];
+        }
+
+        if let (Some(session), Some(versus)) = (&mut self.online, &mut self.versus) {
+            let decided = versus.round.is_some();
+            let events = session.advance(&mut self.game, versus, self.rl.get_frame_time());
+
+            // the next round was dealt, the old stacks' debris goes with them
+            if decided && versus.round.is_none() {
+                self.particles = Particles::new(self.particles.enabled);
+                self.rival_particles = Particles::new(self.rival_particles.enabled);
             }
+
+            return events;
         }
 
-        true
-    }
+        // the console holds the game still while commands are typed
+        if !self.console.open && !self.is_decided() {
+            if let Some(bot) = &mut self.bot {
+                if !self.game.paused {
+                    bot.update(&mut self.game, self.rl.get_frame_time());
+                }
+            }
 
-    fn update_lines(&mut self) {
-        let mut index = 0;
+            self.game.update(self.rl.get_frame_time());
 
-        for line in self.game.lines.clone() {
-            if self.is_valid_point(&line) {
-                self.game.lines.remove(index);
-                self.game.lines.push(vec![false; 5]);
+            if let Some(versus) = &mut self.versus {
+                if let Some(bot) = &mut versus.bot {
+                    if !versus.rival.paused {
+                        bot.update(&mut versus.rival, self.rl.get_frame_time());
+                    }
+                }
 
-                self.game.score.lines += 1;
+                versus.rival.update(self.rl.get_frame_time());
             }
+        }
+
+        let events = [self.game.take_events(), self.versus.as_mut().map(|versus| versus.rival.take_events()).unwrap_or_default()];
 
-            index += 1;
+        if let Some(versus) = &mut self.versus {
+            versus.update(&mut self.game, [&events[0], &events[1]]);
         }
+
+        if let Some(cheese) = &mut self.cheese {
+            if !self.console.open && !self.game.paused {
+                cheese.update(&mut self.game, &events[0], self.rl.get_frame_time());
+            }
+        }
+
+        events
     }
 
-    fn play_theme(&mut self) {
-        if !self.audio.is_sound_playing(&self.assets.theme) {
-            self.audio.play_sound(&self.assets.theme);
+    fn play_events(&mut self, [events, rival_events]: [Vec<Event>; 2]) {
+        if let Some(attempt) = &mut self.attempt {
+            attempt.update(&events, &self.game);
+        }
+
+        let sides = events.into_iter().map(|event| (0, event)).chain(rival_events.into_iter().map(|event| (1, event)));
+
+        for (side, event) in sides {
+            if matches!(event, Event::Fault(_)) && !self.settings.finesse {
+                continue;
+            }
+
+            self.audio.sfx.play(self.audio.backend.as_mut(), &self.settings.mixer, event);
+            self.camera_controller.handle(event);
+
+            if side == 0 {
+                self.audio.music.handle(event);
+            }
+
+            match event {
+                Event::ClearedRows(rows) => {
+                    let offset = self.board_offset(side);
+                    let positions: Vec<Vector3> = (0..ROWS)
+                        .filter(|row| rows & 1 << row != 0)
+                        .flat_map(|y| (0..COLUMNS).map(move |x| Self::cell_position(x as f32, y as f32) + offset))
+                        .collect();
+
+                    self.particles_mut(side).burst(&positions);
+                },
+                Event::GameOver => {
+                    let positions = self.side_positions(side);
+
+                    self.particles_mut(side).collapse(&positions);
+                },
+                _ => {},
+            }
         }
     }
 
-    pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.game.delta = Instant::now();
+    fn particles_mut(&mut self, side: usize) -> &mut Particles {
+        if side == 0 { &mut self.particles } else { &mut self.rival_particles }
+    }
+
+    fn update_camera(&mut self) {
+        let heights: Vec<f32> = self.game
+            .piece(self.settings.smooth)
+            .iter()
+            .map(|(x, y)| Self::cell_position(*x, *y).y)
+            .collect();
+
+        let height = match heights.len() {
+            0 => BOARD_CENTER.y,
+            count => heights.iter().sum::<f32>() / count as f32,
+        };
+
+        self.camera = self.camera_controller.update(self.rl, self.rl.get_frame_time(), height);
+    }
+
+    fn play_theme(&mut self) {
+        self.audio.backend.set_master_volume(self.settings.mixer.master_volume());
+
+        let danger = self.game.stack_height() >= DANGER_HEIGHT && !self.game.over;
+
+        self.audio.music.update(self.audio.backend.as_mut(), &self.settings.mixer, self.game.paused, danger, self.rl.get_frame_time());
+    }
 
-        while !self.rl.window_should_close() {
-            self.draw()?;
-            self.lock_size();
+    pub fn run(&mut self) -> Result<()> {
+        while !self.rl.window_should_close() && !self.finished {
+            self.resize();
+            self.assets.postfx.reload(self.rl, self.thread, false);
+            self.update_camera();
+            self.draw();
             self.play_theme();
             self.handle_input();
-            self.update_position();
-            self.update_lines();
+            self.overlay.record(self.rl.get_frame_time());
+
+            let events = self.simulate();
+
+            if let Some(broadcast) = &mut self.broadcast {
+                broadcast.publish(&self.game, &events[0]);
+            }
+
+            if !self.game.paused {
+                self.particles.update(self.rl.get_frame_time());
+                self.rival_particles.update(self.rl.get_frame_time());
+            }
+            self.play_events(events);
         }
 
+        self.save();
+
+        // the music outlives the game, the next one starts it from the top
+        self.audio.music.stop(self.audio.backend.as_mut());
+
         Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lowest(game: &Game) -> usize {
+        game.shape().iter().map(|position| position.y).min().unwrap()
+    }
+
+    #[test]
+    fn garbage_pushes_the_piece_up() {
+        let mut game = Game::new(TShape::fixture(), Rng::new(1));
+
+        game.soft_drop();
+        game.soft_drop();
+
+        let low = lowest(&game);
+
+        // every fixture shape keeps clear of the hole in the first column
+        game.insert_garbage(low as u32 + 1, 0);
+
+        assert!(!game.over);
+        assert_eq!(lowest(&game), low + 1);
+        assert_eq!(game.stack_height(), low + 1);
+    }
+
+    #[test]
+    fn garbage_with_no_room_for_the_piece_ends_the_game() {
+        let mut game = Game::new(TShape::fixture(), Rng::new(1));
+
+        game.insert_garbage(ROWS as u32, 0);
 
+        assert!(game.over);
+        assert_eq!(game.take_events(), [Event::Garbage(ROWS as u32), Event::GameOver]);
+    }
+
+    #[test]
+    fn garbage_pushing_blocks_off_the_top_ends_the_game() {
+        let mut game = Game::new(TShape::fixture(), Rng::new(1));
+
+        game.insert_garbage(2, 0);
+        game.take_events();
+        game.insert_garbage(ROWS as u32 - 1, 0);
+
+        assert!(game.over);
+        assert_eq!(game.take_events(), [Event::Garbage(ROWS as u32 - 1), Event::GameOver]);
+
+        // nothing more rises once it's over
+        game.insert_garbage(1, 0);
+
+        assert!(game.take_events().is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+use crate::board::Board;
+use crate::bot::{Bot, Speed};
+use crate::error::{Error, Result};
+use crate::game::Game;
+use crate::json::Value;
+use crate::net::{self, Connection, Session, Start};
+use crate::replay::{self, Recording};
+use crate::rng::Rng;
+use crate::tbp::Client;
+use crate::versus::Versus;
+use crate::TShape;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Every update moves the game on by this much, as if it ran at 60 frames per second.
+const DELTA: f32 = 1.0 / 60.0;
+
+/// How often to look for the answer of an external bot that is thinking.
+const POLL: Duration = Duration::from_millis(1);
+
+/// Games run without a window as fast as the bot can play them, the results are printed as JSON.
+pub struct Config {
+    pub games: u32,
+    /// Game n is dealt from `seed + n`, random when not given.
+    pub seed: Option<u64>,
+    /// The ruleset is the shapes file and the level the games start at.
+    pub shapes: Option<String>,
+    pub level: u32,
+    /// Stack and queue every game starts from.
+    pub board: Option<String>,
+    /// Plays the recorded games again instead of running the bot, at the level and from the board
+    /// they were recorded with.
+    pub replay: Option<String>,
+    /// Where to write what was played, to replay it later.
+    pub record: Option<String>,
+    /// A game that gets this far is stopped, a good bot would go on forever. Replays run to the end.
+    pub max_pieces: u32,
+    /// Plays a versus match on this server instead, in `lobby`.
+    pub connect: Option<String>,
+    pub lobby: String,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            games: 1,
+            seed: None,
+            shapes: None,
+            level: 1,
+            board: None,
+            replay: None,
+            record: None,
+            max_pieces: 1000,
+            connect: None,
+            lobby: String::from("tetris"),
+        }
+    }
+}
+
+enum Player<'a> {
+    Bot(&'a mut Bot),
+    Replay(&'a Recording),
+}
+
+/// How a game went.
+struct Outcome {
+    seed: u64,
+    points: u32,
+    lines: u32,
+    pieces: u32,
+    /// Game time in seconds.
+    time: f32,
+    over: bool,
+}
+
+impl Outcome {
+    fn encode(&self) -> Value {
+        Value::object([
+            // seeds don't fit in a JSON number
+            ("seed", Value::String(self.seed.to_string())),
+            ("score", self.points.into()),
+            ("lines", self.lines.into()),
+            ("pieces", self.pieces.into()),
+            ("time", self.time.into()),
+            ("over", self.over.into()),
+        ])
+    }
+}
+
+/// Starts at `level` from `board`, the file it was loaded from goes into the recording.
+fn play(config: &Config, shapes: &TShape, level: u32, board: Option<(&str, &Board)>, seed: u64, mut player: Player) -> (Outcome, Recording) {
+    let mut game = Game::new(shapes.clone(), Rng::new(seed));
+    let mut next = 0;
+
+    let max_pieces = match player {
+        Player::Bot(_) => config.max_pieces,
+        Player::Replay(_) => u32::MAX,
+    };
+
+    game.set_level(level);
+
+    if let Some((_, board)) = board {
+        game.load_board(board);
+    }
+
+    game.start_recording();
+
+    while !game.over && game.stats.pieces < max_pieces {
+        match &mut player {
+            Player::Bot(bot) => {
+                bot.update(&mut game, DELTA);
+
+                // the game waits for the bot, a slow one shouldn't lose pieces to gravity
+                while bot.thinking() && !game.over {
+                    thread::sleep(POLL);
+                    bot.update(&mut game, 0.0);
+                }
+            },
+            Player::Replay(recording) => {
+                while let Some((_, action)) = recording.actions.get(next).filter(|(tick, _)| *tick <= game.ticks()) {
+                    game.apply(*action);
+                    next += 1;
+                }
+
+                // the last tick only had actions, a game over stopped the clock
+                if game.ticks() >= recording.ticks {
+                    break;
+                }
+            },
+        }
+
+        game.update(DELTA);
+        game.take_events();
+    }
+
+    let result = Outcome {
+        seed,
+        points: game.score.points,
+        lines: game.score.lines,
+        pieces: game.stats.pieces,
+        time: game.stats.elapsed,
+        over: game.over,
+    };
+
+    let recording = Recording {
+        seed,
+        ticks: game.ticks(),
+        level,
+        board: board.map(|(file, _)| file.to_string()),
+        actions: game.take_recording(),
+    };
+
+    (result, recording)
+}
+
+/// Lowest, highest, mean, median and standard deviation.
+fn summary(values: impl Iterator<Item = f64>) -> Value {
+    let mut values: Vec<f64> = values.collect();
+
+    if values.is_empty() {
+        return Value::Null;
+    }
+
+    values.sort_by(f64::total_cmp);
+
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / count;
+
+    let middle = values.len() / 2;
+    let median = if values.len().is_multiple_of(2) { (values[middle - 1] + values[middle]) / 2.0 } else { values[middle] };
+
+    Value::object([
+        ("min", Value::Number(values[0])),
+        ("max", Value::Number(values[values.len() - 1])),
+        ("mean", Value::Number(mean)),
+        ("median", Value::Number(median)),
+        ("stddev", Value::Number(variance.sqrt())),
+    ])
+}
+
+/// Joins `lobby` on the server at `address` and plays the match there with the built-in bot.
+/// The final hash of both boards is printed, the other side's should be the same.
+fn run_online(config: &Config, shapes: TShape, address: &str) -> Result<()> {
+    let mut connection = Connection::open(address, "Bot")?;
+
+    connection.send(Value::object([
+        ("type", "join".into()),
+        ("lobby", config.lobby.as_str().into()),
+        ("rounds", Value::Number(0.0)),
+    ]))?;
+
+    let start = loop {
+        match connection.receive()? {
+            Some(message) if net::kind(&message) == Some("start") => break Start::decode(&message)?,
+            Some(message) if net::kind(&message) == Some("error") => return Err(Error::Network(message.to_string())),
+            Some(_) => {},
+            None => thread::sleep(POLL),
+        }
+    };
+
+    let mut player = Game::new(shapes, Rng::new(start.seed));
+    let mut versus = Versus::seeded(&mut player, start.rounds, start.seed, start.names.clone());
+    let mut session = Session::new(connection, &start);
+    let mut bot = Bot::new(Speed::Instant);
+
+    while versus.winner().is_none() && session.ended.is_none() {
+        if versus.round.is_none() && !session.in_flight() {
+            let game = if start.side == 0 { &player } else { &versus.rival };
+
+            for action in bot.plan(game).unwrap_or_default() {
+                session.queue(action);
+            }
+        }
+
+        let tick = session.tick();
+
+        session.advance(&mut player, &mut versus, net::DELTA);
+
+        if session.tick() == tick {
+            thread::sleep(POLL);
+        }
+    }
+
+    let stats = Value::object([
+        ("side", Value::Number(start.side as f64)),
+        ("names", Value::Array(start.names.iter().map(|name| name.as_str().into()).collect())),
+        ("wins", Value::Array(versus.sides.iter().map(|side| side.wins.into()).collect())),
+        ("winner", versus.winner().map_or(Value::Null, |side| Value::Number(side as f64))),
+        ("ended", session.ended.clone().map_or(Value::Null, Value::String)),
+        ("ticks", Value::Number(session.tick() as f64)),
+        ("hash", Value::String(Session::hash([&player, &versus.rival]).to_string())),
+    ]);
+
+    session.into_connection();
+
+    println!("{}", stats);
+
+    Ok(())
+}
+
+/// Plays with the built-in bot, the external one when `tbp` is given, or replays recorded games.
+pub fn run(config: &Config, shapes: TShape, tbp: Option<&str>) -> Result<()> {
+    if let Some(address) = &config.connect {
+        return run_online(config, shapes, address);
+    }
+
+    let recordings = config.replay.as_deref().map(replay::load).transpose()?;
+    let seed = config.seed.unwrap_or_else(rand::random);
+
+    let started = Instant::now();
+    let mut results = Vec::new();
+    let mut played = Vec::new();
+
+    let player = match &recordings {
+        Some(recordings) => {
+            for recording in recordings {
+                let board = recording.board.as_deref().map(|file| Board::load(file, &shapes)).transpose()?;
+                let board = recording.board.as_deref().zip(board.as_ref());
+                let (result, replayed) = play(config, &shapes, recording.level, board, recording.seed, Player::Replay(recording));
+
+                results.push(result);
+                played.push(replayed);
+            }
+
+            String::from("replay")
+        },
+        None => {
+            let mut bot = match tbp {
+                Some(command) => Bot::external(Speed::Instant, Client::spawn(command)?),
+                None => Bot::new(Speed::Instant),
+            };
+
+            let board = config.board.as_deref().map(|file| Board::load(file, &shapes)).transpose()?;
+            let board = config.board.as_deref().zip(board.as_ref());
+
+            for index in 0..config.games {
+                bot.reset();
+
+                let (result, recording) = play(config, &shapes, config.level, board, seed.wrapping_add(index as u64), Player::Bot(&mut bot));
+
+                results.push(result);
+                played.push(recording);
+            }
+
+            bot.name().unwrap_or(if tbp.is_some() { "external" } else { "builtin" }).to_string()
+        },
+    };
+
+    let duration = started.elapsed().as_secs_f64();
+
+    if let Some(file) = &config.record {
+        replay::save(file, &played)?;
+    }
+
+    let stats = Value::object([
+        ("player", Value::String(player)),
+        ("seed", Value::String(seed.to_string())),
+        ("games", Value::Number(results.len() as f64)),
+        ("duration", Value::Number(duration)),
+        ("score", summary(results.iter().map(|result| result.points as f64))),
+        ("lines", summary(results.iter().map(|result| result.lines as f64))),
+        ("pieces", summary(results.iter().map(|result| result.pieces as f64))),
+        ("time", summary(results.iter().map(|result| result.time as f64))),
+        ("results", Value::Array(results.iter().map(Outcome::encode).collect())),
+    ]);
+
+    println!("{}", stats);
+
+    Ok(())
+}
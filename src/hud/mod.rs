@@ -0,0 +1,231 @@
+use crate::assets::Resolver;
+use crate::error::{Error, Result};
+use crate::game::{Game, ROWS};
+use crate::layout::{HEIGHT, WIDTH};
+use crate::versus::Versus;
+
+use raylib::prelude::*;
+
+const LABEL: Color = Color::new(0x9A, 0xA4, 0xB8, 0xFF);
+const VALUE: Color = Color::new(0xFF, 0xFF, 0xFF, 0xFF);
+const BAR: Color = Color::new(0x3A, 0x6E, 0xA5, 0xFF);
+const FAULT: Color = Color::new(0xE0, 0x4A, 0x4A, 0xFF);
+
+const LABEL_SIZE: f32 = 20.0;
+const VALUE_SIZE: f32 = 34.0;
+const SPACING: f32 = 2.0;
+
+/// The board leaves a column free on either side of the canvas.
+const LEFT: f32 = 15.0;
+const RIGHT: f32 = 640.0;
+const COLUMN_WIDTH: f32 = 145.0;
+
+/// Pixel size of a cell in the histogram's shape previews.
+const PREVIEW_CELL: i32 = 7;
+const HISTOGRAM_ROW: f32 = 40.0;
+
+/// Seconds a finesse fault stays flagged.
+const FAULT_TIME: f32 = 1.0;
+
+/// Left edges of the two players' stats in versus, over their boards.
+const VERSUS_COLUMNS: [f32; 2] = [40.0, 440.0];
+const VERSUS_WIDTH: f32 = 320.0;
+
+/// Columns of the finesse breakdown shown after the game.
+const BREAKDOWN: [(&str, f32); 4] = [("PIECE", 0.0), ("PLACED", 120.0), ("FAULTS", 240.0), ("WASTED", 360.0)];
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum HudMode {
+    #[default]
+    Full,
+    /// Score, level and lines only.
+    Compact,
+    Hidden,
+}
+
+impl HudMode {
+    pub const ALL: [HudMode; 3] = [HudMode::Full, HudMode::Compact, HudMode::Hidden];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HudMode::Full => "Full",
+            HudMode::Compact => "Compact",
+            HudMode::Hidden => "Hidden",
+        }
+    }
+
+    pub fn next(&self) -> HudMode {
+        let index = HudMode::ALL.iter().position(|mode| mode == self).unwrap_or(0);
+
+        HudMode::ALL[(index + 1) % HudMode::ALL.len()]
+    }
+
+    pub fn from_index(index: u8) -> Option<HudMode> {
+        HudMode::ALL.get(index as usize).copied()
+    }
+
+    pub fn index(&self) -> u8 {
+        HudMode::ALL.iter().position(|mode| mode == self).unwrap_or(0) as u8
+    }
+}
+
+pub struct Hud {
+    font: Font,
+}
+
+impl Hud {
+    pub fn load(rl: &mut RaylibHandle, thread: &RaylibThread, resolver: &Resolver) -> Result<Hud> {
+        let path = resolver.resolve("ui/InriaSerif-Regular.ttf")?;
+
+        Ok(Hud {
+            font: rl.load_font_ex(thread, &path, 60, FontLoadEx::Default(256)).map_err(|err| Error::decode(&path, err))?,
+        })
+    }
+
+    /// A small label with its value underneath, returns the height used.
+    fn stat(&self, drawer: &mut impl RaylibDraw, x: f32, y: f32, label: &str, value: &str) -> f32 {
+        drawer.draw_text_ex(&self.font, label, Vector2::new(x, y), LABEL_SIZE, SPACING, LABEL);
+        drawer.draw_text_ex(&self.font, value, Vector2::new(x, y + LABEL_SIZE), VALUE_SIZE, SPACING, VALUE);
+
+        LABEL_SIZE + VALUE_SIZE + 20.0
+    }
+
+    fn time(elapsed: f32) -> String {
+        let minutes = (elapsed / 60.0) as u32;
+
+        format!("{}:{:04.1}", minutes, elapsed - minutes as f32 * 60.0)
+    }
+
+    /// Draws in virtual canvas coordinates, on top of either renderer,
+    /// `finesse` adds the fault counter.
+    pub fn draw(&self, drawer: &mut impl RaylibDraw, game: &Game, mode: HudMode, finesse: bool) {
+        if mode == HudMode::Hidden {
+            return;
+        }
+
+        let mut y = 20.0;
+
+        y += self.stat(drawer, LEFT, y, "SCORE", &game.score.points.to_string());
+        y += self.stat(drawer, LEFT, y, "LEVEL", &game.score.level.to_string());
+        y += self.stat(drawer, LEFT, y, "LINES", &game.score.lines.to_string());
+
+        if mode == HudMode::Compact {
+            return;
+        }
+
+        self.stat(drawer, LEFT, y, "TIME", &Self::time(game.stats.elapsed));
+
+        let mut y = 20.0;
+
+        y += self.stat(drawer, RIGHT, y, "PPS", &format!("{:.2}", game.stats.pps()));
+        y += self.stat(drawer, RIGHT, y, "KPP", &format!("{:.2}", game.stats.kpp()));
+
+        if finesse {
+            if game.finesse.since_fault < FAULT_TIME {
+                let fault = format!("+{}", game.finesse.last_wasted);
+
+                drawer.draw_text_ex(&self.font, &fault, Vector2::new(RIGHT + 80.0, y + LABEL_SIZE), VALUE_SIZE, SPACING, FAULT);
+            }
+
+            y += self.stat(drawer, RIGHT, y, "FAULTS", &game.finesse.faults.to_string());
+        }
+
+        self.histogram(drawer, game, y);
+    }
+
+    /// Both players' round wins, score and lines, with a meter for the garbage about to rise.
+    pub fn draw_versus(&self, drawer: &mut impl RaylibDraw, player: &Game, versus: &Versus) {
+        for (index, (game, x)) in [player, &versus.rival].into_iter().zip(VERSUS_COLUMNS).enumerate() {
+            let side = &versus.sides[index];
+            let half = VERSUS_WIDTH / 2.0;
+
+            let y = 20.0 + self.stat(drawer, x, 20.0, &versus.name(index).to_uppercase(), &format!("{} / {}", side.wins, versus.settings.rounds.wins_needed()));
+
+            self.stat(drawer, x + half, 20.0, "INCOMING", &side.pending().to_string());
+            self.stat(drawer, x, y, "SCORE", &game.score.points.to_string());
+            let y = y + self.stat(drawer, x + half, y, "LINES", &game.score.lines.to_string());
+
+            let filled = (side.pending() as f32 / ROWS as f32).min(1.0);
+
+            drawer.draw_rectangle(x as i32, y as i32, VERSUS_WIDTH as i32, 12, BAR);
+            drawer.draw_rectangle(x as i32, y as i32, (VERSUS_WIDTH * filled) as i32, 12, FAULT);
+        }
+    }
+
+    /// Placements and faults per shape, under the game over text.
+    pub fn draw_finesse(&self, drawer: &mut impl RaylibDraw, game: &Game) {
+        let width = BREAKDOWN[BREAKDOWN.len() - 1].1 + 100.0;
+        let left = (WIDTH as f32 - width) / 2.0;
+        let mut y = HEIGHT as f32 / 2.0 + 60.0;
+
+        for (label, x) in BREAKDOWN {
+            drawer.draw_text_ex(&self.font, label, Vector2::new(left + x, y), LABEL_SIZE, SPACING, LABEL);
+        }
+
+        for (index, piece) in game.finesse.pieces.iter().enumerate().filter(|(_, piece)| piece.placed > 0) {
+            y += LABEL_SIZE + 8.0;
+
+            let values = [
+                game.shapes().name(index).to_string(),
+                piece.placed.to_string(),
+                piece.faults.to_string(),
+                piece.wasted.to_string(),
+            ];
+
+            for ((_, x), value) in BREAKDOWN.iter().zip(values) {
+                drawer.draw_text_ex(&self.font, &value, Vector2::new(left + x, y), LABEL_SIZE, SPACING, if piece.faults > 0 { FAULT } else { VALUE });
+            }
+        }
+    }
+
+    /// One row per shape, a preview of it and a bar scaled to the most frequent one.
+    fn histogram(&self, drawer: &mut impl RaylibDraw, game: &Game, top: f32) {
+        drawer.draw_text_ex(&self.font, "PIECES", Vector2::new(RIGHT, top), LABEL_SIZE, SPACING, LABEL);
+
+        let most = game.stats.histogram.iter().copied().max().unwrap_or(0).max(1);
+        let bar_x = RIGHT + 5.0 * PREVIEW_CELL as f32 + 8.0;
+        let bar_width = COLUMN_WIDTH - (bar_x - RIGHT) - 30.0;
+
+        for (index, count) in game.stats.histogram.iter().enumerate() {
+            let y = top + LABEL_SIZE + 8.0 + index as f32 * HISTOGRAM_ROW;
+
+            // more shapes than fit, the rest just aren't shown
+            if y + HISTOGRAM_ROW > HEIGHT as f32 {
+                break;
+            }
+
+            let shape = game.shapes().get(index);
+
+            let max_x = shape.iter().map(|position| position.x).max().unwrap_or(0);
+            let max_y = shape.iter().map(|position| position.y).max().unwrap_or(0);
+
+            // the board is drawn mirrored with y up, the preview has to match
+            for position in &shape {
+                drawer.draw_rectangle(
+                    RIGHT as i32 + (max_x - position.x) as i32 * PREVIEW_CELL,
+                    y as i32 + (max_y - position.y) as i32 * PREVIEW_CELL,
+                    PREVIEW_CELL - 1,
+                    PREVIEW_CELL - 1,
+                    VALUE,
+                );
+            }
+
+            drawer.draw_rectangle(
+                bar_x as i32,
+                y as i32 + 4,
+                (bar_width * *count as f32 / most as f32) as i32,
+                PREVIEW_CELL * 2,
+                BAR,
+            );
+
+            drawer.draw_text_ex(
+                &self.font,
+                &count.to_string(),
+                Vector2::new(bar_x + bar_width + 4.0, y),
+                LABEL_SIZE,
+                SPACING,
+                VALUE,
+            );
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use crate::error::{Error, Result};
+
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Just enough JSON for the bot protocol, objects keep their keys in order.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn object<const N: usize>(fields: [(&str, Value); N]) -> Value {
+        Value::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// Only whole numbers that fit, bots may send `3.0` for 3.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_f64().filter(|number| number.fract() == 0.0 && number.abs() < 2f64.powi(53)).map(|number| number as i64)
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Value> {
+        let mut parser = Parser {
+            chars: text.chars().peekable(),
+        };
+
+        let value = parser.value()?;
+
+        parser.skip_whitespace();
+
+        match parser.chars.next() {
+            Some(other) => Err(Parser::error(format!("unexpected `{}` after the value", other))),
+            None => Ok(value),
+        }
+    }
+}
+
+/// One value per line until the reader ends, blank lines are skipped.
+pub fn lines(reader: impl Read) -> impl Iterator<Item = Result<Value>> {
+    BufReader::new(reader)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Value::parse(&line))
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Bool(value)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(number: u32) -> Value {
+        Value::Number(number as f64)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(number: f32) -> Value {
+        Value::Number(number as f64)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Value {
+        Value::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    f.write_str("\"")?;
+
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+
+    f.write_str("\"")
+}
+
+/// Compact, on one line, which is what line based protocols need.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            // JSON has no infinity or NaN
+            Value::Number(number) if !number.is_finite() => f.write_str("null"),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::String(text) => write_string(f, text),
+            Value::Array(values) => {
+                f.write_str("[")?;
+
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+
+                    write!(f, "{}", value)?;
+                }
+
+                f.write_str("]")
+            },
+            Value::Object(fields) => {
+                f.write_str("{")?;
+
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+
+                f.write_str("}")
+            },
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn error(reason: impl ToString) -> Error {
+        Error::decode("json", reason)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(Self::error(format!("expected `{}`, found `{}`", expected, c))),
+            None => Err(Self::error(format!("expected `{}`, found the end", expected))),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+
+        match self.chars.peek() {
+            Some('n') => self.literal("null", Value::Null),
+            Some('t') => self.literal("true", Value::Bool(true)),
+            Some('f') => self.literal("false", Value::Bool(false)),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(Self::error(format!("unexpected `{}`", c))),
+            None => Err(Self::error("unexpected end")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let mut text = String::new();
+
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+            text.push(c);
+        }
+
+        text.parse().map(Value::Number).map_err(|_| Self::error(format!("invalid number `{}`", text)))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+
+        let mut text = String::new();
+
+        loop {
+            match self.chars.next().ok_or_else(|| Self::error("unterminated string"))? {
+                '"' => return Ok(text),
+                '\\' => {
+                    let escaped = match self.chars.next().ok_or_else(|| Self::error("unterminated string"))? {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let code: String = self.chars.by_ref().take(4).collect();
+                            let code = u32::from_str_radix(&code, 16).map_err(|_| Self::error(format!("invalid escape `\\u{}`", code)))?;
+
+                            // surrogate pairs don't come up in this protocol
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        },
+                        other => return Err(Self::error(format!("invalid escape `\\{}`", other))),
+                    };
+
+                    text.push(escaped);
+                },
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        self.skip_whitespace();
+
+        let mut values = Vec::new();
+
+        if self.chars.next_if_eq(&']').is_some() {
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+
+            match self.chars.next() {
+                Some(',') => {},
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err(Self::error("expected `,` or `]` in array")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        self.skip_whitespace();
+
+        let mut fields = Vec::new();
+
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(Value::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+
+            let key = self.string()?;
+
+            self.skip_whitespace();
+            self.expect(':')?;
+
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+
+            match self.chars.next() {
+                Some(',') => {},
+                Some('}') => return Ok(Value::Object(fields)),
+                _ => return Err(Self::error("expected `,` or `}` in object")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let value = Value::object([
+            ("null", Value::Null),
+            ("flags", vec![true, false].into()),
+            ("numbers", Value::Array(vec![Value::Number(0.0), Value::Number(-3.0), Value::Number(0.1), Value::Number(1e21)])),
+            ("text", "quote \" backslash \\ newline \n tab \t bell \u{7} ü".into()),
+            ("nested", Value::object([("empty", Value::Array(Vec::new())), ("object", Value::Object(Vec::new()))])),
+        ]);
+
+        assert_eq!(Value::parse(&value.to_string()).unwrap(), value);
+    }
+
+    #[test]
+    fn whitespace_and_escapes() {
+        let value = Value::parse(" { \"a\" : [ 1 , 2.5e1 ] , \"b\" : \"\\u0041\\/\" } ").unwrap();
+
+        assert_eq!(value.to_string(), r#"{"a":[1,25],"b":"A/"}"#);
+    }
+
+    #[test]
+    fn whole_numbers() {
+        assert_eq!(Value::parse("3.0").unwrap().as_i64(), Some(3));
+        assert_eq!(Value::parse("3.5").unwrap().as_i64(), None);
+    }
+
+    #[test]
+    fn malformed() {
+        for text in ["", "{", "[1,]", "{\"a\" 1}", "\"open", "nul", "1 2", "{1:2}", "\"\\x\""] {
+            assert!(Value::parse(text).is_err(), "{:?} parsed", text);
+        }
+    }
+}
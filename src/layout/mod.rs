@@ -0,0 +1,159 @@
+use raylib::prelude::*;
+use raylib::consts::ConfigFlags;
+
+/// Everything is laid out on a virtual canvas of this size and scaled to the window.
+pub const HEIGHT: i32 = 1000;
+pub const WIDTH: i32 = 800;
+
+/// Maps the virtual canvas onto the window, keeping its aspect ratio with letterboxing.
+#[derive(Clone, Copy)]
+pub struct Layout {
+    pub scale: f32,
+    pub offset: Vector2,
+    dpi: Vector2,
+}
+
+impl Layout {
+    pub fn new(rl: &RaylibHandle) -> Layout {
+        let width = rl.get_screen_width() as f32;
+        let height = rl.get_screen_height() as f32;
+
+        let scale = (width / WIDTH as f32).min(height / HEIGHT as f32).max(0.01);
+
+        Layout {
+            scale,
+            offset: Vector2::new(
+                ((width - WIDTH as f32 * scale) / 2.0).floor(),
+                ((height - HEIGHT as f32 * scale) / 2.0).floor(),
+            ),
+            dpi: rl.get_window_scale_dpi(),
+        }
+    }
+
+    /// Camera that turns virtual coordinates into screen coordinates, draw the UI inside it.
+    pub fn camera(&self) -> Camera2D {
+        Camera2D {
+            offset: self.offset,
+            target: Vector2::zero(),
+            rotation: 0.0,
+            zoom: self.scale,
+        }
+    }
+
+    pub fn to_virtual(self, point: Vector2) -> Vector2 {
+        (point - self.offset) / self.scale
+    }
+
+    /// Size in pixels a render texture needs to cover the canvas without upscaling.
+    pub fn pixel_size(&self) -> (u32, u32) {
+        (
+            (WIDTH as f32 * self.scale * self.dpi.x).round().max(1.0) as u32,
+            (HEIGHT as f32 * self.scale * self.dpi.y).round().max(1.0) as u32,
+        )
+    }
+}
+
+pub fn init(title: &str) -> (RaylibHandle, RaylibThread) {
+    // the builder doesn't expose this flag, raylib ORs it with the builder's
+    unsafe {
+        raylib::ffi::SetConfigFlags(ConfigFlags::FLAG_WINDOW_HIGHDPI as u32);
+    }
+
+    let (mut rl, thread) = raylib::init()
+        .title(title)
+        .size(WIDTH, HEIGHT)
+        .resizable()
+        .build();
+
+    rl.set_window_min_size(WIDTH / 4, HEIGHT / 4);
+
+    (rl, thread)
+}
+
+/// Switches between a window of the default size and fullscreen at the monitor's resolution.
+pub fn set_fullscreen(rl: &mut RaylibHandle, fullscreen: bool) {
+    if rl.is_window_fullscreen() == fullscreen {
+        return;
+    }
+
+    if fullscreen {
+        let monitor = get_current_monitor();
+
+        rl.set_window_size(get_monitor_width(monitor), get_monitor_height(monitor));
+        rl.toggle_fullscreen();
+    } else {
+        rl.toggle_fullscreen();
+        rl.set_window_size(WIDTH, HEIGHT);
+    }
+}
+
+/// How the frame rate is limited, vsync waits for the display and the rest cap it with a timer.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum FrameRate {
+    #[default]
+    VSync,
+    Fps30,
+    Fps60,
+    Fps120,
+    Fps144,
+    Unlimited,
+}
+
+impl FrameRate {
+    pub const ALL: [FrameRate; 6] = [
+        FrameRate::VSync,
+        FrameRate::Fps30,
+        FrameRate::Fps60,
+        FrameRate::Fps120,
+        FrameRate::Fps144,
+        FrameRate::Unlimited,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameRate::VSync => "VSync",
+            FrameRate::Fps30 => "30",
+            FrameRate::Fps60 => "60",
+            FrameRate::Fps120 => "120",
+            FrameRate::Fps144 => "144",
+            FrameRate::Unlimited => "Unlimited",
+        }
+    }
+
+    /// Target passed to raylib, 0 leaves the frame rate uncapped.
+    fn target(&self) -> u32 {
+        match self {
+            FrameRate::VSync | FrameRate::Unlimited => 0,
+            FrameRate::Fps30 => 30,
+            FrameRate::Fps60 => 60,
+            FrameRate::Fps120 => 120,
+            FrameRate::Fps144 => 144,
+        }
+    }
+
+    pub fn next(&self) -> FrameRate {
+        let index = FrameRate::ALL.iter().position(|rate| rate == self).unwrap_or(0);
+
+        FrameRate::ALL[(index + 1) % FrameRate::ALL.len()]
+    }
+
+    pub fn from_index(index: u8) -> Option<FrameRate> {
+        FrameRate::ALL.get(index as usize).copied()
+    }
+
+    pub fn index(&self) -> u8 {
+        FrameRate::ALL.iter().position(|rate| rate == self).unwrap_or(0) as u8
+    }
+}
+
+pub fn set_frame_rate(rl: &mut RaylibHandle, rate: FrameRate) {
+    let vsync = WindowState::default().set_vsync_hint(true);
+
+    if rate == FrameRate::VSync {
+        rl.set_window_state(vsync);
+    } else {
+        rl.clear_window_state(vsync);
+    }
+
+    rl.set_target_fps(rate.target());
+}
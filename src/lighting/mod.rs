@@ -0,0 +1,393 @@
+use crate::assets::Resolver;
+use crate::error::{Error, Result};
+
+use raylib::prelude::*;
+
+use std::fs;
+
+pub const MAX_LIGHTS: usize = 4;
+
+/// DrawMesh only binds material maps, so the shadow map rides in the height map slot
+/// which none of the models use.
+const SHADOW_SLOT: usize = MaterialMapIndex::MATERIAL_MAP_HEIGHT as usize;
+
+/// Side of the square the shadow camera covers and how far back it sits, in world units.
+const SHADOW_EXTENT: f32 = 140.0;
+const SHADOW_DISTANCE: f32 = 200.0;
+
+/// Bits of the `maps` uniform, telling the shader which maps the model came with.
+const MAP_BITS: [(MaterialMapIndex, i32); 4] = [
+    (MaterialMapIndex::MATERIAL_MAP_METALNESS, 1),
+    (MaterialMapIndex::MATERIAL_MAP_NORMAL, 2),
+    (MaterialMapIndex::MATERIAL_MAP_ROUGHNESS, 4),
+    (MaterialMapIndex::MATERIAL_MAP_OCCLUSION, 8),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Quality {
+    Off,
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl Quality {
+    pub const ALL: [Quality; 4] = [Quality::Off, Quality::Low, Quality::Medium, Quality::High];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Quality::Off => "Off",
+            Quality::Low => "Low",
+            Quality::Medium => "Medium",
+            Quality::High => "High",
+        }
+    }
+
+    /// Low is plain per pixel lighting, medium adds normal maps and shadows, high filters the shadows.
+    fn shadow_size(&self) -> Option<u32> {
+        match self {
+            Quality::Off | Quality::Low => None,
+            Quality::Medium => Some(1024),
+            Quality::High => Some(2048),
+        }
+    }
+
+    pub fn next(&self) -> Quality {
+        let index = Quality::ALL.iter().position(|quality| quality == self).unwrap_or(0);
+
+        Quality::ALL[(index + 1) % Quality::ALL.len()]
+    }
+
+    pub fn from_index(index: u8) -> Option<Quality> {
+        Quality::ALL.get(index as usize).copied()
+    }
+
+    pub fn index(&self) -> u8 {
+        Quality::ALL.iter().position(|quality| quality == self).unwrap_or(0) as u8
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    Point,
+    /// `position` is the direction towards the light.
+    Directional,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub kind: Kind,
+    pub position: Vector3,
+    pub color: Vector3,
+    pub intensity: f32,
+}
+
+/// The lights and ambient color from `lights.txt`, see the comment at the top of that file.
+struct Rig {
+    ambient: Vector3,
+    lights: Vec<Light>,
+}
+
+impl Rig {
+    fn load(path: &str) -> Result<Rig> {
+        let source = fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+
+        Self::parse(&source).map_err(|(line, reason)| Error::decode(path, format!("line {}: {}", line + 1, reason)))
+    }
+
+    fn parse(source: &str) -> std::result::Result<Rig, (usize, String)> {
+        let mut rig = Rig {
+            ambient: Vector3::new(0.2, 0.2, 0.2),
+            lights: Vec::new(),
+        };
+
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let kind = words.next().unwrap_or_default();
+
+            let values = words
+                .map(|word| word.parse::<f32>().map_err(|_| (index, format!("expected a number, found {:?}", word))))
+                .collect::<std::result::Result<Vec<f32>, _>>()?;
+
+            let kind = match (kind, values.as_slice()) {
+                ("ambient", &[r, g, b]) => {
+                    rig.ambient = Vector3::new(r, g, b);
+                    continue;
+                },
+                ("directional", &[_, _, _, _, _, _, _]) => Kind::Directional,
+                ("point", &[_, _, _, _, _, _, _]) => Kind::Point,
+                ("ambient" | "directional" | "point", _) => {
+                    return Err((index, format!("wrong number of values for {}", kind)));
+                },
+                _ => return Err((index, format!("unknown light kind {:?}", kind))),
+            };
+
+            if rig.lights.len() == MAX_LIGHTS {
+                return Err((index, format!("at most {} lights are supported", MAX_LIGHTS)));
+            }
+
+            rig.lights.push(Light {
+                kind,
+                position: Vector3::new(values[0], values[1], values[2]),
+                color: Vector3::new(values[3], values[4], values[5]),
+                intensity: values[6],
+            });
+        }
+
+        Ok(rig)
+    }
+}
+
+struct Locations {
+    view: i32,
+    ambient: i32,
+    quality: i32,
+    maps: i32,
+    light_count: i32,
+    light_positions: i32,
+    light_colors: i32,
+    light_vp: i32,
+    shadow_light: i32,
+    shadow_size: i32,
+}
+
+/// Lights the 3D scene, swapping its shader into the models' materials only while they are drawn.
+pub struct Lighting {
+    quality: Quality,
+    rig: Rig,
+    shader: Shader,
+    depth: Shader,
+    shadowmap: Option<RenderTexture2D>,
+    locations: Locations,
+    /// What the materials had before `bind`, restored by `unbind`.
+    previous: Option<raylib::ffi::Shader>,
+}
+
+impl Lighting {
+    pub fn load(rl: &mut RaylibHandle, thread: &RaylibThread, resolver: &Resolver, quality: Quality) -> Result<Lighting> {
+        let rig = Rig::load(&resolver.resolve("lights.txt")?)?;
+
+        // there is no binding for the default shader, an empty load hands it back
+        let default = rl.load_shader(thread, None, None).map_err(Error::Device)?.id;
+
+        let mut shader = Self::load_shader(rl, thread, resolver, "shaders/lighting", default)?;
+        let depth = Self::load_shader(rl, thread, resolver, "shaders/depth", default)?;
+
+        let maps = [
+            (ShaderLocationIndex::SHADER_LOC_MATRIX_MODEL as usize, "matModel"),
+            (ShaderLocationIndex::SHADER_LOC_VECTOR_VIEW as usize, "viewPos"),
+            (ShaderLocationIndex::SHADER_LOC_MAP_ALBEDO as usize, "albedoMap"),
+            (ShaderLocationIndex::SHADER_LOC_MAP_METALNESS as usize, "metalnessMap"),
+            (ShaderLocationIndex::SHADER_LOC_MAP_NORMAL as usize, "normalMap"),
+            (ShaderLocationIndex::SHADER_LOC_MAP_ROUGHNESS as usize, "roughnessMap"),
+            (ShaderLocationIndex::SHADER_LOC_MAP_OCCLUSION as usize, "occlusionMap"),
+            // DrawMesh indexes the map locations by material slot
+            (ShaderLocationIndex::SHADER_LOC_MAP_ALBEDO as usize + SHADOW_SLOT, "shadowMap"),
+        ];
+
+        for (index, name) in maps {
+            shader.locs_mut()[index] = shader.get_shader_location(name);
+        }
+
+        let locations = Locations {
+            view: shader.locs()[ShaderLocationIndex::SHADER_LOC_VECTOR_VIEW as usize],
+            ambient: shader.get_shader_location("ambient"),
+            quality: shader.get_shader_location("quality"),
+            maps: shader.get_shader_location("maps"),
+            light_count: shader.get_shader_location("lightCount"),
+            light_positions: shader.get_shader_location("lightPositions"),
+            light_colors: shader.get_shader_location("lightColors"),
+            light_vp: shader.get_shader_location("lightVP"),
+            shadow_light: shader.get_shader_location("shadowLight"),
+            shadow_size: shader.get_shader_location("shadowSize"),
+        };
+
+        // the render texture's color attachment is created with nearest filtering,
+        // which is what the packed depth needs
+        let shadowmap = match quality.shadow_size() {
+            Some(size) => Some(rl.load_render_texture(thread, size, size).map_err(Error::Device)?),
+            None => None,
+        };
+
+        let mut lighting = Lighting {
+            quality,
+            rig,
+            shader,
+            depth,
+            shadowmap,
+            locations,
+            previous: None,
+        };
+
+        lighting.upload_lights();
+
+        Ok(lighting)
+    }
+
+    fn load_shader(rl: &mut RaylibHandle, thread: &RaylibThread, resolver: &Resolver, name: &str, default: u32) -> Result<Shader> {
+        let vs = resolver.resolve(&format!("{}.vs", name))?;
+        let fs = resolver.resolve(&format!("{}.fs", name))?;
+
+        let shader = rl.load_shader(thread, Some(&vs), Some(&fs)).map_err(|err| Error::decode(&fs, err))?;
+
+        // raylib falls back to its default shader when compilation fails
+        if shader.id == default {
+            return Err(Error::decode(&fs, "shader failed to compile, see the log above"));
+        }
+
+        Ok(shader)
+    }
+
+    /// The light casting the shadows, if shadows are on.
+    fn shadow_light(&self) -> Option<(usize, &Light)> {
+        self.shadowmap.as_ref()?;
+
+        self.rig.lights
+            .iter()
+            .enumerate()
+            .find(|(_, light)| light.kind == Kind::Directional)
+    }
+
+    fn upload_lights(&mut self) {
+        let positions: Vec<Vector4> = self.rig.lights
+            .iter()
+            .map(|light| {
+                let w = if light.kind == Kind::Point { 1.0 } else { 0.0 };
+
+                Vector4::new(light.position.x, light.position.y, light.position.z, w)
+            })
+            .collect();
+
+        let colors: Vec<Vector3> = self.rig.lights
+            .iter()
+            .map(|light| light.color.scale_by(light.intensity))
+            .collect();
+
+        let shadow_light = self.shadow_light().map_or(-1, |(index, _)| index as i32);
+        let shadow_size = self.quality.shadow_size().unwrap_or(1) as i32;
+
+        self.shader.set_shader_value(self.locations.ambient, self.rig.ambient);
+        self.shader.set_shader_value(self.locations.quality, self.quality.index() as i32);
+        self.shader.set_shader_value(self.locations.light_count, self.rig.lights.len() as i32);
+        self.shader.set_shader_value_v(self.locations.light_positions, &positions);
+        self.shader.set_shader_value_v(self.locations.light_colors, &colors);
+        self.shader.set_shader_value(self.locations.shadow_light, shadow_light);
+        self.shader.set_shader_value(self.locations.shadow_size, shadow_size);
+    }
+
+    /// Orthographic camera looking at `target` along the shadow light.
+    fn shadow_camera(light: &Light, target: Vector3) -> Camera3D {
+        let direction = light.position.normalized();
+
+        // look_at breaks down when up is parallel to the view direction
+        let up = if direction.y.abs() > 0.99 {
+            Vector3::new(0.0, 0.0, 1.0)
+        } else {
+            Vector3::new(0.0, 1.0, 0.0)
+        };
+
+        Camera3D::orthographic(target + direction.scale_by(SHADOW_DISTANCE), target, up, SHADOW_EXTENT)
+    }
+
+    /// Renders the depth of `model` drawn at each of `positions` from the shadow light,
+    /// `target` is the point the shadow map is centered on.
+    pub fn render_shadows(
+        &mut self,
+        drawer: &mut RaylibDrawHandle,
+        thread: &RaylibThread,
+        model: &mut Model,
+        positions: &[Vector3],
+        scale: f32,
+        target: Vector3,
+    ) {
+        let camera = match self.shadow_light() {
+            Some((_, light)) => Self::shadow_camera(light, target),
+            None => return,
+        };
+
+        // same matrices BeginMode3D builds for a square orthographic target
+        let half = SHADOW_EXTENT / 2.0;
+        let view = Matrix::look_at(camera.position, camera.target, camera.up);
+        let projection = Matrix::ortho(-half, half, -half, half, 0.01, 1000.0);
+
+        self.shader.set_shader_value_matrix(self.locations.light_vp, view * projection);
+
+        let previous = Self::swap(model, *self.depth.as_ref());
+
+        if let Some(shadowmap) = &mut self.shadowmap {
+            let mut texture_drawer = drawer.begin_texture_mode(thread, shadowmap);
+
+            // white unpacks to the far plane
+            texture_drawer.clear_background(Color::WHITE);
+
+            let mut render3d = texture_drawer.begin_mode3D(camera);
+
+            for position in positions {
+                render3d.draw_model(&*model, *position, scale, Color::WHITE);
+            }
+        }
+
+        Self::swap(model, previous);
+    }
+
+    /// Points every material of `model` at `shader`, returning the one it had before.
+    fn swap(model: &mut Model, shader: raylib::ffi::Shader) -> raylib::ffi::Shader {
+        let mut previous = shader;
+
+        for material in model.materials_mut() {
+            previous = std::mem::replace(&mut material.as_mut().shader, shader);
+        }
+
+        previous
+    }
+
+    /// Switches `model` over to the lighting shader until `unbind`, call once per frame before drawing it.
+    pub fn bind(&mut self, model: &mut Model, camera: &Camera3D) {
+        self.shader.set_shader_value(self.locations.view, camera.position);
+
+        let mut maps = 0;
+
+        if let Some(material) = model.materials_mut().first_mut() {
+            for (index, bit) in MAP_BITS {
+                if material.maps()[index as usize].texture.id != 0 {
+                    maps |= bit;
+                }
+            }
+        }
+
+        self.shader.set_shader_value(self.locations.maps, maps);
+
+        let shadow = self.shadowmap.as_ref().map(|shadowmap| shadowmap.texture);
+
+        for material in model.materials_mut() {
+            if let Some(texture) = shadow {
+                material.maps_mut()[SHADOW_SLOT].texture = texture;
+            }
+        }
+
+        let previous = Self::swap(model, *self.shader.as_ref());
+
+        if previous.id != self.shader.id {
+            self.previous = Some(previous);
+        }
+    }
+
+    /// Hands `model` its own shader back, the model must not be dropped while it holds ours.
+    pub fn unbind(&self, model: &mut Model) {
+        if let Some(previous) = self.previous {
+            Self::swap(model, previous);
+        }
+
+        for material in model.materials_mut() {
+            // UnloadMaterial would free the shadow map along with the model
+            material.maps_mut()[SHADOW_SLOT].texture.id = 0;
+        }
+    }
+}
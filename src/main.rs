@@ -1,24 +1,193 @@
+mod assets;
+mod audio;
+mod board;
+mod bot;
+mod camera;
+mod cheese;
+mod debug;
+mod editor;
+mod error;
+mod hud;
+mod json;
+mod layout;
+mod lighting;
+mod net;
+mod particles;
+mod postfx;
+mod puzzle;
+mod replay;
+mod rng;
+mod save;
+mod search;
+mod server;
+mod spectate;
+mod tbp;
 mod tshape;
+mod versus;
 mod game;
+mod headless;
 mod menu;
 
 use tshape::TShape;
 use game::Renderer;
 use menu::Menu;
+use assets::Resolver;
 
 use std::process;
+use std::env;
+use std::iter::Peekable;
+use std::str::FromStr;
+
+pub struct Options {
+    pub sound: bool,
+    pub skin: Option<String>,
+    /// Command line of an external bot to play with.
+    pub tbp: Option<String>,
+    /// Streams the games played to spectators on this address.
+    pub stream: Option<String>,
+    /// Watches the game streamed on this address first thing.
+    pub spectate: Option<String>,
+    /// Runs the built-in bot over the bot protocol instead of the game.
+    pub serve_bot: bool,
+    /// Hosts versus lobbies on this address instead of running the game.
+    pub server: Option<String>,
+    /// Runs `simulation` without a window instead of the game.
+    pub headless: bool,
+    pub simulation: headless::Config,
+}
+
+impl Options {
+    fn parse() -> Options {
+        let mut options = Options {
+            sound: true,
+            skin: None,
+            tbp: None,
+            stream: None,
+            spectate: None,
+            serve_bot: false,
+            server: None,
+            headless: false,
+            simulation: headless::Config::default(),
+        };
+
+        let mut args = env::args().skip(1).peekable();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--no-audio" => options.sound = false,
+                "--skin" => options.skin = args.next(),
+                "--tbp" => options.tbp = args.next(),
+                "--tbp-bot" => options.serve_bot = true,
+                "--stream" => options.stream = Some(Self::optional(&mut args).unwrap_or_else(|| String::from(spectate::DEFAULT_ADDRESS))),
+                "--spectate" => options.spectate = Some(Self::optional(&mut args).unwrap_or_else(|| String::from(spectate::DEFAULT_ADDRESS))),
+                "--server" => options.server = Some(Self::optional(&mut args).unwrap_or_else(|| String::from(net::DEFAULT_ADDRESS))),
+                "--headless" => options.headless = true,
+                "--connect" => options.simulation.connect = args.next(),
+                "--lobby" => options.simulation.lobby = Self::optional(&mut args).unwrap_or(options.simulation.lobby),
+                "--games" => Self::number(&arg, args.next(), &mut options.simulation.games),
+                "--seed" => options.simulation.seed = Self::value(&arg, args.next()),
+                "--shapes" => options.simulation.shapes = args.next(),
+                "--level" => Self::number(&arg, args.next(), &mut options.simulation.level),
+                "--board" => options.simulation.board = args.next(),
+                "--replay" => options.simulation.replay = args.next(),
+                "--record" => options.simulation.record = args.next(),
+                "--max-pieces" => Self::number(&arg, args.next(), &mut options.simulation.max_pieces),
+                _ => eprintln!("[WARNING] unknown argument: {}", arg),
+            }
+        }
+
+        options
+    }
+
+    /// The value of an argument that can go without one, the next argument isn't it.
+    fn optional(args: &mut Peekable<impl Iterator<Item = String>>) -> Option<String> {
+        args.next_if(|next| !next.starts_with("--"))
+    }
+
+    fn value<T: FromStr>(arg: &str, value: Option<String>) -> Option<T> {
+        let parsed = value.as_deref().and_then(|value| value.parse().ok());
+
+        if parsed.is_none() {
+            eprintln!("[WARNING] {} expects a number, found {}", arg, value.as_deref().unwrap_or("nothing"));
+        }
+
+        parsed
+    }
+
+    /// Keeps the default when the value doesn't parse.
+    fn number<T: FromStr>(arg: &str, value: Option<String>, target: &mut T) {
+        if let Some(value) = Self::value(arg, value) {
+            *target = value;
+        }
+    }
+
+    pub fn resolver(&self) -> Resolver {
+        match &self.skin {
+            Some(skin) => Resolver::new().with_skin(skin),
+            None => Resolver::new(),
+        }
+    }
+}
+
+/// Stdout belongs to the protocol here.
+fn serve_bot(options: &Options) {
+    let served = options
+        .resolver()
+        .resolve("shapes.tshape")
+        .and_then(|path| TShape::load(&path))
+        .and_then(tbp::serve);
+
+    if let Err(err) = served {
+        eprintln!("[ERROR] bot failed: {}", err);
+        process::exit(1);
+    }
+}
+
+/// No window is opened, the game runs on its own and stdout only gets the JSON results.
+fn run_headless(options: &Options) {
+    let shapes = match &options.simulation.shapes {
+        Some(file) => TShape::load(file),
+        None => options.resolver().resolve("shapes.tshape").and_then(|path| TShape::load(&path)),
+    };
+
+    if let Err(err) = shapes.and_then(|shapes| headless::run(&options.simulation, shapes, options.tbp.as_deref())) {
+        eprintln!("[ERROR] simulation failed: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run_server(address: &str) {
+    if let Err(err) = server::run(address) {
+        eprintln!("[ERROR] server failed: {}", err);
+        process::exit(1);
+    }
+}
 
 fn main() {
-    let mut menu = match Menu::new() {
+    let options = Options::parse();
+
+    if options.serve_bot {
+        return serve_bot(&options);
+    }
+
+    if let Some(address) = &options.server {
+        return run_server(address);
+    }
+
+    if options.headless {
+        return run_headless(&options);
+    }
+
+    let mut menu = match Menu::new(&options) {
         Ok(menu) => menu,
         Err(err) => {
-            println!("[ERROR] failed to initialize menu: {}", err.to_string());
+            eprintln!("[ERROR] failed to initialize menu: {}", err);
             process::exit(1);
         },
     };
 
     if let Err(err) = menu.run() {
-        println!("[ERROR] failed to run menu: {}", err.to_string());
+        eprintln!("[ERROR] failed to run menu: {}", err);
         process::exit(1);
     }
 }
@@ -1,13 +1,43 @@
 use raylib::prelude::*;
 
-use crate::{Renderer, game::Settings};
+use crate::{Options, Renderer, TShape, game::Settings};
+use crate::assets::{self, Resolver};
+use crate::audio::{self, Audio, Mixer};
+use crate::board::Board;
+use crate::bot::{BotSettings, Speed};
+use crate::camera;
+use crate::cheese::{Mode, RaceLength};
+use crate::editor::Editor;
+use crate::error::{self, Error, Result};
+use crate::json::Value;
+use crate::hud::HudMode;
+use crate::layout::{self, FrameRate, Layout, HEIGHT, WIDTH};
+use crate::lighting::Quality;
+use crate::net::{self, Connection, Session, Start};
+use crate::spectate::{Broadcast, Viewer};
+use crate::postfx::{Effects, Preset};
+use crate::puzzle::{Outcome, Puzzle};
+use crate::save;
+use crate::versus::{Rival, Rounds, VersusSettings};
 
-use std::io::Write;
+use std::io::{ErrorKind, Write};
 use std::fs::File;
-use std::fs;
+use std::{env, fs};
 
-const HEIGHT: i32 = 1000;
-const WIDTH: i32 = 800;
+const BACKGROUND: Color = Color::new(0x0B, 0x0D, 0x13, 0xFF);
+const FOREGROUND: Color = Color::new(0xFF, 0xFF, 0xFF, 0xFF);
+const BUTTON: Color = Color::new(0x0F, 0x19, 0x23, 0xFF);
+
+const SLIDER_STEP: f32 = 0.1;
+
+/// Seconds without input on the title page before the bot starts a demo.
+const DEMO_DELAY: f32 = 20.0;
+
+/// Seconds between asking the server for open lobbies, and how many of them are shown.
+const LOBBY_REFRESH: f32 = 2.0;
+const LOBBIES_SHOWN: usize = 4;
+
+const MAX_FIELD: usize = 24;
 
 pub struct Assets {
     font: Font,
@@ -21,46 +51,261 @@ pub enum Label {
         label: &'static str,
         state: bool,
     },
+    Slider {
+        label: &'static str,
+        value: f32,
+    },
+    Choice {
+        label: &'static str,
+        value: &'static str,
+    },
+    /// Text, only the server, name and lobby fields can be typed into.
+    Field {
+        label: &'static str,
+        value: String,
+    },
+    /// An entry of the puzzle list, ticked once solved.
+    Puzzle {
+        index: usize,
+        name: String,
+        solved: bool,
+    },
 }
 
 impl Label {
     pub fn get_label(&self) -> &'static str {
         match self {
-            Label::Button(label) => &label,
-            Label::Toggle { label, .. } => &label,
+            Label::Button(label) => label,
+            Label::Toggle { label, .. } => label,
+            Label::Slider { label, .. } => label,
+            Label::Choice { label, .. } => label,
+            Label::Field { label, .. } => label,
+            Label::Puzzle { .. } => "",
         }
     }
 }
 
+#[derive(Default)]
 pub struct Config {
     highscore: u16,
+    mixer: Mixer,
+    fullscreen: bool,
+    low_effects: bool,
+    finesse: bool,
+    effects: Effects,
+    lighting: Quality,
+    camera: camera::Mode,
+    frame_rate: FrameRate,
+    hud: HudMode,
+    /// Names of the puzzles solved.
+    solved: Vec<String>,
+    bot: BotSettings,
+    versus: VersusSettings,
+    race: RaceLength,
+}
+
+/// Reads the config one fixed size section at a time.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Sections added after a config was written are simply missing, a cut off one is corrupt.
+    fn section<const N: usize>(&mut self) -> std::result::Result<Option<[u8; N]>, String> {
+        if self.0.is_empty() {
+            return Ok(None);
+        }
+
+        let (section, rest) = self.0
+            .split_first_chunk::<N>()
+            .ok_or_else(|| format!("truncated section, expected {} bytes but found {}", N, self.0.len()))?;
+
+        self.0 = rest;
+
+        Ok(Some(*section))
+    }
+
+    /// A section of `len` bytes, with its length written before it.
+    fn sized(&mut self, len: usize) -> std::result::Result<&'a [u8], String> {
+        let (section, rest) = self.0
+            .split_at_checked(len)
+            .ok_or_else(|| format!("truncated section, expected {} bytes but found {}", len, self.0.len()))?;
+
+        self.0 = rest;
+
+        Ok(section)
+    }
 }
 
 impl Config {
-    pub fn load() -> Result<Config, Box<dyn std::error::Error>> {
-        let bytes = fs::read("config.tr")?.iter().map(|x| *x as u16).collect::<Vec<u16>>();
+    pub fn load() -> Result<Config> {
+        let path = assets::config_path("config.tr")?;
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(Error::io(path, err)),
+        };
 
-        Ok(Config {
-            highscore: bytes[0] << 8 | bytes[1],
+        Self::decode(&bytes).map_err(|reason| Error::ConfigCorrupt {
+            path,
+            reason,
         })
     }
 
-    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let mut fd = File::create("config.tr")?;
+    /// The layout is the big endian highscore followed by the mixer, the flags, the effects,
+    /// the lighting quality, the camera mode, the frame rate, the HUD mode, the names of the solved
+    /// puzzles, the bot, versus and the race length, older configs that stop after a section keep the
+    /// defaults for the rest.
+    fn decode(bytes: &[u8]) -> std::result::Result<Config, String> {
+        let volume = |byte: u8| match byte {
+            0..=100 => Ok(byte as f32 / 100.0),
+            _ => Err(format!("volume out of range: {}", byte)),
+        };
+
+        let mut reader = Reader(bytes);
+        let mut config = Config::default();
+
+        let [high, low] = reader.section()?.ok_or("missing highscore")?;
+        config.highscore = u16::from_be_bytes([high, low]);
 
-        fd.write_all(&[
-            ((self.highscore & 0xff00) >> 8) as u8,
-            (self.highscore & 0x00ff) as u8,
-        ])?;
+        if let Some([master, music, sfx, muted]) = reader.section()? {
+            config.mixer = Mixer {
+                master: volume(master)?,
+                music: volume(music)?,
+                sfx: volume(sfx)?,
+                muted: muted != 0,
+            };
+        }
+
+        if let Some([flags]) = reader.section()? {
+            config.fullscreen = flags & 1 != 0;
+            config.low_effects = flags & 2 != 0;
+            config.finesse = flags & 4 != 0;
+        }
+
+        if let Some([preset, intensity]) = reader.section()? {
+            config.effects = Effects {
+                preset: Preset::from_index(preset).ok_or_else(|| format!("unknown effect preset: {}", preset))?,
+                intensity: volume(intensity)?,
+            };
+        }
+
+        if let Some([quality]) = reader.section()? {
+            config.lighting = Quality::from_index(quality).ok_or_else(|| format!("unknown lighting quality: {}", quality))?;
+        }
+
+        if let Some([mode]) = reader.section()? {
+            config.camera = camera::Mode::from_index(mode).ok_or_else(|| format!("unknown camera mode: {}", mode))?;
+        }
+
+        if let Some([rate]) = reader.section()? {
+            config.frame_rate = FrameRate::from_index(rate).ok_or_else(|| format!("unknown frame rate: {}", rate))?;
+        }
+
+        if let Some([hud]) = reader.section()? {
+            config.hud = HudMode::from_index(hud).ok_or_else(|| format!("unknown hud mode: {}", hud))?;
+        }
+
+        if let Some(count) = reader.section()? {
+            for _ in 0..u16::from_be_bytes(count) {
+                let len = reader.section()?.ok_or("truncated puzzle name")?;
+                let name = reader.sized(u16::from_be_bytes(len) as usize)?;
+
+                config.solved.push(String::from_utf8(name.to_vec()).map_err(|_| "puzzle name isn't utf-8")?);
+            }
+        }
+
+        if let Some([speed, flags]) = reader.section()? {
+            config.bot = BotSettings {
+                speed: Speed::from_index(speed).ok_or_else(|| format!("unknown bot speed: {}", speed))?,
+                hints: flags & 1 != 0,
+                demo: flags & 2 != 0,
+            };
+        }
+
+        if let Some([rounds, rival]) = reader.section()? {
+            config.versus = VersusSettings {
+                rounds: Rounds::from_index(rounds).ok_or_else(|| format!("unknown versus rounds: {}", rounds))?,
+                rival: Rival::from_index(rival).ok_or_else(|| format!("unknown versus rival: {}", rival))?,
+            };
+        }
+
+        if let Some([race]) = reader.section()? {
+            config.race = RaceLength::from_index(race).ok_or_else(|| format!("unknown race length: {}", race))?;
+        }
+
+        if !reader.0.is_empty() {
+            return Err(format!("{} unexpected trailing bytes", reader.0.len()));
+        }
+
+        Ok(config)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let volume = |value: f32| (value.clamp(0.0, 1.0) * 100.0).round() as u8;
+
+        let mut bytes = self.highscore.to_be_bytes().to_vec();
+
+        bytes.extend([
+            volume(self.mixer.master),
+            volume(self.mixer.music),
+            volume(self.mixer.sfx),
+            self.mixer.muted as u8,
+        ]);
+
+        bytes.push(self.fullscreen as u8 | (self.low_effects as u8) << 1 | (self.finesse as u8) << 2);
+
+        bytes.extend([
+            self.effects.preset.index(),
+            volume(self.effects.intensity),
+        ]);
+
+        bytes.push(self.lighting.index());
+        bytes.push(self.camera.index());
+        bytes.push(self.frame_rate.index());
+        bytes.push(self.hud.index());
+        bytes.extend((self.solved.len() as u16).to_be_bytes());
+
+        for name in &self.solved {
+            bytes.extend((name.len() as u16).to_be_bytes());
+            bytes.extend(name.as_bytes());
+        }
+
+        bytes.extend([self.bot.speed.index(), self.bot.hints as u8 | (self.bot.demo as u8) << 1]);
+        bytes.extend([self.versus.rounds.index(), self.versus.rival.index()]);
+        bytes.push(self.race.index());
+
+        bytes
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = assets::config_path("config.tr")?;
+        let mut fd = File::create(&path).map_err(|err| Error::io(&path, err))?;
+
+        fd.write_all(&self.encode()).map_err(|err| Error::io(&path, err))?;
 
         Ok(())
     }
 }
 
+/// The online pages, the connection stays open between matches.
+struct Online {
+    server: String,
+    name: String,
+    lobby: String,
+    connection: Option<Connection>,
+    /// Lobbies with someone waiting in them, as last listed by the server.
+    lobbies: Vec<String>,
+    /// Joined a lobby, the match starts when someone else does.
+    waiting: bool,
+    /// Seconds until the lobbies are listed again.
+    refresh: f32,
+}
+
 pub struct Menu {
     rl: RaylibHandle,
     thread: RaylibThread,
-    audio: RaylibAudio,
+    audio: Audio,
+    resolver: Resolver,
     assets: Assets,
     settings: Settings,
     selected: usize,
@@ -68,53 +313,125 @@ pub struct Menu {
     title: &'static str,
     should_close: bool,
     config: Config,
+    /// Loaded the first time the puzzle page opens.
+    puzzles: Vec<Puzzle>,
+    /// Seconds since the last key press or mouse movement.
+    idle: f32,
+    mouse: Vector2,
+    /// External bot to play new games with, from `--tbp`.
+    tbp: Option<String>,
+    online: Online,
+    /// Streams the games played from here, from `--stream`.
+    broadcast: Option<Broadcast>,
+    /// Game to watch before the menu opens, from `--spectate`.
+    spectate: Option<String>,
+    /// A game was saved on the way out, the title page offers to continue it.
+    saved: bool,
+    /// Shown under the labels, what went wrong or what's being waited for.
+    status: Option<String>,
 }
 
 impl Menu {
-    pub fn new() -> Result<Menu, Box<dyn std::error::Error>> {
-        let (mut rl, thread) = raylib::init()
-            .title("Tetris")
-            .size(WIDTH, HEIGHT)
-            .build();
+    pub fn new(options: &Options) -> Result<Menu> {
+        let (mut rl, thread) = layout::init("Tetris");
+
+        let resolver = options.resolver();
 
-        let audio = RaylibAudio::init_audio_device();
+        let audio = Audio::load(audio::init(options.sound), &thread, &resolver);
 
-        let mut logo = Image::load_image("assets/ui/bg.png")?;
+        let logo_path = resolver.resolve("ui/bg.png")?;
+        let mut logo = Image::load_image(&logo_path).map_err(|err| Error::decode(&logo_path, err))?;
         logo.resize(300, 300);
 
+        let font_path = resolver.resolve("ui/InriaSerif-Regular.ttf")?;
+
         let assets = Assets {
-            font: rl.load_font_ex(&thread, "assets/ui/InriaSerif-Regular.ttf", 60, FontLoadEx::Default(256))?,
-            logo: rl.load_texture_from_image(&thread, &logo)?,
+            font: rl.load_font_ex(&thread, &font_path, 60, FontLoadEx::Default(256)).map_err(|err| Error::decode(&font_path, err))?,
+            logo: rl.load_texture_from_image(&thread, &logo).map_err(|err| Error::decode(&logo_path, err))?,
         };
 
-        Ok(Menu {
+        let config = error::recover(Config::load()).unwrap_or_default();
+
+        layout::set_fullscreen(&mut rl, config.fullscreen);
+        layout::set_frame_rate(&mut rl, config.frame_rate);
+
+        let mut menu = Menu {
             rl,
             thread,
             audio,
+            resolver,
             assets,
             settings: Settings {
                 smooth: true,
                 mode3d: true,
+                mixer: config.mixer,
+                fullscreen: config.fullscreen,
+                effects: config.effects,
+                lighting: config.lighting,
+                camera: config.camera,
+                low_effects: config.low_effects,
+                frame_rate: config.frame_rate,
+                hud: config.hud,
+                finesse: config.finesse,
+                bot: config.bot,
+                versus: config.versus,
+                race: config.race,
             },
             selected: 0,
-            labels: vec![Label::Button("Play"), Label::Button("Settings"), Label::Button("Exit")],
+            labels: Vec::new(),
             title: "Tetris",
             should_close: false,
-            config: Config::load()?,
-        })
+            config,
+            puzzles: Vec::new(),
+            idle: 0.0,
+            mouse: Vector2::zero(),
+            tbp: options.tbp.clone(),
+            online: Online {
+                server: String::from(net::DEFAULT_ADDRESS),
+                name: env::var("USER").unwrap_or_else(|_| String::from("Player")),
+                lobby: String::from("tetris"),
+                connection: None,
+                lobbies: Vec::new(),
+                waiting: false,
+                refresh: 0.0,
+            },
+            broadcast: options.stream.as_deref().and_then(|address| error::recover(Broadcast::bind(address))),
+            spectate: options.spectate.clone(),
+            saved: save::exists(),
+            status: None,
+        };
+
+        menu.update_menu();
+
+        Ok(menu)
     }
 
-    fn draw(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Long pages start higher up and give up the logo's space.
+    fn labels_top(&self) -> i32 {
+        let overflow = self.labels.len().saturating_sub(5) as i32;
+
+        (HEIGHT / 2) - 90 * overflow
+    }
+
+    fn draw(&mut self) {
+        let layout = Layout::new(&self.rl);
+        let top = self.labels_top();
         let mut drawer = self.rl.begin_drawing(&self.thread);
 
-        drawer.clear_background(Color::from_hex("0B0D13")?);
+        drawer.clear_background(Color::BLACK);
+
+        let mut drawer = drawer.begin_mode2D(layout.camera());
 
-        let fg = Color::from_hex("FFFFFF")?;
-        let bg = Color::from_hex("0F1923")?;
+        drawer.draw_rectangle(0, 0, WIDTH, HEIGHT, BACKGROUND);
+
+        let fg = FOREGROUND;
+        let bg = BUTTON;
 
         // let size = text::measure_text_ex(&self.assets.font, self.title, 40.0, 42.0);
 
-        drawer.draw_texture(&self.assets.logo, (WIDTH / 2) - (self.assets.logo.width / 2), 130, Color::WHITE);
+        if top >= 130 + self.assets.logo.height {
+            drawer.draw_texture(&self.assets.logo, (WIDTH / 2) - (self.assets.logo.width / 2), 130, Color::WHITE);
+        }
 
         /*
         drawer.draw_text_ex(
@@ -134,7 +451,7 @@ impl Menu {
             drawer.draw_rectangle_rounded(
                 Rectangle::new(
                     ((WIDTH / 2) - 150) as f32,
-                    ((HEIGHT / 2) + (90 * index as i32)) as f32,
+                    (top + (90 * index as i32)) as f32,
                     300.0,
                     60.0,
                 ),
@@ -147,7 +464,7 @@ impl Menu {
                 drawer.draw_rectangle_rounded_lines(
                     Rectangle::new(
                         ((WIDTH / 2) - 150) as f32,
-                        ((HEIGHT / 2) + (90 * index as i32)) as f32,
+                        (top + (90 * index as i32)) as f32,
                         300.0,
                         60.0,
                     ),
@@ -160,26 +477,46 @@ impl Menu {
 
             let text = match label {
                 Label::Button(label) => {
-                    label
+                    label.to_string()
                 },
-                Label::Toggle { label, state } => {
+                Label::Toggle { label: _, state } | Label::Puzzle { solved: state, .. } => {
                     if *state {
                         drawer.draw_circle(
                             (WIDTH / 2) + 130,
-                            (HEIGHT / 2) + (90 * index as i32) + 30,
+                            top + (90 * index as i32) + 30,
                             10.0,
                             fg,
                         );
                     } else {
                         drawer.draw_circle_lines(
                             (WIDTH / 2) + 130,
-                            (HEIGHT / 2) + (90 * index as i32) + 30,
+                            top + (90 * index as i32) + 30,
                             10.0,
                             fg,
                         );
                     }
 
-                    label
+                    match label {
+                        Label::Puzzle { name, .. } => name.clone(),
+                        label => label.get_label().to_string(),
+                    }
+                },
+                Label::Choice { label, value } => {
+                    format!("{}: {}", label, value)
+                },
+                Label::Field { label, value } => {
+                    format!("{}: {}", label, value)
+                },
+                Label::Slider { label, value } => {
+                    drawer.draw_rectangle(
+                        (WIDTH / 2) - 130,
+                        top + (90 * index as i32) + 50,
+                        (260.0 * value) as i32,
+                        4,
+                        fg,
+                    );
+
+                    format!("{} {}%", label, (value * 100.0).round() as i32)
                 },
             };
 
@@ -188,7 +525,7 @@ impl Menu {
                 &text,
                 Vector2::new(
                     ((WIDTH / 2) as f32 - (text::measure_text_ex(&self.assets.font, &text, 40.0, 2.0).x / 2.0)) as f32,
-                    ((HEIGHT / 2) + (90 * index as i32) + 10) as f32,
+                    (top + (90 * index as i32) + 10) as f32,
                 ),
                 40.0,
                 2.0,
@@ -197,6 +534,12 @@ impl Menu {
 
         }
 
+        if let Some(status) = &self.status {
+            let size = text::measure_text_ex(&self.assets.font, status, 30.0, 2.0);
+
+            drawer.draw_text_ex(&self.assets.font, status, Vector2::new((WIDTH / 2) as f32 - (size.x / 2.0), (HEIGHT - 60) as f32), 30.0, 2.0, fg);
+        }
+
         // highscore
         drawer.draw_rectangle_rounded(
             Rectangle::new(
@@ -236,14 +579,17 @@ impl Menu {
             2.0,
             fg
         );
-
-        Ok(())
     }
 
-    fn draw_loading(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    fn draw_loading(&mut self) {
+        let layout = Layout::new(&self.rl);
         let mut drawer = self.rl.begin_drawing(&self.thread);
 
-        drawer.clear_background(Color::from_hex("0B0D13")?);
+        drawer.clear_background(Color::BLACK);
+
+        let mut drawer = drawer.begin_mode2D(layout.camera());
+
+        drawer.draw_rectangle(0, 0, WIDTH, HEIGHT, BACKGROUND);
 
         let text = "Loading Assets";
         let size = text::measure_text_ex(&self.assets.font, text, 40.0, 2.0);
@@ -257,43 +603,468 @@ impl Menu {
             ),
             40.0,
             2.0,
-            Color::from_hex("FFFFFF")?
+            FOREGROUND
         );
+    }
+
+    /// Starts from `board` when given, those practice games don't count for the highscore.
+    fn play_game(&mut self, board: Option<&Board>) -> Result<()> {
+        self.draw_loading();
+
+        let mut renderer = Renderer::new(&mut self.rl, &self.thread, &mut self.audio, &self.resolver, self.settings)?;
+
+        if let Some(board) = board {
+            renderer.game.load_board(board);
+        }
+
+        if let Some(command) = &self.tbp {
+            renderer.start_external(command);
+        }
+
+        renderer.saving = board.is_none();
+        renderer.broadcast = self.broadcast.take();
+        renderer.run()?;
+
+        self.broadcast = renderer.broadcast.take();
+        self.saved = save::exists();
+
+        if board.is_none() && renderer.game.score.lines > self.config.highscore as u32 {
+            self.config.highscore = renderer.game.score.lines as u16;
+        }
+
+        Self::keep_hotkeys(&mut self.settings, &renderer.settings);
+        self.save_config();
 
         Ok(())
     }
 
-    fn play_game(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.draw_loading()?;
+    fn watch(&mut self, address: &str) -> Result<()> {
+        let viewer = match Viewer::connect(address) {
+            Ok(viewer) => viewer,
+            Err(err) => {
+                self.status = Some(err.to_string());
+                return Ok(());
+            },
+        };
+
+        self.draw_loading();
 
-        let mut renderer = Renderer::new(&mut self.rl, &self.thread, &mut self.audio, self.settings)?;
+        let mut renderer = Renderer::new(&mut self.rl, &self.thread, &mut self.audio, &self.resolver, self.settings)?;
 
+        renderer.start_spectating(viewer);
         renderer.run()?;
 
+        Self::keep_hotkeys(&mut self.settings, &renderer.settings);
+        self.save_config();
+
+        Ok(())
+    }
+
+    /// Plays on from the saved game, one that can't be loaded any more is thrown away.
+    fn continue_game(&mut self) -> Result<()> {
+        self.draw_loading();
+
+        let mut renderer = Renderer::new(&mut self.rl, &self.thread, &mut self.audio, &self.resolver, self.settings)?;
+
+        match save::load(renderer.game.shapes()) {
+            Ok(game) => renderer.resume(game),
+            Err(err) => {
+                error::warn(&err);
+                error::recover(save::remove());
+
+                self.saved = false;
+                self.selected = 0;
+                self.status = Some(String::from("The saved game can't be continued"));
+
+                return Ok(());
+            },
+        }
+
+        if let Some(command) = &self.tbp {
+            renderer.start_external(command);
+        }
+
+        renderer.broadcast = self.broadcast.take();
+        renderer.run()?;
+
+        self.broadcast = renderer.broadcast.take();
+        self.saved = save::exists();
+
         if renderer.game.score.lines > self.config.highscore as u32 {
             self.config.highscore = renderer.game.score.lines as u16;
         }
 
-        self.config.save()?;
+        Self::keep_hotkeys(&mut self.settings, &renderer.settings);
+        self.save_config();
+
+        Ok(())
+    }
+
+    /// The mute, fullscreen, camera, hud and finesse hotkeys work in game too.
+    fn keep_hotkeys(settings: &mut Settings, game: &Settings) {
+        settings.mixer = game.mixer;
+        settings.fullscreen = game.fullscreen;
+        settings.camera = game.camera;
+        settings.hud = game.hud;
+        settings.finesse = game.finesse;
+        settings.bot.hints = game.bot.hints;
+    }
+
+    /// Matches don't count for the highscore.
+    fn play_versus(&mut self) -> Result<()> {
+        self.draw_loading();
+
+        let mut renderer = Renderer::new(&mut self.rl, &self.thread, &mut self.audio, &self.resolver, self.settings)?;
+
+        renderer.start_versus(self.settings.versus);
+        renderer.run()?;
+
+        Self::keep_hotkeys(&mut self.settings, &renderer.settings);
+        self.save_config();
+
+        Ok(())
+    }
+
+    /// Survival and races don't count for the highscore either.
+    fn play_cheese(&mut self, mode: Mode) -> Result<()> {
+        self.draw_loading();
+
+        let mut renderer = Renderer::new(&mut self.rl, &self.thread, &mut self.audio, &self.resolver, self.settings)?;
+
+        renderer.start_cheese(mode);
+        renderer.broadcast = self.broadcast.take();
+        renderer.run()?;
+
+        self.broadcast = renderer.broadcast.take();
+
+        Self::keep_hotkeys(&mut self.settings, &renderer.settings);
+        self.save_config();
+
+        Ok(())
+    }
+
+    /// Hands the connection to the match and takes it back for the lobby afterwards.
+    fn play_online(&mut self, start: Start) -> Result<()> {
+        let Some(connection) = self.online.connection.take() else {
+            return Ok(());
+        };
+
+        self.online.waiting = false;
+        self.status = None;
+        self.draw_loading();
+
+        let mut renderer = Renderer::new(&mut self.rl, &self.thread, &mut self.audio, &self.resolver, self.settings)?;
+
+        renderer.start_online(&start, Session::new(connection, &start));
+        renderer.run()?;
+
+        self.online.connection = renderer.online.take().map(Session::into_connection);
+        self.online.refresh = 0.0;
+
+        Self::keep_hotkeys(&mut self.settings, &renderer.settings);
+        self.save_config();
+
+        Ok(())
+    }
+
+    fn connect(&mut self) {
+        match Connection::open(self.online.server.trim(), self.online.name.trim()) {
+            Ok(connection) => {
+                self.online.connection = Some(connection);
+                self.online.lobbies.clear();
+                self.online.refresh = 0.0;
+                self.status = None;
+                self.selected = 0;
+                self.title = "Lobbies";
+            },
+            Err(err) => self.status = Some(err.to_string()),
+        }
+    }
+
+    fn send(&mut self, message: Value) {
+        let sent = self.online.connection.as_mut().map(|connection| connection.send(message));
+
+        if let Some(Err(err)) = sent {
+            self.disconnect(err.to_string());
+        }
+    }
+
+    fn disconnect(&mut self, reason: String) {
+        self.online.connection = None;
+        self.online.waiting = false;
+        self.status = Some(reason);
+        self.selected = 0;
+        self.title = "Online";
+    }
+
+    /// The rounds setting of whoever opens the lobby is the one that's played.
+    fn join(&mut self, lobby: String) {
+        self.send(Value::object([
+            ("type", "join".into()),
+            ("lobby", Value::String(lobby)),
+            ("rounds", Value::Number(self.settings.versus.rounds.index() as f64)),
+        ]));
+    }
+
+    /// Keeps the lobby list fresh and answers the server, a full lobby starts the match.
+    fn update_online(&mut self) -> Result<()> {
+        let Some(connection) = &mut self.online.connection else {
+            return Ok(());
+        };
+
+        let mut messages = Vec::new();
+
+        let lost = loop {
+            match connection.receive() {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) => break None,
+                Err(err) => break Some(err),
+            }
+        };
+
+        self.online.refresh -= self.rl.get_frame_time();
+
+        if self.online.refresh <= 0.0 && !self.online.waiting && self.title == "Lobbies" {
+            self.online.refresh = LOBBY_REFRESH;
+            self.send(Value::object([("type", "list".into())]));
+        }
+
+        for message in messages {
+            match net::kind(&message) {
+                Some("lobbies") => {
+                    self.online.lobbies = message
+                        .get("lobbies")
+                        .and_then(Value::as_array)
+                        .unwrap_or_default()
+                        .iter()
+                        .filter_map(|lobby| lobby.get("name").and_then(Value::as_str).map(str::to_string))
+                        .take(LOBBIES_SHOWN)
+                        .collect();
+                },
+                Some("joined") => {
+                    let lobby = message.get("lobby").and_then(Value::as_str).unwrap_or("the lobby");
+
+                    self.online.waiting = true;
+                    self.status = Some(format!("Waiting in {} for an opponent", lobby));
+                },
+                Some("start") => match Start::decode(&message) {
+                    Ok(start) => self.play_online(start)?,
+                    Err(err) => self.status = Some(err.to_string()),
+                },
+                Some("error") => {
+                    self.status = message.get("reason").and_then(Value::as_str).map(str::to_string);
+                },
+                // the welcome, and whatever was still on its way when the last match ended
+                _ => {},
+            }
+        }
+
+        if let Some(err) = lost {
+            self.disconnect(err.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// The selected field, when it's one that can be typed into.
+    fn field_mut(&mut self) -> Option<&mut String> {
+        match self.labels.get(self.selected)?.get_label() {
+            "Server" => Some(&mut self.online.server),
+            "Name" => Some(&mut self.online.name),
+            "Lobby" => Some(&mut self.online.lobby),
+            _ => None,
+        }
+    }
+
+    fn play_demo(&mut self) -> Result<()> {
+        self.draw_loading();
+
+        let mut renderer = Renderer::new(&mut self.rl, &self.thread, &mut self.audio, &self.resolver, self.settings)?;
+
+        renderer.start_demo();
+        renderer.run()?;
+
+        self.rl.set_window_title(&self.thread, "Tetris");
+
+        Ok(())
+    }
+
+    fn load_puzzles(&mut self) -> Result<()> {
+        let shapes = TShape::load(&self.resolver.resolve("shapes.tshape")?)?;
+
+        self.puzzles = Puzzle::load_pack(&self.resolver.resolve("puzzles/pack.txt")?, &shapes)?;
 
         Ok(())
     }
 
-    fn enter_selected(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    fn play_puzzle(&mut self, index: usize) -> Result<()> {
+        self.draw_loading();
+
+        let mut renderer = Renderer::new(&mut self.rl, &self.thread, &mut self.audio, &self.resolver, self.settings)?;
+
+        renderer.start_puzzle(&self.puzzles[index]);
+        renderer.run()?;
+
+        let solved = renderer.attempt.as_ref().is_some_and(|attempt| attempt.outcome == Some(Outcome::Solved));
+
+        Self::keep_hotkeys(&mut self.settings, &renderer.settings);
+
+        let name = &self.puzzles[index].name;
+
+        if solved && !self.config.solved.contains(name) {
+            self.config.solved.push(name.clone());
+        }
+
+        self.save_config();
+
+        Ok(())
+    }
+
+    /// The editor comes back after every test game until it's left with Q.
+    fn open_editor(&mut self) -> Result<()> {
+        let shapes = TShape::load(&self.resolver.resolve("shapes.tshape")?)?;
+        let mut editor = Editor::new(shapes);
+
+        while let Some(board) = editor.run(&mut self.rl, &self.thread)? {
+            self.play_game(Some(&board))?;
+        }
+
+        self.rl.set_window_title(&self.thread, "Tetris");
+
+        Ok(())
+    }
+
+    fn save_config(&mut self) {
+        self.config.mixer = self.settings.mixer;
+        self.config.fullscreen = self.settings.fullscreen;
+        self.config.effects = self.settings.effects;
+        self.config.lighting = self.settings.lighting;
+        self.config.camera = self.settings.camera;
+        self.config.low_effects = self.settings.low_effects;
+        self.config.frame_rate = self.settings.frame_rate;
+        self.config.hud = self.settings.hud;
+        self.config.finesse = self.settings.finesse;
+        self.config.bot = self.settings.bot;
+        self.config.versus = self.settings.versus;
+        self.config.race = self.settings.race;
+
+        error::recover(self.config.save());
+    }
+
+    fn enter_selected(&mut self) -> Result<()> {
+        if let Label::Puzzle { index, .. } = self.labels[self.selected] {
+            return self.play_puzzle(index);
+        }
+
         let label = self.labels[self.selected].get_label();
 
-        if label == "Play" {
-            self.play_game()?;
+        if label == "Continue" {
+            self.continue_game()?;
+        } else if label == "Play" {
+            self.play_game(None)?;
+        } else if label == "Versus" {
+            self.selected = 0;
+            self.title = "Versus";
+        } else if label == "Start" {
+            self.play_versus()?;
+        } else if label == "Garbage" {
+            self.selected = 0;
+            self.title = "Garbage";
+        } else if label == "Survival" {
+            self.play_cheese(Mode::Survival)?;
+        } else if label == "Dig race" {
+            self.play_cheese(Mode::Race(self.settings.race))?;
+        } else if label == "Race" {
+            self.settings.race = self.settings.race.next();
+        } else if label == "Online" {
+            self.selected = 0;
+            self.status = None;
+            self.title = "Online";
+        } else if label == "Connect" {
+            self.connect();
+        } else if label == "Join" {
+            self.join(self.online.lobby.trim().to_string());
+        } else if let Label::Field { label: "Open", value } = &self.labels[self.selected] {
+            self.join(value.clone());
+        } else if label == "Leave" {
+            self.send(Value::object([("type", "leave".into())]));
+
+            self.online.waiting = false;
+            self.online.refresh = 0.0;
+            self.status = None;
+        } else if label == "Rounds" {
+            self.settings.versus.rounds = self.settings.versus.rounds.next();
+        } else if label == "Player 2" {
+            self.settings.versus.rival = self.settings.versus.rival.next();
+        } else if label == "Editor" {
+            self.open_editor()?;
+        } else if label == "Puzzles" {
+            if self.puzzles.is_empty() {
+                error::recover(self.load_puzzles());
+            }
+
+            self.selected = 0;
+            self.title = "Puzzles";
         } else if label == "Smooth" {
             self.settings.smooth = !self.settings.smooth;
         } else if label == "3D mode" {
             self.settings.mode3d = !self.settings.mode3d;
+        } else if label == "Fullscreen" {
+            self.toggle_fullscreen();
+        } else if label == "Mute" {
+            self.settings.mixer.muted = !self.settings.mixer.muted;
         } else if label == "Settings" {
             self.selected = 0;
             self.title = "Settings";
+        } else if label == "Audio" {
+            self.selected = 0;
+            self.title = "Audio";
+        } else if label == "Graphics" {
+            self.selected = 0;
+            self.title = "Graphics";
+        } else if label == "Bot" {
+            self.selected = 0;
+            self.title = "Bot";
+        } else if label == "Speed" {
+            self.settings.bot.speed = self.settings.bot.speed.next();
+        } else if label == "Hints" {
+            self.settings.bot.hints = !self.settings.bot.hints;
+        } else if label == "Demo" {
+            self.settings.bot.demo = !self.settings.bot.demo;
+        } else if label == "Preset" {
+            self.settings.effects.preset = self.settings.effects.preset.next();
+        } else if label == "Lighting" {
+            self.settings.lighting = self.settings.lighting.next();
+        } else if label == "Frame rate" {
+            self.settings.frame_rate = self.settings.frame_rate.next();
+
+            layout::set_frame_rate(&mut self.rl, self.settings.frame_rate);
+        } else if label == "HUD" {
+            self.settings.hud = self.settings.hud.next();
+        } else if label == "Finesse" {
+            self.settings.finesse = !self.settings.finesse;
+        } else if label == "Low effects" {
+            self.settings.low_effects = !self.settings.low_effects;
+        } else if label == "Camera" {
+            self.settings.camera = self.settings.camera.next();
         } else if label == "Back" {
+            if self.title != "Settings" {
+                self.save_config();
+            }
+
+            if self.title == "Lobbies" {
+                self.online.connection = None;
+                self.online.waiting = false;
+                self.status = None;
+            }
+
             self.selected = 0;
-            self.title = "Tetris";
+            self.title = match self.title {
+                "Settings" | "Puzzles" | "Versus" | "Garbage" => "Tetris",
+                "Online" => "Versus",
+                "Lobbies" => "Online",
+                _ => "Settings",
+            };
         } else if label == "Exit" {
             self.should_close = true;
         }
@@ -301,17 +1072,137 @@ impl Menu {
         Ok(())
     }
 
+    /// Moves the selected slider to `value`, clamped to 0.0..=1.0.
+    fn set_slider(&mut self, value: f32) {
+        let value = value.clamp(0.0, 1.0);
+
+        match self.labels[self.selected].get_label() {
+            "Master" => self.settings.mixer.master = value,
+            "Music" => self.settings.mixer.music = value,
+            "SFX" => self.settings.mixer.sfx = value,
+            "Intensity" => self.settings.effects.intensity = value,
+            _ => {},
+        }
+    }
+
+    fn step_slider(&mut self, step: f32) {
+        if let Label::Slider { value, .. } = self.labels[self.selected] {
+            self.set_slider(((value + step) / SLIDER_STEP).round() * SLIDER_STEP);
+        }
+    }
+
     fn update_menu(&mut self) {
+        let mixer = self.settings.mixer;
+
         if self.title == "Settings" {
-            self.labels = vec![Label::Toggle { label: "3D mode", state: self.settings.mode3d }, Label::Toggle { label: "Smooth", state: self.settings.smooth }, Label::Button("Back")];
+            self.labels = vec![
+                Label::Toggle { label: "3D mode", state: self.settings.mode3d },
+                Label::Toggle { label: "Smooth", state: self.settings.smooth },
+                Label::Toggle { label: "Fullscreen", state: self.settings.fullscreen },
+                Label::Choice { label: "Frame rate", value: self.settings.frame_rate.name() },
+                Label::Toggle { label: "Finesse", state: self.settings.finesse },
+                Label::Button("Audio"),
+                Label::Button("Graphics"),
+                Label::Button("Bot"),
+                Label::Button("Back"),
+            ];
+        } else if self.title == "Audio" {
+            self.labels = vec![
+                Label::Slider { label: "Master", value: mixer.master },
+                Label::Slider { label: "Music", value: mixer.music },
+                Label::Slider { label: "SFX", value: mixer.sfx },
+                Label::Toggle { label: "Mute", state: mixer.muted },
+                Label::Button("Back"),
+            ];
+        } else if self.title == "Graphics" {
+            self.labels = vec![
+                Label::Choice { label: "Lighting", value: self.settings.lighting.name() },
+                Label::Choice { label: "Preset", value: self.settings.effects.preset.name() },
+                Label::Slider { label: "Intensity", value: self.settings.effects.intensity },
+                Label::Choice { label: "Camera", value: self.settings.camera.name() },
+                Label::Toggle { label: "Low effects", state: self.settings.low_effects },
+                Label::Choice { label: "HUD", value: self.settings.hud.name() },
+                Label::Button("Back"),
+            ];
+        } else if self.title == "Bot" {
+            self.labels = vec![
+                Label::Choice { label: "Speed", value: self.settings.bot.speed.name() },
+                Label::Toggle { label: "Hints", state: self.settings.bot.hints },
+                Label::Toggle { label: "Demo", state: self.settings.bot.demo },
+                Label::Button("Back"),
+            ];
+        } else if self.title == "Versus" {
+            self.labels = vec![
+                Label::Button("Start"),
+                Label::Choice { label: "Rounds", value: self.settings.versus.rounds.name() },
+                Label::Choice { label: "Player 2", value: self.settings.versus.rival.name() },
+                Label::Button("Online"),
+                Label::Button("Back"),
+            ];
+        } else if self.title == "Garbage" {
+            self.labels = vec![
+                Label::Button("Survival"),
+                Label::Button("Dig race"),
+                Label::Choice { label: "Race", value: self.settings.race.name() },
+                Label::Button("Back"),
+            ];
+        } else if self.title == "Online" {
+            self.labels = vec![
+                Label::Field { label: "Server", value: self.online.server.clone() },
+                Label::Field { label: "Name", value: self.online.name.clone() },
+                Label::Button("Connect"),
+                Label::Button("Back"),
+            ];
+        } else if self.title == "Lobbies" && self.online.waiting {
+            self.labels = vec![Label::Button("Leave"), Label::Button("Back")];
+        } else if self.title == "Lobbies" {
+            self.labels = [Label::Field { label: "Lobby", value: self.online.lobby.clone() }, Label::Button("Join")]
+                .into_iter()
+                .chain(self.online.lobbies.iter().map(|lobby| Label::Field { label: "Open", value: lobby.clone() }))
+                .chain([Label::Button("Back")])
+                .collect();
+        } else if self.title == "Puzzles" {
+            self.labels = self.puzzles
+                .iter()
+                .enumerate()
+                .map(|(index, puzzle)| Label::Puzzle { index, name: puzzle.name.clone(), solved: self.config.solved.contains(&puzzle.name) })
+                .chain([Label::Button("Back")])
+                .collect();
         } else if self.title == "Tetris" {
-            self.labels = vec![Label::Button("Play"), Label::Button("Settings"), Label::Button("Exit")];
+            self.labels = self.saved
+                .then_some(Label::Button("Continue"))
+                .into_iter()
+                .chain(["Play", "Versus", "Garbage", "Puzzles", "Editor", "Settings", "Exit"].map(Label::Button))
+                .collect();
         }
     }
 
-    fn handle_input(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    fn handle_input(&mut self) -> Result<()> {
+        let typing = self.field_mut().is_some();
+
+        // raylib-rs doesn't wrap the character queue, it's drained even when nothing is typed into
+        loop {
+            let char = unsafe { raylib::ffi::GetCharPressed() };
+
+            match char::from_u32(char as u32) {
+                Some(char) if char != '\0' => {
+                    if let Some(field) = self.field_mut().filter(|field| field.chars().count() < MAX_FIELD && !char.is_control()) {
+                        field.push(char);
+                    }
+                },
+                _ => break,
+            }
+        }
+
         if let Some(key) = self.rl.get_key_pressed() {
+            self.idle = 0.0;
+
             match key {
+                KeyboardKey::KEY_BACKSPACE => {
+                    if let Some(field) = self.field_mut() {
+                        field.pop();
+                    }
+                },
                 KeyboardKey::KEY_ENTER => {
                     self.enter_selected()?;
                 },
@@ -325,6 +1216,19 @@ impl Menu {
                         self.selected += 1;
                     }
                 },
+                KeyboardKey::KEY_LEFT => {
+                    self.step_slider(-SLIDER_STEP);
+                },
+                KeyboardKey::KEY_RIGHT => {
+                    self.step_slider(SLIDER_STEP);
+                },
+                KeyboardKey::KEY_M if !typing => {
+                    self.settings.mixer.muted = !self.settings.mixer.muted;
+                    self.save_config();
+                },
+                KeyboardKey::KEY_F11 => {
+                    self.toggle_fullscreen();
+                },
                 _ => {},
             }
         }
@@ -336,19 +1240,27 @@ impl Menu {
         Ok(())
     }
 
-    fn handle_mouse(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let mouse = self.rl.get_mouse_position();
+    fn handle_mouse(&mut self) -> Result<()> {
+        let mouse = Layout::new(&self.rl).to_virtual(self.rl.get_mouse_position());
+        let top = self.labels_top();
 
         for index in 0..self.labels.len() {
             let rec = Rectangle::new(
                 ((WIDTH / 2) - 150) as f32,
-                ((HEIGHT / 2) + (90 * index as i32)) as f32,
+                (top + (90 * index as i32)) as f32,
                 300.0,
                 60.0,
             );
 
             if rec.check_collision_point_rec(mouse) {
-                if self.rl.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON) {
+                if let Label::Slider { .. } = self.labels[index] {
+                    self.selected = index;
+
+                    // drag anywhere along the bar
+                    if self.rl.is_mouse_button_down(MouseButton::MOUSE_LEFT_BUTTON) {
+                        self.set_slider((mouse.x - ((WIDTH / 2) - 130) as f32) / 260.0);
+                    }
+                } else if self.rl.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON) {
                     self.enter_selected()?;
                 } else {
                     self.selected = index;
@@ -359,23 +1271,82 @@ impl Menu {
         Ok(())
     }
 
-    fn lock_size(&mut self) {
-        if self.rl.is_window_resized() {
-            self.rl.set_window_size(WIDTH, HEIGHT);
+    fn toggle_fullscreen(&mut self) {
+        self.settings.fullscreen = !self.settings.fullscreen;
+
+        layout::set_fullscreen(&mut self.rl, self.settings.fullscreen);
+        self.save_config();
+    }
+
+    /// Counts time without input, the attract demo starts once it's been long enough.
+    fn update_idle(&mut self) -> Result<()> {
+        let mouse = self.rl.get_mouse_position();
+
+        if mouse != self.mouse {
+            self.mouse = mouse;
+            self.idle = 0.0;
         }
+
+        self.idle += self.rl.get_frame_time();
+
+        if self.idle >= DEMO_DELAY && self.settings.bot.demo && self.title == "Tetris" {
+            self.play_demo()?;
+            self.idle = 0.0;
+        }
+
+        Ok(())
     }
 
-    pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn run(&mut self) -> Result<()> {
+        if let Some(address) = self.spectate.take() {
+            self.watch(&address)?;
+        }
+
         while !self.should_close {
-            self.draw()?;
-            self.lock_size();
+            self.draw();
             self.handle_input()?;
             self.handle_mouse()?;
+            self.update_idle()?;
+            self.update_online()?;
             self.update_menu();
         }
 
+        self.save_config();
+
         Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solved_puzzles_round_trip() {
+        let config = Config {
+            highscore: 1234,
+            solved: vec![String::from("Opener"), String::from("Hard ones 2")],
+            ..Config::default()
+        };
 
+        let decoded = Config::decode(&config.encode()).unwrap();
+
+        assert_eq!(decoded.highscore, 1234);
+        assert_eq!(decoded.solved, config.solved);
+    }
+
+    #[test]
+    fn cut_off_puzzle_name() {
+        let config = Config {
+            solved: vec![String::from("Opener")],
+            ..Config::default()
+        };
+
+        let mut bytes = config.encode();
+        let name = bytes.windows(6).position(|window| window == b"Opener").unwrap();
+
+        bytes.truncate(name + 3);
+
+        assert!(Config::decode(&bytes).is_err());
+    }
+}
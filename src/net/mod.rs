@@ -0,0 +1,426 @@
+use crate::error::{self, Error, Result};
+use crate::game::{Action, Event, Game};
+use crate::json::{self, Value};
+use crate::versus::{Rounds, Versus};
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// Both ends have to speak the same version, the server turns everyone else away.
+pub const PROTOCOL: u32 = 1;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Every machine steps the match by exactly this much, floating point gravity has to add up the same.
+pub const DELTA: f32 = 1.0 / 60.0;
+
+/// Ticks between board hashes, compared to catch a desync.
+const SYNC_EVERY: u64 = 60;
+
+/// A finished round stays on screen for this many ticks before both sides deal the next one.
+const INTERMISSION: u64 = 180;
+
+/// Most ticks caught up in one frame after waiting on the other side.
+const MAX_CATCH_UP: f32 = 8.0;
+
+pub fn kind(message: &Value) -> Option<&str> {
+    message.get("type").and_then(Value::as_str)
+}
+
+/// A line based JSON connection to the versus server.
+pub struct Connection {
+    stream: TcpStream,
+    messages: Receiver<Result<Value>>,
+}
+
+impl Connection {
+    /// Connects and says hello, the server answers with `welcome` or an error.
+    pub fn open(address: &str, name: &str) -> Result<Connection> {
+        let resolved = address
+            .to_socket_addrs()
+            .map_err(|err| Error::io(address, err))?
+            .next()
+            .ok_or_else(|| Error::Network(format!("no address for {}", address)))?;
+
+        let stream = TcpStream::connect_timeout(&resolved, CONNECT_TIMEOUT).map_err(|err| Error::io(address, err))?;
+        let reader = stream.try_clone().map_err(|err| Error::io(address, err))?;
+
+        // inputs are tiny and late ones stall the match
+        error::recover(stream.set_nodelay(true).map_err(|err| Error::io(address, err)));
+
+        let (sender, messages) = mpsc::channel();
+
+        thread::spawn(move || {
+            for message in json::lines(reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut connection = Connection {
+            stream,
+            messages,
+        };
+
+        connection.send(Value::object([
+            ("type", "hello".into()),
+            ("version", PROTOCOL.into()),
+            ("name", name.into()),
+        ]))?;
+
+        Ok(connection)
+    }
+
+    pub fn send(&mut self, message: Value) -> Result<()> {
+        writeln!(self.stream, "{}", message).map_err(|err| Error::Network(format!("failed to send: {}", err)))
+    }
+
+    /// None when nothing arrived yet.
+    pub fn receive(&mut self) -> Result<Option<Value>> {
+        match self.messages.try_recv() {
+            Ok(message) => message.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(Error::Network(String::from("the server closed the connection"))),
+        }
+    }
+}
+
+/// What the server sends both players once their lobby is full.
+pub struct Start {
+    pub seed: u64,
+    /// Which board is ours, side 0 plays on the left.
+    pub side: usize,
+    pub names: [String; 2],
+    pub rounds: Rounds,
+    /// Ticks between pressing a key and the action landing on both machines.
+    pub delay: u64,
+}
+
+impl Start {
+    pub fn decode(message: &Value) -> Result<Start> {
+        let field = |name: &str| message.get(name).ok_or_else(|| Error::Network(format!("start without {}", name)));
+        let bad = |name: &str| Error::Network(format!("bad {} in start", name));
+
+        let names = field("names")?.as_array().filter(|names| names.len() == 2).ok_or_else(|| bad("names"))?;
+        let name = |index: usize| names[index].as_str().map(str::to_string).ok_or_else(|| bad("names"));
+
+        Ok(Start {
+            seed: field("seed")?.as_str().and_then(|seed| seed.parse().ok()).ok_or_else(|| bad("seed"))?,
+            side: field("side")?.as_i64().filter(|side| (0..2).contains(side)).ok_or_else(|| bad("side"))? as usize,
+            names: [name(0)?, name(1)?],
+            rounds: field("rounds")?.as_i64().and_then(|rounds| Rounds::from_index(rounds as u8)).ok_or_else(|| bad("rounds"))?,
+            delay: field("delay")?.as_i64().filter(|delay| (0..=60).contains(delay)).ok_or_else(|| bad("delay"))? as u64,
+        })
+    }
+}
+
+/// A versus match in lockstep. Both machines simulate both boards from the same seed, so only the
+/// inputs travel, each tagged with the tick it lands on. A tick only runs once both sides' inputs
+/// for it are in, and the local ones are sent `delay` ticks ahead so that rarely has to wait.
+pub struct Session {
+    connection: Connection,
+    pub side: usize,
+    delay: u64,
+    /// Next tick to simulate.
+    tick: u64,
+    /// Next tick to send our inputs for.
+    sent: u64,
+    /// Pressed since the last send.
+    pending: Vec<Action>,
+    inputs: [BTreeMap<u64, Vec<Action>>; 2],
+    hashes: [BTreeMap<u64, u64>; 2],
+    time: f32,
+    /// Tick the current round ended on.
+    decided: Option<u64>,
+    /// The opponent is gone, what they sent before that still gets played.
+    left: bool,
+    /// Why the match stopped early, the opponent left, the connection broke or the boards disagree.
+    pub ended: Option<String>,
+}
+
+impl Session {
+    /// The match itself is `Versus::seeded` from the same start message.
+    pub fn new(connection: Connection, start: &Start) -> Session {
+        Session {
+            connection,
+            side: start.side,
+            delay: start.delay,
+            tick: 0,
+            sent: 0,
+            pending: Vec::new(),
+            inputs: [BTreeMap::new(), BTreeMap::new()],
+            hashes: [BTreeMap::new(), BTreeMap::new()],
+            time: 0.0,
+            decided: None,
+            left: false,
+            ended: None,
+        }
+    }
+
+    /// Hands the connection back for the lobby, after telling the server we're done.
+    pub fn into_connection(mut self) -> Connection {
+        error::recover(self.connection.send(Value::object([("type", "leave".into())])));
+
+        self.connection
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn queue(&mut self, action: Action) {
+        self.pending.push(action);
+    }
+
+    /// Our actions were sent but haven't landed yet.
+    pub fn in_flight(&self) -> bool {
+        !self.pending.is_empty() || self.inputs[self.side].values().any(|actions| !actions.is_empty())
+    }
+
+    fn end(&mut self, reason: impl Into<String>) {
+        if self.ended.is_none() {
+            self.ended = Some(reason.into());
+        }
+    }
+
+    fn receive(&mut self) -> Result<()> {
+        let other = 1 - self.side;
+
+        while let Some(message) = self.connection.receive()? {
+            let tick = message.get("tick").and_then(Value::as_i64).map(|tick| tick as u64);
+
+            match (kind(&message), tick) {
+                (Some("input"), Some(tick)) => {
+                    let actions = message
+                        .get("actions")
+                        .and_then(Value::as_array)
+                        .ok_or_else(|| Error::Network(String::from("input without actions")))?
+                        .iter()
+                        .map(|action| action.as_str().and_then(Action::from_name).ok_or_else(|| Error::Network(format!("unknown action {}", action))))
+                        .collect::<Result<Vec<Action>>>()?;
+
+                    self.inputs[other].insert(tick, actions);
+                },
+                (Some("sync"), Some(tick)) => {
+                    // hashes don't fit in a JSON number either
+                    let hash = message.get("hash").and_then(Value::as_str).and_then(|hash| hash.parse().ok());
+
+                    self.hashes[other].insert(tick, hash.ok_or_else(|| Error::Network(String::from("sync without a hash")))?);
+                },
+                (Some("left"), _) => self.left = true,
+                (Some("error"), _) => {
+                    let reason = message.get("reason").and_then(Value::as_str).unwrap_or("unknown");
+
+                    return Err(Error::Network(format!("server error: {}", reason)));
+                },
+                _ => return Err(Error::Network(format!("unexpected message {}", message))),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every tick gets an input message, most of them empty, so the other side knows it can go on.
+    fn send_inputs(&mut self) -> Result<()> {
+        while self.sent <= self.tick + self.delay {
+            let actions = if self.sent == self.tick + self.delay { std::mem::take(&mut self.pending) } else { Vec::new() };
+
+            self.connection.send(Value::object([
+                ("type", "input".into()),
+                ("tick", Value::Number(self.sent as f64)),
+                ("actions", actions.iter().map(Action::name).collect::<Vec<&str>>().into()),
+            ]))?;
+
+            self.inputs[self.side].insert(self.sent, actions);
+            self.sent += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the match on by `delta` seconds, as far as the inputs that arrived allow, and returns
+    /// what happened on each board. `player` is the left board.
+    pub fn advance(&mut self, player: &mut Game, versus: &mut Versus, delta: f32) -> [Vec<Event>; 2] {
+        let mut events = [Vec::new(), Vec::new()];
+
+        if self.ended.is_some() {
+            return events;
+        }
+
+        if let Err(err) = self.receive() {
+            error::warn(&err);
+            self.end("Connection lost");
+
+            return events;
+        }
+
+        self.time = (self.time + delta).min(DELTA * MAX_CATCH_UP);
+
+        while self.time >= DELTA && self.ended.is_none() {
+            if let Err(err) = self.send_inputs() {
+                error::warn(&err);
+                self.end("Connection lost");
+
+                break;
+            }
+
+            let Some(theirs) = self.inputs[1 - self.side].remove(&self.tick) else {
+                if self.left {
+                    self.end("Opponent left");
+                }
+
+                break;
+            };
+
+            let ours = self.inputs[self.side].remove(&self.tick).unwrap_or_default();
+            let inputs = if self.side == 0 { [ours, theirs] } else { [theirs, ours] };
+
+            self.step(player, versus, inputs, &mut events);
+            self.time -= DELTA;
+            self.tick += 1;
+        }
+
+        events
+    }
+
+    fn step(&mut self, player: &mut Game, versus: &mut Versus, inputs: [Vec<Action>; 2], events: &mut [Vec<Event>; 2]) {
+        if versus.round.is_some() {
+            // both sides count the same ticks, so they deal the next round together
+            if versus.winner().is_none() && self.decided.is_some_and(|decided| self.tick >= decided + INTERMISSION) {
+                versus.next_round(player);
+                self.decided = None;
+            }
+
+            return;
+        }
+
+        for (side, actions) in inputs.into_iter().enumerate() {
+            let game = if side == 0 { &mut *player } else { &mut versus.rival };
+
+            for action in actions {
+                game.apply(action);
+            }
+        }
+
+        player.update(DELTA);
+        versus.rival.update(DELTA);
+
+        let happened = [player.take_events(), versus.rival.take_events()];
+
+        versus.update(player, [&happened[0], &happened[1]]);
+
+        if versus.round.is_some() {
+            self.decided = Some(self.tick);
+        }
+
+        for (events, happened) in events.iter_mut().zip(happened) {
+            events.extend(happened);
+        }
+
+        if self.tick.is_multiple_of(SYNC_EVERY) {
+            self.sync(player, &versus.rival);
+        }
+    }
+
+    /// Sums up both boards, equal on both machines as long as they agree. It's FNV-1a over fixed
+    /// width little endian numbers, so builds and platforms that differ still compare.
+    pub fn hash(games: [&Game; 2]) -> u64 {
+        let mut hash = Fnv::new();
+
+        for game in games {
+            for line in game.lines() {
+                hash.write(&line.iter().map(|block| *block as u8).collect::<Vec<u8>>());
+            }
+
+            for position in game.shape() {
+                hash.number(position.x as u64);
+                hash.number(position.y as u64);
+            }
+
+            hash.number(game.piece_index() as u64);
+            hash.number(game.rng().state());
+            hash.number(game.score.points as u64);
+            hash.number(game.over as u64);
+        }
+
+        hash.0
+    }
+
+    /// Sends our hash of this tick and compares it with theirs once both are in, a mismatch means
+    /// the machines disagree and the match can't be trusted any more.
+    fn sync(&mut self, player: &Game, rival: &Game) {
+        let hash = Self::hash([player, rival]);
+
+        self.hashes[self.side].insert(self.tick, hash);
+
+        error::recover(self.connection.send(Value::object([
+            ("type", "sync".into()),
+            ("tick", Value::Number(self.tick as f64)),
+            ("hash", Value::String(hash.to_string())),
+        ])));
+
+        let [ours, theirs] = &mut self.hashes;
+
+        while let Some((&tick, &hash)) = ours.first_key_value() {
+            let Some(other) = theirs.remove(&tick) else {
+                break;
+            };
+
+            ours.remove(&tick);
+
+            if hash != other {
+                error::warn(&Error::Network(format!("the boards went out of sync at tick {}", tick)));
+                self.end("Out of sync");
+
+                return;
+            }
+        }
+
+        // ours can run ahead of theirs, theirs can't run ahead of ours for long
+        theirs.retain(|tick, _| *tick > self.tick.saturating_sub(SYNC_EVERY * 10));
+    }
+}
+
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn number(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+    use crate::TShape;
+
+    #[test]
+    fn hash_is_pinned() {
+        let shapes = TShape::fixture();
+        let mut player = Game::new(shapes.clone(), Rng::new(1));
+        let rival = Game::new(shapes, Rng::new(2));
+
+        player.apply(Action::HardDrop);
+
+        // the other end may be another build or platform, the hash can't depend on either
+        assert_eq!(Session::hash([&player, &rival]), 13939872861536702380);
+    }
+}
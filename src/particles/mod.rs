@@ -0,0 +1,192 @@
+use raylib::prelude::*;
+
+/// The simulation runs at a fixed rate so the same events always play out the same way.
+const STEP: f32 = 1.0 / 120.0;
+
+/// Never run more than this many steps in one frame, after a hitch the debris just slows down.
+const MAX_STEPS: usize = 8;
+
+const GRAVITY: f32 = -120.0;
+
+/// Height of the table surface and its extent, crates past the edges fall to the floor.
+const TABLE_TOP: f32 = 2.5;
+const TABLE_X: (f32, f32) = (-45.0, 25.0);
+const TABLE_Z: f32 = 40.0;
+
+/// Velocity kept after hitting the table, and how much sliding is lost per bounce.
+const RESTITUTION: f32 = 0.3;
+const FRICTION: f32 = 0.7;
+
+/// Bodies are dropped once they fall this far or live this long.
+const FLOOR: f32 = -80.0;
+const LIFETIME: f32 = 6.0;
+
+/// Small xorshift generator, seeded from the event so replays see the same debris.
+struct Noise(u64);
+
+impl Noise {
+    fn new(seed: u64) -> Noise {
+        // zero would get stuck, the constant only has to be odd
+        Noise(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in `min..max`.
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next()
+    }
+}
+
+pub struct Body {
+    pub position: Vector3,
+    velocity: Vector3,
+    pub axis: Vector3,
+    /// Degrees around `axis`, which is what draw_model_ex takes.
+    pub angle: f32,
+    spin: f32,
+    age: f32,
+    /// Seconds before the body starts moving, staggers the collapse from the top down.
+    delay: f32,
+}
+
+impl Body {
+    fn step(&mut self) {
+        if self.delay > 0.0 {
+            self.delay -= STEP;
+            return;
+        }
+
+        self.age += STEP;
+        self.velocity.y += GRAVITY * STEP;
+        self.position += self.velocity.scale_by(STEP);
+        self.angle += self.spin * STEP;
+
+        let over_table = (TABLE_X.0..=TABLE_X.1).contains(&self.position.x) && self.position.z.abs() <= TABLE_Z;
+
+        if over_table && self.position.y < TABLE_TOP && self.velocity.y < 0.0 {
+            self.position.y = TABLE_TOP;
+            self.velocity.y *= -RESTITUTION;
+            self.velocity.x *= FRICTION;
+            self.velocity.z *= FRICTION;
+            self.spin *= FRICTION;
+        }
+    }
+
+    fn is_dead(&self) -> bool {
+        self.position.y < FLOOR || self.age > LIFETIME
+    }
+}
+
+/// Loose crates from line clears and the game over collapse.
+pub struct Particles {
+    bodies: Vec<Body>,
+    accumulator: f32,
+    /// Counts spawns so two identical clears in one game still differ.
+    spawned: u64,
+    /// Set once the stack collapsed, the renderer stops drawing the board then.
+    pub collapsed: bool,
+    pub enabled: bool,
+}
+
+impl Particles {
+    pub fn new(enabled: bool) -> Particles {
+        Particles {
+            bodies: Vec::new(),
+            accumulator: 0.0,
+            spawned: 0,
+            collapsed: false,
+            enabled,
+        }
+    }
+
+    fn noise(&mut self, position: Vector3) -> Noise {
+        self.spawned += 1;
+
+        let cell = (position.y as i64 * 31 + position.z as i64) as u64;
+
+        Noise::new(self.spawned.wrapping_mul(1_000_003) ^ cell)
+    }
+
+    fn spawn(&mut self, position: Vector3, velocity: Vector3, delay: f32) {
+        let mut noise = self.noise(position);
+
+        let axis = Vector3::new(noise.range(-1.0, 1.0), noise.range(-1.0, 1.0), noise.range(-1.0, 1.0));
+
+        self.bodies.push(Body {
+            position,
+            velocity,
+            axis: if axis.length() > 0.01 { axis.normalized() } else { Vector3::new(0.0, 1.0, 0.0) },
+            angle: 0.0,
+            spin: noise.range(-540.0, 540.0),
+            age: 0.0,
+            delay,
+        });
+    }
+
+    /// Crates of a cleared row burst outward, away from the middle of the board and towards the camera.
+    pub fn burst(&mut self, positions: &[Vector3]) {
+        if !self.enabled {
+            return;
+        }
+
+        for position in positions {
+            let mut noise = self.noise(*position);
+
+            let velocity = Vector3::new(
+                noise.range(10.0, 35.0),
+                noise.range(25.0, 55.0),
+                position.z * 2.5 + noise.range(-15.0, 15.0),
+            );
+
+            self.spawn(*position, velocity, 0.0);
+        }
+    }
+
+    /// The whole stack topples, the higher a crate the sooner it goes.
+    pub fn collapse(&mut self, positions: &[Vector3]) {
+        if !self.enabled {
+            return;
+        }
+
+        let top = positions.iter().map(|position| position.y).fold(0.0, f32::max);
+
+        for position in positions {
+            let mut noise = self.noise(*position);
+
+            let velocity = Vector3::new(
+                noise.range(-5.0, 20.0),
+                noise.range(0.0, 15.0),
+                position.z * 1.5 + noise.range(-10.0, 10.0),
+            );
+
+            self.spawn(*position, velocity, (top - position.y) * 0.01);
+        }
+
+        self.collapsed = true;
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        self.accumulator = (self.accumulator + delta).min(STEP * MAX_STEPS as f32);
+
+        while self.accumulator >= STEP {
+            self.accumulator -= STEP;
+
+            for body in &mut self.bodies {
+                body.step();
+            }
+        }
+
+        self.bodies.retain(|body| !body.is_dead());
+    }
+
+    pub fn bodies(&self) -> &[Body] {
+        &self.bodies
+    }
+}
@@ -0,0 +1,250 @@
+use crate::assets::Resolver;
+use crate::error::{self, Error, Result};
+
+use raylib::prelude::*;
+
+use std::time::SystemTime;
+use std::fs;
+
+/// Seconds between two checks of the shader files for changes.
+const RELOAD_INTERVAL: f32 = 1.0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Preset {
+    None,
+    Posterize,
+    Crt,
+    Bloom,
+    Chromatic,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [Preset::None, Preset::Posterize, Preset::Crt, Preset::Bloom, Preset::Chromatic];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::None => "None",
+            Preset::Posterize => "Posterize",
+            Preset::Crt => "CRT",
+            Preset::Bloom => "Bloom",
+            Preset::Chromatic => "Chromatic",
+        }
+    }
+
+    /// Fragment shaders applied in order.
+    fn passes(&self) -> &'static [&'static str] {
+        match self {
+            Preset::None => &[],
+            Preset::Posterize => &["shaders/posterize.fs"],
+            Preset::Crt => &["shaders/bloom.fs", "shaders/chromatic.fs", "shaders/crt.fs"],
+            Preset::Bloom => &["shaders/bloom.fs"],
+            Preset::Chromatic => &["shaders/chromatic.fs"],
+        }
+    }
+
+    pub fn next(&self) -> Preset {
+        let index = Preset::ALL.iter().position(|preset| preset == self).unwrap_or(0);
+
+        Preset::ALL[(index + 1) % Preset::ALL.len()]
+    }
+
+    pub fn from_index(index: u8) -> Option<Preset> {
+        Preset::ALL.get(index as usize).copied()
+    }
+
+    pub fn index(&self) -> u8 {
+        Preset::ALL.iter().position(|preset| preset == self).unwrap_or(0) as u8
+    }
+}
+
+/// The post processing settings, `intensity` is in the 0.0..=1.0 range.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Effects {
+    pub preset: Preset,
+    pub intensity: f32,
+}
+
+impl Default for Effects {
+    fn default() -> Effects {
+        Effects {
+            preset: Preset::Posterize,
+            intensity: 0.5,
+        }
+    }
+}
+
+struct Pass {
+    path: String,
+    shader: Shader,
+    modified: Option<SystemTime>,
+    resolution: i32,
+    intensity: i32,
+}
+
+impl Pass {
+    fn load(rl: &mut RaylibHandle, thread: &RaylibThread, path: String, default: u32) -> Result<Pass> {
+        let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+        let shader = rl.load_shader(thread, None, Some(&path)).map_err(|err| Error::decode(&path, err))?;
+
+        // raylib falls back to its default shader when compilation fails
+        if shader.id == default {
+            return Err(Error::decode(&path, "shader failed to compile, see the log above"));
+        }
+
+        Ok(Pass {
+            resolution: shader.get_shader_location("resolution"),
+            intensity: shader.get_shader_location("intensity"),
+            path,
+            shader,
+            modified,
+        })
+    }
+
+    fn set_uniforms(&mut self, width: i32, height: i32, effects: &Effects) {
+        self.shader.set_shader_value(self.resolution, Vector2::new(width as f32, height as f32));
+        self.shader.set_shader_value(self.intensity, effects.intensity);
+    }
+}
+
+/// Runs the scene through the preset's fragment shaders, ping-ponging between two render textures.
+pub struct Chain {
+    passes: Vec<Pass>,
+    targets: Vec<RenderTexture2D>,
+    output: Option<usize>,
+    default: u32,
+    timer: f32,
+}
+
+impl Chain {
+    pub fn load(rl: &mut RaylibHandle, thread: &RaylibThread, resolver: &Resolver, preset: Preset) -> Result<Chain> {
+        // there is no binding for the default shader, an empty load hands it back
+        let default = rl.load_shader(thread, None, None).map_err(Error::Device)?.id;
+
+        let mut passes = Vec::new();
+
+        for name in preset.passes() {
+            passes.push(Pass::load(rl, thread, resolver.resolve(name)?, default)?);
+        }
+
+        Ok(Chain {
+            passes,
+            targets: Vec::new(),
+            output: None,
+            default,
+            timer: 0.0,
+        })
+    }
+
+    /// Reloads every shader whose file changed on disk, or all of them if `force` is set.
+    pub fn reload(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread, force: bool) {
+        self.timer += rl.get_frame_time();
+
+        if !force && self.timer < RELOAD_INTERVAL {
+            return;
+        }
+
+        self.timer = 0.0;
+
+        for pass in &mut self.passes {
+            let modified = fs::metadata(&pass.path).and_then(|meta| meta.modified()).ok();
+
+            if force || modified != pass.modified {
+                // keep the old shader if the new one doesn't compile, but don't retry until it changes again
+                pass.modified = modified;
+
+                if let Some(reloaded) = error::recover(Pass::load(rl, thread, pass.path.clone(), self.default)) {
+                    *pass = reloaded;
+                    eprintln!("[INFO] reloaded {}", pass.path);
+                }
+            }
+        }
+    }
+
+    /// Intermediate passes need render textures the size of the scene.
+    pub fn resize(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread, width: u32, height: u32) -> Result<()> {
+        let needed = self.passes.len().saturating_sub(1).min(2);
+
+        let matches = self.targets
+            .iter()
+            .all(|target| target.texture.width == width as i32 && target.texture.height == height as i32);
+
+        if self.targets.len() == needed && matches {
+            return Ok(());
+        }
+
+        self.targets.clear();
+
+        for _ in 0..needed {
+            self.targets.push(rl.load_render_texture(thread, width, height).map_err(Error::Device)?);
+        }
+
+        Ok(())
+    }
+
+    /// Runs all passes but the last one, which is drawn straight to the screen by `draw`.
+    pub fn apply(&mut self, drawer: &mut RaylibDrawHandle, thread: &RaylibThread, scene: &RenderTexture2D, effects: &Effects) {
+        let width = scene.texture.width;
+        let height = scene.texture.height;
+        let source = Rectangle::new(0.0, 0.0, width as f32, -height as f32);
+
+        self.output = None;
+
+        let count = self.passes.len().saturating_sub(1);
+
+        // resize failed, show the scene with just the last pass rather than nothing
+        if self.targets.len() < count.min(2) {
+            return;
+        }
+
+        for (index, pass) in self.passes.iter_mut().take(count).enumerate() {
+            let target = index % 2;
+            let (front, back) = self.targets.split_at_mut(1);
+
+            let (output, input) = match target {
+                0 => (&mut front[0], back.first()),
+                _ => (&mut back[0], front.first()),
+            };
+
+            let input = match self.output {
+                Some(_) => input.map(|input| input.texture()),
+                None => Some(scene.texture()),
+            };
+
+            pass.set_uniforms(width, height, effects);
+
+            let mut texture_drawer = drawer.begin_texture_mode(thread, output);
+            texture_drawer.clear_background(Color::BLACK);
+
+            if let Some(input) = input {
+                let mut shader = texture_drawer.begin_shader_mode(&pass.shader);
+
+                shader.draw_texture_rec(input, source, Vector2::zero(), Color::WHITE);
+            }
+
+            self.output = Some(target);
+        }
+    }
+
+    /// Draws the result of `apply` through the last pass into `dest`.
+    pub fn draw(&mut self, drawer: &mut impl RaylibDraw, scene: &RenderTexture2D, effects: &Effects, dest: Rectangle) {
+        let input = match self.output {
+            Some(index) => self.targets[index].texture(),
+            None => scene.texture(),
+        };
+
+        let source = Rectangle::new(0.0, 0.0, input.width as f32, -input.height as f32);
+
+        match self.passes.last_mut() {
+            Some(pass) => {
+                pass.set_uniforms(input.width, input.height, effects);
+
+                let mut shader = drawer.begin_shader_mode(&pass.shader);
+
+                shader.draw_texture_pro(input, source, dest, Vector2::zero(), 0.0, Color::WHITE);
+            },
+            None => {
+                drawer.draw_texture_pro(input, source, dest, Vector2::zero(), 0.0, Color::WHITE);
+            },
+        }
+    }
+}
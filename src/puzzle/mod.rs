@@ -0,0 +1,172 @@
+use crate::board::Board;
+use crate::error::{Error, Result};
+use crate::game::{Event, Game};
+use crate::TShape;
+
+use std::fs;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Goal {
+    /// Clear at least this many lines in total.
+    Lines(u32),
+    /// Clear lines until the board is empty.
+    PerfectClear,
+    /// A spin that clears at least this many lines at once.
+    Spin(u32),
+}
+
+impl Goal {
+    fn parse(text: &str) -> Option<Goal> {
+        let mut words = text.split_whitespace();
+
+        let goal = match words.next()? {
+            "lines" => Goal::Lines(words.next()?.parse().ok()?),
+            "perfect" => Goal::PerfectClear,
+            "spin" => Goal::Spin(words.next()?.parse().ok()?),
+            _ => return None,
+        };
+
+        words.next().is_none().then_some(goal)
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Goal::Lines(1) => String::from("Clear a line"),
+            Goal::Lines(lines) => format!("Clear {} lines", lines),
+            Goal::PerfectClear => String::from("Perfect clear"),
+            Goal::Spin(0) => String::from("Spin"),
+            Goal::Spin(1) => String::from("Spin single"),
+            Goal::Spin(2) => String::from("Spin double"),
+            Goal::Spin(lines) => format!("Spin clearing {} lines", lines),
+        }
+    }
+}
+
+pub struct Puzzle {
+    pub name: String,
+    pub goal: Goal,
+    pub board: Board,
+}
+
+impl Puzzle {
+    /// A pack is a list of puzzles, each starting with `puzzle: <name>` followed by a `goal:`
+    /// line and its board in the editor's text format, the queue is the whole piece sequence.
+    pub fn load_pack(file: &str, shapes: &TShape) -> Result<Vec<Puzzle>> {
+        let source = fs::read_to_string(file).map_err(|err| Error::io(file, err))?;
+
+        let error = |line: usize, reason: String| Error::BoardParse {
+            path: file.to_string(),
+            line: line + 1,
+            reason,
+        };
+
+        let lines: Vec<(usize, &str)> = source.lines().enumerate().collect();
+        let starts: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, line))| line.trim_start().starts_with("puzzle:"))
+            .map(|(start, _)| start)
+            .collect();
+
+        let mut puzzles = Vec::new();
+
+        for (index, start) in starts.iter().enumerate() {
+            let end = starts.get(index + 1).copied().unwrap_or(lines.len());
+            let (line, header) = lines[*start];
+
+            let name = header.trim_start().trim_start_matches("puzzle:").trim();
+
+            let (goal_line, goal) = lines[*start + 1..end]
+                .iter()
+                .find_map(|(line, text)| Some((*line, text.trim_start().strip_prefix("goal:")?)))
+                .ok_or_else(|| error(line, format!("puzzle `{}` has no goal", name)))?;
+
+            let goal = Goal::parse(goal).ok_or_else(|| error(goal_line, format!("unknown goal `{}`", goal.trim())))?;
+
+            let board = Board::parse_lines(
+                file,
+                lines[*start + 1..end].iter().copied().filter(|(line, _)| *line != goal_line),
+                shapes,
+            )?;
+
+            if board.queue.is_empty() {
+                return Err(error(line, format!("puzzle `{}` has no pieces", name)));
+            }
+
+            puzzles.push(Puzzle {
+                name: name.to_string(),
+                goal,
+                board,
+            });
+        }
+
+        Ok(puzzles)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Outcome {
+    Solved,
+    Failed,
+}
+
+/// Follows a game played on a puzzle's board, it fails once the sequence runs out.
+pub struct Attempt {
+    pub board: Board,
+    pub goal: Goal,
+    locked: usize,
+    lines: u32,
+    pub outcome: Option<Outcome>,
+}
+
+impl Attempt {
+    pub fn new(puzzle: &Puzzle) -> Attempt {
+        Attempt {
+            board: puzzle.board.clone(),
+            goal: puzzle.goal,
+            locked: 0,
+            lines: 0,
+            outcome: None,
+        }
+    }
+
+    pub fn retry(&mut self) {
+        self.locked = 0;
+        self.lines = 0;
+        self.outcome = None;
+    }
+
+    pub fn pieces_left(&self) -> usize {
+        self.board.queue.len().saturating_sub(self.locked)
+    }
+
+    /// Takes every event of a frame at once, a lock and the clear it caused arrive together.
+    pub fn update(&mut self, events: &[Event], game: &Game) {
+        if self.outcome.is_some() {
+            return;
+        }
+
+        for event in events {
+            match (*event, self.goal) {
+                (Event::Lock, _) => self.locked += 1,
+                (Event::Clear(lines), Goal::Lines(goal)) if self.lines + lines >= goal => self.outcome = Some(Outcome::Solved),
+                (Event::Clear(_), Goal::PerfectClear) if game.stack_height() == 0 => self.outcome = Some(Outcome::Solved),
+                (Event::Spin(lines), Goal::Spin(goal)) if lines >= goal => self.outcome = Some(Outcome::Solved),
+                (Event::GameOver, _) => self.outcome = Some(Outcome::Failed),
+                _ => {},
+            }
+
+            if let Event::Clear(lines) = event {
+                self.lines += lines;
+            }
+
+            if self.outcome.is_some() {
+                return;
+            }
+        }
+
+        if self.pieces_left() == 0 {
+            self.outcome = Some(Outcome::Failed);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::error::{Error, Result};
+use crate::game::Action;
+
+use std::fs;
+
+/// Enough to play a game again, the seed deals the same pieces and the actions land on the same
+/// ticks. Gravity runs on a fixed step, so only the headless runner's games replay exactly.
+/// The shapes aren't kept, a game is only replayed right with the shapes file it was played with.
+pub struct Recording {
+    pub seed: u64,
+    /// Updates the game ran for.
+    pub ticks: u64,
+    /// The level the game started at.
+    pub level: u32,
+    /// Board file the game started from.
+    pub board: Option<String>,
+    pub actions: Vec<(u64, Action)>,
+}
+
+/// Every game starts with a `game <seed> <ticks> <level> [board]` line followed by one
+/// `<tick> <action>` line per action, `;` starts a comment.
+pub fn parse(file: &str, source: &str) -> Result<Vec<Recording>> {
+    let mut recordings: Vec<Recording> = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let error = |reason: String| Error::ReplayParse {
+            path: file.to_string(),
+            line: index + 1,
+            reason,
+        };
+
+        let number = |word: Option<&str>, name: &str| -> Result<u64> {
+            let word = word.ok_or_else(|| error(format!("missing {}", name)))?;
+
+            word.parse().map_err(|_| error(format!("bad {} `{}`", name, word)))
+        };
+
+        let mut words = line.split(';').next().unwrap_or("").split_whitespace();
+
+        match words.next() {
+            None => continue,
+            Some("game") => recordings.push(Recording {
+                seed: number(words.next(), "seed")?,
+                ticks: number(words.next(), "tick count")?,
+                level: number(words.next(), "level")?.try_into().map_err(|_| error(String::from("level out of range")))?,
+                board: words.next().map(String::from),
+                actions: Vec::new(),
+            }),
+            tick => {
+                let tick = number(tick, "tick")?;
+                let name = words.next().ok_or_else(|| error(String::from("missing action")))?;
+                let action = Action::from_name(name).ok_or_else(|| error(format!("unknown action `{}`", name)))?;
+
+                let recording = recordings.last_mut().ok_or_else(|| error(String::from("action before the first game")))?;
+
+                if recording.actions.last().is_some_and(|(last, _)| *last > tick) {
+                    return Err(error(String::from("actions out of order")));
+                }
+
+                recording.actions.push((tick, action));
+            },
+        }
+
+        if words.next().is_some() {
+            return Err(error(String::from("trailing words")));
+        }
+    }
+
+    Ok(recordings)
+}
+
+pub fn load(file: &str) -> Result<Vec<Recording>> {
+    let source = fs::read_to_string(file).map_err(|err| Error::io(file, err))?;
+
+    parse(file, &source)
+}
+
+pub fn to_text(recordings: &[Recording]) -> String {
+    let mut text = String::new();
+
+    for recording in recordings {
+        text += &format!("game {} {} {}", recording.seed, recording.ticks, recording.level);
+
+        if let Some(board) = &recording.board {
+            text += &format!(" {}", board);
+        }
+
+        text += "\n";
+
+        for (tick, action) in &recording.actions {
+            text += &format!("{} {}\n", tick, action.name());
+        }
+    }
+
+    text
+}
+
+pub fn save(file: &str, recordings: &[Recording]) -> Result<()> {
+    fs::write(file, to_text(recordings)).map_err(|err| Error::io(file, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_and_board_round_trip() {
+        let text = "game 7 120 5 boards/tsd.txt\n3 left\n90 hard_drop\ngame 8 60 1\n";
+        let recordings = parse("replay", text).unwrap();
+
+        assert_eq!(recordings[0].level, 5);
+        assert_eq!(recordings[0].board.as_deref(), Some("boards/tsd.txt"));
+        assert_eq!(recordings[1].level, 1);
+        assert_eq!(recordings[1].board, None);
+        assert_eq!(to_text(&recordings), text);
+    }
+
+    #[test]
+    fn rejects_a_game_line_without_a_level() {
+        assert!(parse("replay", "game 7 120\n").is_err());
+    }
+}
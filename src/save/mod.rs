@@ -0,0 +1,149 @@
+use crate::assets;
+use crate::error::{Error, Result};
+use crate::game::Game;
+use crate::json::Value;
+use crate::rng::Rng;
+use crate::TShape;
+
+use std::io::ErrorKind;
+use std::fs;
+
+/// Goes up whenever the saved state changes shape, older saves are turned down instead of misread.
+const VERSION: u32 = 1;
+
+const FILE: &str = "save.json";
+
+pub fn exists() -> bool {
+    assets::config_path(FILE).is_ok_and(|path| path.is_file())
+}
+
+/// Written next to the save and renamed over it, a crash halfway leaves the old save whole.
+pub fn store(game: &Game) -> Result<()> {
+    let path = assets::config_path(FILE)?;
+    let temp = path.with_extension("json.tmp");
+
+    fs::write(&temp, encode(game).to_string()).map_err(|err| Error::io(&temp, err))?;
+    fs::rename(&temp, &path).map_err(|err| Error::io(path, err))
+}
+
+/// The saved game, dealt from `shapes` like the one that was saved.
+pub fn load(shapes: &TShape) -> Result<Game> {
+    let path = assets::config_path(FILE)?;
+    let text = fs::read_to_string(&path).map_err(|err| Error::io(&path, err))?;
+
+    decode(&text, shapes).map_err(|reason| Error::SaveRejected {
+        path,
+        reason,
+    })
+}
+
+fn encode(game: &Game) -> Value {
+    Value::object([
+        ("version", VERSION.into()),
+        ("game", game.save_state()),
+    ])
+}
+
+/// Why the save can't be continued when it can't.
+fn decode(text: &str, shapes: &TShape) -> std::result::Result<Game, String> {
+    let save = Value::parse(text).map_err(|err| err.to_string())?;
+    let version = save.get("version").and_then(Value::as_i64);
+
+    if version != Some(VERSION as i64) {
+        return Err(format!("version {} expected, found {}", VERSION, version.map_or(String::from("none"), |version| version.to_string())));
+    }
+
+    let mut game = Game::new(shapes.clone(), Rng::from_entropy());
+
+    game.load_state(save.get("game").ok_or("missing game")?)?;
+
+    Ok(game)
+}
+
+/// A finished game can't be continued, nothing to do when there's no save.
+pub fn remove() -> Result<()> {
+    let path = assets::config_path(FILE)?;
+
+    match fs::remove_file(&path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(Error::io(path, err)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Action, Direction};
+
+    /// A game some way in, with a held piece and a few locked ones.
+    fn played() -> Game {
+        let mut game = Game::new(TShape::fixture(), Rng::new(5));
+
+        for action in [Action::Hold, Action::HardDrop, Action::Rotate, Action::Shift(Direction::Left), Action::HardDrop, Action::HardDrop] {
+            game.apply(action);
+            game.update(0.1);
+        }
+
+        game
+    }
+
+    fn field<'a>(value: &'a mut Value, key: &str) -> &'a mut Value {
+        let Value::Object(fields) = value else {
+            panic!("{} isn't in an object", key);
+        };
+
+        &mut fields.iter_mut().find(|(name, _)| name == key).unwrap().1
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut game = played();
+        let mut loaded = decode(&encode(&game).to_string(), &TShape::fixture()).unwrap();
+
+        assert_eq!(loaded.lines(), game.lines());
+        assert_eq!(loaded.board().queue, game.board().queue);
+        assert_eq!(loaded.hold_piece(), game.hold_piece());
+        assert!(game.hold_piece().is_some());
+        assert_eq!(loaded.score.points, game.score.points);
+        assert_eq!(loaded.score.lines, game.score.lines);
+        assert_eq!(loaded.score.level, game.score.level);
+        assert_eq!(loaded.score.combo, game.score.combo);
+        assert_eq!(loaded.rng().state(), game.rng().state());
+        assert_eq!(loaded.ticks(), game.ticks());
+
+        // the same pieces keep coming
+        for _ in 0..10 {
+            game.apply(Action::HardDrop);
+            loaded.apply(Action::HardDrop);
+        }
+
+        assert_eq!(loaded.snapshot(), game.snapshot());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut save = encode(&played());
+
+        *field(&mut save, "version") = (VERSION + 1).into();
+
+        assert_eq!(decode(&save.to_string(), &TShape::fixture()).err().as_deref(), Some("version 1 expected, found 2"));
+    }
+
+    #[test]
+    fn rejects_a_histogram_for_other_shapes() {
+        let mut save = encode(&played());
+
+        *field(field(field(field(&mut save, "game"), "game"), "stats"), "histogram") = vec![1u32, 2].into();
+
+        assert_eq!(decode(&save.to_string(), &TShape::fixture()).err().as_deref(), Some("bad histogram"));
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        let text = encode(&played()).to_string();
+
+        assert!(decode(&text[..text.len() - 1], &TShape::fixture()).is_err());
+        assert!(decode("", &TShape::fixture()).is_err());
+        assert!(decode("{\"version\":1}", &TShape::fixture()).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::game::{Action, Direction, Game, Position};
+
+use std::collections::{HashMap, VecDeque};
+
+/// Moves the search tries from every spot, falling comes last so it is preferred on ties.
+const MOVES: [Action; 4] = [
+    Action::Shift(Direction::Right),
+    Action::Shift(Direction::Left),
+    Action::Rotate,
+    Action::SoftDrop,
+];
+
+/// Fewest inputs to reach a spot, and the spot and move it was reached from.
+type Visited = HashMap<Vec<Position>, (u32, Option<(Vec<Position>, Action)>)>;
+
+/// A spot the piece can lock in.
+pub struct Placement {
+    /// Sorted bottom to top, so placements reached in different ways compare equal.
+    pub cells: Vec<Position>,
+    /// Shortest way there, without the hard drop that locks it.
+    pub path: Vec<Action>,
+    /// Shifts and rotations on the way, gravity does the falling so it costs nothing.
+    pub inputs: u32,
+}
+
+fn step(game: &Game, shape: &[Position], action: Action) -> Option<Vec<Position>> {
+    let moved = match action {
+        Action::Shift(Direction::Right) => Game::translated(shape, -1, 0)?,
+        Action::Shift(Direction::Left) => Game::translated(shape, 1, 0)?,
+        Action::SoftDrop => Game::translated(shape, 0, -1)?,
+        Action::Rotate => Game::rotated(shape),
+        Action::HardDrop | Action::Hold => return None,
+    };
+
+    game.fits(&moved).then_some(moved)
+}
+
+/// Every resting spot `start` can reach on the game's board with the fewest inputs to get there.
+/// Shifts and rotations cost one and falling is free, so it's a 0-1 breadth first search.
+pub fn placements(game: &Game, start: &[Position]) -> Vec<Placement> {
+    let mut best: Visited = HashMap::new();
+    let mut queue = VecDeque::new();
+
+    if !game.fits(start) {
+        return Vec::new();
+    }
+
+    best.insert(start.to_vec(), (0, None));
+    queue.push_back((start.to_vec(), 0));
+
+    while let Some((shape, cost)) = queue.pop_front() {
+        // a cheaper way here was found after this one was queued
+        if best.get(&shape).is_some_and(|(known, _)| *known < cost) {
+            continue;
+        }
+
+        for action in MOVES {
+            let Some(next) = step(game, &shape, action) else {
+                continue;
+            };
+
+            let price = if action == Action::SoftDrop { 0 } else { 1 };
+
+            if best.get(&next).is_some_and(|(known, _)| *known <= cost + price) {
+                continue;
+            }
+
+            best.insert(next.clone(), (cost + price, Some((shape.clone(), action))));
+
+            if price == 0 {
+                queue.push_front((next, cost));
+            } else {
+                queue.push_back((next, cost + price));
+            }
+        }
+    }
+
+    // the map iterates in a random order, bots have to see the same list every time
+    let mut resting: Vec<(&Vec<Position>, u32)> = best
+        .iter()
+        .filter(|(shape, _)| game.is_resting(shape))
+        .map(|(shape, (inputs, _))| (shape, *inputs))
+        .collect();
+
+    resting.sort();
+
+    let mut placements: Vec<Placement> = Vec::new();
+
+    for (shape, inputs) in resting {
+        let mut cells = shape.clone();
+        cells.sort_by_key(|position| (position.y, position.x));
+
+        match placements.iter_mut().find(|placement| placement.cells == cells) {
+            Some(placement) if placement.inputs <= inputs => {},
+            Some(placement) => {
+                placement.inputs = inputs;
+                placement.path = path(&best, shape);
+            },
+            None => placements.push(Placement {
+                cells,
+                path: path(&best, shape),
+                inputs,
+            }),
+        }
+    }
+
+    placements
+}
+
+fn path(best: &Visited, shape: &[Position]) -> Vec<Action> {
+    let mut path = Vec::new();
+    let mut shape = shape.to_vec();
+
+    while let Some((_, Some((from, action)))) = best.get(&shape) {
+        path.push(*action);
+        shape = from.clone();
+    }
+
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::rng::Rng;
+    use crate::TShape;
+
+    /// The fixture's shapes by index.
+    const O: usize = 1;
+    const I: usize = 2;
+
+    fn game(piece: usize, lines: Vec<Vec<bool>>) -> Game {
+        let mut game = Game::new(TShape::fixture(), Rng::new(1));
+
+        game.load_board(&Board { lines, queue: vec![piece] });
+        game
+    }
+
+    fn cells(positions: &[(usize, usize)]) -> Vec<Position> {
+        positions.iter().map(|&(x, y)| Position { x, y }).collect()
+    }
+
+    fn find<'a>(placements: &'a [Placement], positions: &[(usize, usize)]) -> &'a Placement {
+        placements.iter().find(|placement| placement.cells == cells(positions)).unwrap()
+    }
+
+    #[test]
+    fn falling_is_free_and_shifts_cost_one_each() {
+        let game = game(O, Board::empty().lines);
+        let placements = placements(&game, game.shape());
+
+        // the square fits in four places on the floor of the five wide board
+        assert_eq!(placements.len(), 4);
+
+        for (x, inputs) in [(0, 2), (1, 1), (2, 0), (3, 1)] {
+            let placement = find(&placements, &[(x, 0), (x + 1, 0), (x, 1), (x + 1, 1)]);
+
+            assert_eq!(placement.inputs, inputs);
+            assert_eq!(placement.path.iter().filter(|action| **action != Action::SoftDrop).count(), inputs as usize);
+        }
+    }
+
+    #[test]
+    fn tucks_under_an_overhang() {
+        let mut lines = Board::empty().lines;
+
+        lines[1] = vec![true, true, false, false, false];
+
+        let game = game(I, lines);
+        let placements = placements(&game, game.shape());
+        let tuck = find(&placements, &[(0, 0), (1, 0)]);
+
+        // down the open side first, then two shifts under the roof
+        assert_eq!(tuck.inputs, 2);
+        assert_eq!(tuck.path[tuck.path.len() - 2..], [Action::Shift(Direction::Right); 2]);
+    }
+}
@@ -0,0 +1,435 @@
+use crate::error::{Error, Result};
+use crate::json::{self, Value};
+use crate::net::{self, PROTOCOL};
+use crate::rng::Rng;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender, SyncSender};
+use std::thread;
+
+/// Ticks between pressing a key and the action landing, enough to cover a round trip on a LAN.
+const INPUT_DELAY: u64 = 3;
+
+/// Longer names are cut, they have to fit over a board.
+const MAX_NAME: usize = 16;
+
+/// Messages a player can fall behind before it's dropped, a stalled one mustn't hold up the rest.
+const BACKLOG: usize = 240;
+
+enum Incoming {
+    Connected(usize, TcpStream, SyncSender<String>),
+    Message(usize, Result<Value>),
+    Closed(usize),
+}
+
+struct Player {
+    stream: TcpStream,
+    /// Lines for the thread writing to the player, None once it's being sent away.
+    outbox: Option<SyncSender<String>>,
+    name: String,
+    /// Nothing but hello is answered before it.
+    greeted: bool,
+    lobby: Option<String>,
+}
+
+/// Two players at most, the first one picks the number of rounds.
+struct Lobby {
+    players: Vec<usize>,
+    rounds: u8,
+    started: bool,
+}
+
+/// Matches players up and passes their inputs on. It doesn't simulate anything, both clients run
+/// the whole match themselves and garbage follows from the inputs on either end.
+struct Server {
+    players: HashMap<usize, Player>,
+    lobbies: BTreeMap<String, Lobby>,
+    /// Seeds the matches.
+    rng: Rng,
+}
+
+fn error(reason: impl Into<String>) -> Value {
+    Value::object([("type", "error".into()), ("reason", Value::String(reason.into()))])
+}
+
+impl Server {
+    fn new() -> Server {
+        Server {
+            players: HashMap::new(),
+            lobbies: BTreeMap::new(),
+            rng: Rng::from_entropy(),
+        }
+    }
+
+    /// A failed write or a player too far behind is cut off, which shows up as the reader closing,
+    /// that's where the player is dropped.
+    fn send(&mut self, id: usize, message: &Value) {
+        let Some(player) = self.players.get(&id) else {
+            return;
+        };
+
+        if player.outbox.as_ref().is_some_and(|outbox| outbox.try_send(message.to_string()).is_err()) {
+            let _ = player.stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn opponent(&self, id: usize) -> Option<usize> {
+        let lobby = self.lobbies.get(self.players.get(&id)?.lobby.as_ref()?)?;
+
+        lobby.started.then(|| lobby.players.iter().copied().find(|other| *other != id))?
+    }
+
+    fn list(&self) -> Value {
+        let lobbies = self.lobbies
+            .iter()
+            .filter(|(_, lobby)| !lobby.started)
+            .map(|(name, lobby)| Value::object([
+                ("name", name.as_str().into()),
+                ("players", Value::Number(lobby.players.len() as f64)),
+            ]))
+            .collect();
+
+        Value::object([("type", "lobbies".into()), ("lobbies", Value::Array(lobbies))])
+    }
+
+    fn join(&mut self, id: usize, message: &Value) -> std::result::Result<(), String> {
+        let name = message.get("lobby").and_then(Value::as_str).map(str::trim).filter(|name| !name.is_empty()).ok_or("missing lobby name")?;
+        let rounds = message.get("rounds").and_then(Value::as_i64).unwrap_or(1).clamp(0, 3) as u8;
+
+        if self.players.get(&id).is_some_and(|player| player.lobby.is_some()) {
+            return Err(String::from("already in a lobby"));
+        }
+
+        let lobby = self.lobbies.entry(name.to_string()).or_insert(Lobby {
+            players: Vec::new(),
+            rounds,
+            started: false,
+        });
+
+        if lobby.started || lobby.players.len() >= 2 {
+            return Err(format!("lobby {} is full", name));
+        }
+
+        lobby.players.push(id);
+
+        let side = lobby.players.len() - 1;
+        let full = lobby.players.len() == 2;
+
+        if let Some(player) = self.players.get_mut(&id) {
+            player.lobby = Some(name.to_string());
+        }
+
+        self.send(id, &Value::object([
+            ("type", "joined".into()),
+            ("lobby", name.into()),
+            ("side", Value::Number(side as f64)),
+        ]));
+
+        if full {
+            self.start(name);
+        }
+
+        Ok(())
+    }
+
+    fn start(&mut self, name: &str) {
+        let seed = self.rng.next_u64();
+
+        let Some(lobby) = self.lobbies.get_mut(name) else {
+            return;
+        };
+
+        lobby.started = true;
+
+        let (players, rounds) = (lobby.players.clone(), lobby.rounds);
+        let names: Vec<Value> = players.iter().map(|id| self.players.get(id).map_or("", |player| &player.name).into()).collect();
+
+        for (side, id) in players.into_iter().enumerate() {
+            self.send(id, &Value::object([
+                ("type", "start".into()),
+                // seeds don't fit in a JSON number
+                ("seed", Value::String(seed.to_string())),
+                ("side", Value::Number(side as f64)),
+                ("names", Value::Array(names.clone())),
+                ("rounds", Value::Number(rounds as f64)),
+                ("delay", Value::Number(INPUT_DELAY as f64)),
+            ]));
+        }
+    }
+
+    /// A started match can't go on with one player, the other is told and the lobby closes.
+    fn leave(&mut self, id: usize) {
+        let Some(name) = self.players.get_mut(&id).and_then(|player| player.lobby.take()) else {
+            return;
+        };
+
+        let Some(lobby) = self.lobbies.get_mut(&name) else {
+            return;
+        };
+
+        lobby.players.retain(|other| *other != id);
+
+        if !lobby.started && !lobby.players.is_empty() {
+            return;
+        }
+
+        let remaining = self.lobbies.remove(&name).map(|lobby| lobby.players).unwrap_or_default();
+
+        for other in remaining {
+            if let Some(player) = self.players.get_mut(&other) {
+                player.lobby = None;
+            }
+
+            self.send(other, &Value::object([("type", "left".into())]));
+        }
+    }
+
+    fn hello(&mut self, id: usize, message: &Value) {
+        let version = message.get("version").and_then(Value::as_i64);
+
+        if version != Some(PROTOCOL as i64) {
+            self.send(id, &error(format!("protocol version {} expected", PROTOCOL)));
+
+            // the writer hangs up once the error is out
+            if let Some(player) = self.players.get_mut(&id) {
+                player.outbox = None;
+            }
+
+            return;
+        }
+
+        if let Some(player) = self.players.get_mut(&id) {
+            let name = message.get("name").and_then(Value::as_str).map(str::trim).unwrap_or("");
+
+            if !name.is_empty() {
+                player.name = name.chars().take(MAX_NAME).collect();
+            }
+
+            player.greeted = true;
+        }
+
+        self.send(id, &Value::object([("type", "welcome".into())]));
+    }
+
+    fn handle(&mut self, incoming: Incoming) {
+        let (id, message) = match incoming {
+            Incoming::Connected(id, stream, outbox) => {
+                self.players.insert(id, Player {
+                    stream,
+                    outbox: Some(outbox),
+                    name: format!("Player {}", id + 1),
+                    greeted: false,
+                    lobby: None,
+                });
+
+                return;
+            },
+            Incoming::Closed(id) => {
+                self.leave(id);
+                self.players.remove(&id);
+
+                return;
+            },
+            Incoming::Message(id, Err(err)) => return self.send(id, &error(err.to_string())),
+            Incoming::Message(id, Ok(message)) => (id, message),
+        };
+
+        let greeted = self.players.get(&id).is_some_and(|player| player.greeted);
+
+        match net::kind(&message) {
+            Some("hello") => self.hello(id, &message),
+            _ if !greeted => self.send(id, &error("say hello first")),
+            Some("list") => {
+                let lobbies = self.list();
+
+                self.send(id, &lobbies);
+            },
+            Some("join") => {
+                if let Err(reason) = self.join(id, &message) {
+                    self.send(id, &error(reason));
+                }
+            },
+            Some("leave") => self.leave(id),
+            // inputs and hashes go to the other player as they are, late ones are only dropped
+            // once the match is over
+            Some("input" | "sync") => {
+                if let Some(other) = self.opponent(id) {
+                    self.send(other, &message);
+                }
+            },
+            _ => self.send(id, &error(format!("unexpected message {}", message))),
+        }
+    }
+}
+
+/// Writes the lines it's given until the player goes away or the sender is dropped, then hangs up.
+fn write(mut stream: TcpStream) -> SyncSender<String> {
+    let (outbox, lines) = mpsc::sync_channel::<String>(BACKLOG);
+
+    thread::spawn(move || {
+        for line in lines {
+            if writeln!(stream, "{}", line).is_err() {
+                break;
+            }
+        }
+
+        let _ = stream.shutdown(Shutdown::Both);
+    });
+
+    outbox
+}
+
+/// Every connection gets a reader and a writer thread, the readers all feed the one loop that
+/// owns the lobbies.
+fn accept(listener: TcpListener, sender: Sender<Incoming>) {
+    for (id, stream) in listener.incoming().filter_map(|stream| stream.ok()).enumerate() {
+        let (Ok(reader), Ok(writer)) = (stream.try_clone(), stream.try_clone()) else {
+            continue;
+        };
+
+        let _ = stream.set_nodelay(true);
+
+        if sender.send(Incoming::Connected(id, stream, write(writer))).is_err() {
+            return;
+        }
+
+        let sender = sender.clone();
+
+        thread::spawn(move || {
+            for message in json::lines(reader) {
+                if sender.send(Incoming::Message(id, message)).is_err() {
+                    return;
+                }
+            }
+
+            let _ = sender.send(Incoming::Closed(id));
+        });
+    }
+}
+
+/// Hosts versus lobbies on `address` until the process is stopped.
+pub fn run(address: &str) -> Result<()> {
+    let listener = TcpListener::bind(address).map_err(|err| Error::io(address, err))?;
+
+    eprintln!("[INFO] serving versus lobbies on {}", address);
+
+    serve(listener);
+
+    Ok(())
+}
+
+fn serve(listener: TcpListener) {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || accept(listener, sender));
+
+    let mut server = Server::new();
+
+    for incoming in receiver {
+        server.handle(incoming);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Action, Direction, Game};
+    use crate::net::{Connection, Session, Start};
+    use crate::versus::Versus;
+    use crate::TShape;
+
+    use std::time::{Duration, Instant};
+
+    fn wait_for_start(connection: &mut Connection) -> Start {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        loop {
+            assert!(Instant::now() < deadline, "the match never started");
+
+            match connection.receive().unwrap() {
+                Some(message) if net::kind(&message) == Some("start") => return Start::decode(&message).unwrap(),
+                Some(message) => assert_ne!(net::kind(&message), Some("error"), "{}", message),
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+    }
+
+    #[test]
+    fn both_ends_play_the_same_match() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || serve(listener));
+
+        let mut connections = ["Left", "Right"].map(|name| {
+            let mut connection = Connection::open(&address, name).unwrap();
+
+            connection.send(Value::object([
+                ("type", "join".into()),
+                ("lobby", "test".into()),
+                ("rounds", Value::Number(1.0)),
+            ])).unwrap();
+
+            connection
+        });
+
+        let starts = connections.each_mut().map(wait_for_start);
+
+        assert_eq!(starts[0].seed, starts[1].seed);
+        assert_ne!(starts[0].side, starts[1].side);
+
+        let shapes = TShape::fixture();
+
+        let scripts = [
+            vec![Action::Shift(Direction::Left), Action::HardDrop, Action::Rotate, Action::HardDrop],
+            vec![Action::Rotate, Action::Shift(Direction::Right), Action::Shift(Direction::Right), Action::HardDrop, Action::HardDrop],
+        ];
+
+        let mut ends: Vec<(Session, Game, Versus, usize)> = connections
+            .into_iter()
+            .zip(&starts)
+            .map(|(connection, start)| {
+                let mut player = Game::new(shapes.clone(), Rng::new(start.seed));
+                let versus = Versus::seeded(&mut player, start.rounds, start.seed, start.names.clone());
+
+                (Session::new(connection, start), player, versus, 0)
+            })
+            .collect();
+
+        let deadline = Instant::now() + Duration::from_secs(60);
+
+        while ends.iter().any(|(session, _, versus, _)| versus.winner().is_none() && session.ended.is_none()) {
+            assert!(Instant::now() < deadline, "the match never ended");
+
+            for (session, player, versus, next) in &mut ends {
+                if versus.winner().is_some() {
+                    continue;
+                }
+
+                if versus.round.is_none() && !session.in_flight() {
+                    let script = &scripts[session.side];
+
+                    session.queue(script[*next % script.len()]);
+                    *next += 1;
+                }
+
+                session.advance(player, versus, net::DELTA);
+            }
+
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let [(left, left_player, left_versus, _), (right, right_player, right_versus, _)] = &ends[..] else {
+            unreachable!();
+        };
+
+        assert_eq!(left.ended, None);
+        assert_eq!(right.ended, None);
+        assert!(left_versus.winner().is_some());
+        assert_eq!(left_versus.winner(), right_versus.winner());
+        assert_eq!(left_versus.sides.each_ref().map(|side| side.wins), right_versus.sides.each_ref().map(|side| side.wins));
+        assert_eq!(Session::hash([left_player, &left_versus.rival]), Session::hash([right_player, &right_versus.rival]));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::error::{self, Error, Result};
+use crate::game::{Event, Game};
+use crate::json::{self, Value};
+
+use std::io::{ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::thread;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7879";
+
+/// Frames a viewer can fall behind before it's dropped, a slow one mustn't hold up the game.
+const BACKLOG: usize = 240;
+
+/// Ticks between frames when only the clocks moved, often enough for the viewers' timers.
+const CLOCK_EVERY: u64 = 30;
+
+/// Streams the game being played to everyone connected. Every frame that changed something is one
+/// line of JSON with the tick, the events and a snapshot of the game, so viewers can join any time.
+pub struct Broadcast {
+    listener: TcpListener,
+    /// Each viewer has a thread writing to it.
+    viewers: Vec<SyncSender<String>>,
+    /// Last snapshot sent without its clocks, a frame where nothing else changed isn't sent.
+    last: Option<Value>,
+    /// Tick of the last frame sent.
+    sent: u64,
+}
+
+impl Broadcast {
+    pub fn bind(address: &str) -> Result<Broadcast> {
+        let listener = TcpListener::bind(address).map_err(|err| Error::io(address, err))?;
+
+        listener.set_nonblocking(true).map_err(|err| Error::io(address, err))?;
+
+        eprintln!("[INFO] streaming games on {}", address);
+
+        Ok(Broadcast {
+            listener,
+            viewers: Vec::new(),
+            last: None,
+            sent: 0,
+        })
+    }
+
+    fn accept(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    self.viewers.push(Self::spawn(stream));
+
+                    // newcomers need a snapshot even if nothing moved
+                    self.last = None;
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    error::warn(&Error::Network(format!("failed to accept a viewer: {}", err)));
+                    break;
+                },
+            }
+        }
+    }
+
+    fn spawn(mut stream: TcpStream) -> SyncSender<String> {
+        let (sender, frames) = mpsc::sync_channel::<String>(BACKLOG);
+
+        // some platforms hand out accepted sockets nonblocking like the listener
+        error::recover(stream.set_nonblocking(false).map_err(|err| Error::Network(err.to_string())));
+        error::recover(stream.set_nodelay(true).map_err(|err| Error::Network(err.to_string())));
+
+        thread::spawn(move || {
+            for frame in frames {
+                if writeln!(stream, "{}", frame).is_err() {
+                    break;
+                }
+            }
+        });
+
+        sender
+    }
+
+    /// Sends this frame's events and the game as it is now, viewers that went away or can't keep
+    /// up are dropped.
+    pub fn publish(&mut self, game: &Game, events: &[Event]) {
+        self.accept();
+
+        if self.viewers.is_empty() {
+            return;
+        }
+
+        let snapshot = game.snapshot();
+        let settled = Self::without_clocks(&snapshot);
+
+        if events.is_empty() && self.last.as_ref() == Some(&settled) && game.ticks() < self.sent + CLOCK_EVERY {
+            return;
+        }
+
+        let frame = Value::object([
+            ("type", "frame".into()),
+            ("tick", Value::Number(game.ticks() as f64)),
+            ("events", Value::Array(events.iter().map(Event::encode).collect())),
+            ("game", snapshot),
+        ])
+        .to_string();
+
+        self.last = Some(settled);
+        self.sent = game.ticks();
+        self.viewers.retain(|viewer| viewer.try_send(frame.clone()).is_ok());
+    }
+
+    /// The gravity timer and the elapsed time move every tick, whether anything else did or not.
+    fn without_clocks(snapshot: &Value) -> Value {
+        let mut settled = snapshot.clone();
+
+        if let Value::Object(fields) = &mut settled {
+            fields.retain(|(key, _)| key != "timer");
+
+            for (key, value) in fields.iter_mut() {
+                if let (true, Value::Object(stats)) = (key == "stats", value) {
+                    stats.retain(|(key, _)| key != "elapsed");
+                }
+            }
+        }
+
+        settled
+    }
+}
+
+/// Follows a broadcast, the game it's given is whatever the last frame said.
+pub struct Viewer {
+    frames: Receiver<Result<Value>>,
+    /// A frame arrived.
+    watching: bool,
+    /// Why there's nothing more to see.
+    ended: Option<String>,
+}
+
+impl Viewer {
+    pub fn connect(address: &str) -> Result<Viewer> {
+        let stream = TcpStream::connect(address).map_err(|err| Error::io(address, err))?;
+        let (sender, frames) = mpsc::channel();
+
+        thread::spawn(move || {
+            for frame in json::lines(stream) {
+                if sender.send(frame).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Viewer {
+            frames,
+            watching: false,
+            ended: None,
+        })
+    }
+
+    /// What to show over the game instead of it, None while it's being streamed.
+    pub fn status(&self) -> Option<&str> {
+        match &self.ended {
+            Some(reason) => Some(reason),
+            None if !self.watching => Some("Waiting for a game"),
+            None => None,
+        }
+    }
+
+    /// Catches `game` up with the frames that arrived and returns their events in order.
+    pub fn update(&mut self, game: &mut Game) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        while self.ended.is_none() {
+            let frame = match self.frames.try_recv() {
+                Ok(frame) => frame,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.ended = Some(String::from("Stream ended"));
+                    break;
+                },
+            };
+
+            let applied = frame.and_then(|frame| Self::apply(&frame, game, &mut events).map_err(Error::Network));
+
+            match applied {
+                Ok(()) => self.watching = true,
+                Err(err) => {
+                    error::warn(&err);
+                    self.ended = Some(String::from("Bad stream"));
+                },
+            }
+        }
+
+        events
+    }
+
+    /// Events from a newer version are skipped, they only make noise.
+    fn apply(frame: &Value, game: &mut Game, events: &mut Vec<Event>) -> std::result::Result<(), String> {
+        let happened = frame.get("events").and_then(Value::as_array).ok_or("frame without events")?;
+        let snapshot = frame.get("game").ok_or("frame without a game")?;
+
+        game.restore(snapshot)?;
+        events.extend(happened.iter().filter_map(Event::decode));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Action;
+    use crate::rng::Rng;
+    use crate::TShape;
+
+    #[test]
+    fn only_changes_and_clocks_are_sent() {
+        let shapes = TShape::fixture();
+        let mut broadcast = Broadcast::bind("127.0.0.1:0").unwrap();
+        let viewer = TcpStream::connect(broadcast.listener.local_addr().unwrap()).unwrap();
+        let mut game = Game::new(shapes, Rng::new(3));
+
+        // the first frame, the newcomer needs one
+        broadcast.publish(&game, &[]);
+
+        // only the clocks move until the last of these
+        for _ in 0..CLOCK_EVERY {
+            game.update(0.001);
+
+            let events = game.take_events();
+
+            broadcast.publish(&game, &events);
+        }
+
+        game.apply(Action::Rotate);
+
+        let events = game.take_events();
+
+        broadcast.publish(&game, &events);
+
+        drop(broadcast);
+
+        let ticks: Vec<i64> = json::lines(viewer).map(|frame| frame.unwrap().get("tick").and_then(Value::as_i64).unwrap()).collect();
+
+        assert_eq!(ticks, [0, CLOCK_EVERY as i64, CLOCK_EVERY as i64]);
+    }
+}
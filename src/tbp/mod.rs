@@ -0,0 +1,593 @@
+use crate::board::Board;
+use crate::bot;
+use crate::error::{self, Error, Result};
+use crate::game::{Action, Game, Position, COLUMNS, ROWS};
+use crate::json::{self, Value};
+use crate::rng::Rng;
+use crate::TShape;
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Quarter turns from the spawn, in the order `Action::Rotate` steps through them.
+const ORIENTATIONS: [&str; 4] = ["north", "east", "south", "west"];
+
+/// How long a bot gets to exit on its own after `quit`.
+const QUIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A move in protocol terms. Our shapes aren't tetrominoes, so a location is the orientation and
+/// where the shape's first cell, as listed in `shapes.tshape`, ends up. Like the protocol, x counts
+/// from the left of the screen and y from the floor, which is not how `Position` counts x.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Move {
+    pub piece: usize,
+    pub orientation: usize,
+    pub x: i64,
+    pub y: i64,
+}
+
+impl Move {
+    /// Where `cells` (sorted bottom to top) put the first cell of `piece` turned `orientation` times.
+    fn locate(shapes: &TShape, piece: usize, orientation: usize, cells: &[Position]) -> Move {
+        let shape = Self::turned(shapes, piece, orientation);
+
+        let mut sorted = shape.clone();
+        sorted.sort_by_key(|position| (position.y, position.x));
+
+        let x = shape[0].x as i64 + cells[0].x as i64 - sorted[0].x as i64;
+        let y = shape[0].y as i64 + cells[0].y as i64 - sorted[0].y as i64;
+
+        Move {
+            piece,
+            orientation,
+            x: COLUMNS as i64 - 1 - x,
+            y,
+        }
+    }
+
+    fn turned(shapes: &TShape, piece: usize, orientation: usize) -> Vec<Position> {
+        (0..orientation).fold(shapes.get(piece), |shape, _| Game::rotated(&shape))
+    }
+
+    /// The cells it covers sorted bottom to top, None if any are off the board.
+    pub fn cells(&self, shapes: &TShape) -> Option<Vec<Position>> {
+        let shape = Self::turned(shapes, self.piece, self.orientation);
+        let x = COLUMNS as i64 - 1 - self.x - shape[0].x as i64;
+        let y = self.y - shape[0].y as i64;
+
+        let mut cells = Game::translated(&shape, x.try_into().ok()?, y.try_into().ok()?)?;
+
+        if cells.iter().any(|position| position.x >= COLUMNS || position.y >= ROWS) {
+            return None;
+        }
+
+        cells.sort_by_key(|position| (position.y, position.x));
+
+        Some(cells)
+    }
+
+    fn encode(&self, shapes: &TShape) -> Value {
+        let location = Value::object([
+            ("type", shapes.name(self.piece).into()),
+            ("orientation", ORIENTATIONS[self.orientation].into()),
+            ("x", Value::Number(self.x as f64)),
+            ("y", Value::Number(self.y as f64)),
+        ]);
+
+        Value::object([("location", location), ("spin", "none".into())])
+    }
+
+    fn decode(value: &Value, shapes: &TShape) -> Option<Move> {
+        let location = value.get("location")?;
+        let orientation = location.get("orientation")?.as_str()?;
+
+        Some(Move {
+            piece: shapes.find(location.get("type")?.as_str()?)?,
+            orientation: ORIENTATIONS.iter().position(|name| *name == orientation)?,
+            x: location.get("x")?.as_i64()?,
+            y: location.get("y")?.as_i64()?,
+        })
+    }
+}
+
+/// The game as a bot sees it, the falling piece is the first in the queue.
+#[derive(Clone, PartialEq, Debug)]
+struct State {
+    lines: Vec<Vec<bool>>,
+    queue: VecDeque<usize>,
+    hold: Option<usize>,
+    combo: u32,
+}
+
+impl State {
+    fn from_game(game: &Game) -> State {
+        let board = game.board();
+
+        State {
+            lines: board.lines,
+            queue: board.queue.into(),
+            hold: game.hold_piece(),
+            combo: game.score.combo,
+        }
+    }
+
+    /// Rows bottom first, each left to right on screen, with a garbage block for every filled cell.
+    fn encode_board(&self) -> Value {
+        let rows = self.lines
+            .iter()
+            .map(|line| Value::Array(line.iter().rev().map(|block| if *block { "G".into() } else { Value::Null }).collect()))
+            .collect();
+
+        Value::Array(rows)
+    }
+
+    fn encode(&self, shapes: &TShape) -> Value {
+        let queue: Vec<&str> = self.queue.iter().map(|piece| shapes.name(*piece)).collect();
+
+        Value::object([
+            ("type", "start".into()),
+            ("hold", self.hold.map(|piece| shapes.name(piece)).into()),
+            ("queue", queue.into()),
+            ("combo", self.combo.into()),
+            ("back_to_back", false.into()),
+            ("board", self.encode_board()),
+        ])
+    }
+
+    fn decode(message: &Value, shapes: &TShape) -> Option<State> {
+        let piece = |value: &Value| shapes.find(value.as_str()?);
+
+        let rows = message.get("board")?.as_array()?;
+        let mut lines = vec![vec![false; COLUMNS]; ROWS];
+
+        for (y, row) in rows.iter().enumerate() {
+            for (column, cell) in row.as_array()?.iter().enumerate() {
+                if y >= ROWS || column >= COLUMNS {
+                    if *cell != Value::Null {
+                        return None;
+                    }
+
+                    continue;
+                }
+
+                lines[y][COLUMNS - 1 - column] = *cell != Value::Null;
+            }
+        }
+
+        let hold = match message.get("hold") {
+            None | Some(Value::Null) => None,
+            Some(value) => Some(piece(value)?),
+        };
+
+        Some(State {
+            lines,
+            queue: message.get("queue")?.as_array()?.iter().map(piece).collect::<Option<_>>()?,
+            hold,
+            combo: message.get("combo").and_then(Value::as_i64).unwrap_or(0) as u32,
+        })
+    }
+
+    /// Plays the move the way the protocol defines holding, false if it can't be played from here.
+    fn play(&mut self, mv: &Move, shapes: &TShape) -> bool {
+        let Some(cells) = mv.cells(shapes) else {
+            return false;
+        };
+
+        if self.queue.front() != Some(&mv.piece) {
+            let current = self.queue.pop_front();
+
+            match self.hold {
+                None if self.queue.front() == Some(&mv.piece) => {
+                    self.queue.pop_front();
+                },
+                Some(held) if held == mv.piece => {},
+                _ => return false,
+            }
+
+            self.hold = current;
+        } else {
+            self.queue.pop_front();
+        }
+
+        if cells.iter().any(|position| self.lines[position.y][position.x]) {
+            return false;
+        }
+
+        let (lines, cleared) = bot::simulate(&self.lines, &cells);
+
+        self.lines = lines;
+        self.combo = if cleared > 0 { self.combo + 1 } else { 0 };
+
+        true
+    }
+}
+
+/// A message with nothing but its type.
+fn plain(kind: &str) -> Value {
+    Value::object([("type", kind.into())])
+}
+
+fn kind(message: &Value) -> &str {
+    message.get("type").and_then(Value::as_str).unwrap_or("")
+}
+
+/// An external bot running as a child process, one JSON message per line on its stdin and stdout.
+/// It never blocks the game, messages are read on a thread and picked up every frame.
+pub struct Client {
+    /// None when the bot isn't a process of ours.
+    child: Option<Child>,
+    stdin: Box<dyn Write + Send>,
+    messages: Receiver<Result<Value>>,
+    /// What the bot called itself.
+    pub name: Option<String>,
+    ready: bool,
+    /// What the bot believes the game looks like, None until it's started.
+    state: Option<State>,
+    /// The piece count a suggestion was asked for at.
+    asked: Option<u32>,
+    failed: bool,
+}
+
+impl Client {
+    /// `command` is the program followed by its arguments, split on whitespace.
+    pub fn spawn(command: &str) -> Result<Client> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or_else(|| Error::Protocol(String::from("empty bot command")))?;
+
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| Error::io(program, err))?;
+
+        let stdin = child.stdin.take().ok_or_else(|| Error::Protocol(String::from("no stdin")))?;
+        let stdout = child.stdout.take().ok_or_else(|| Error::Protocol(String::from("no stdout")))?;
+
+        Ok(Self::attach(Some(child), stdin, stdout))
+    }
+
+    /// Talks to a bot reading `stdin` and writing `stdout`.
+    fn attach(child: Option<Child>, stdin: impl Write + Send + 'static, stdout: impl Read + Send + 'static) -> Client {
+        let (sender, messages) = mpsc::channel();
+
+        thread::spawn(move || {
+            for message in json::lines(stdout) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Client {
+            child,
+            stdin: Box::new(stdin),
+            messages,
+            name: None,
+            ready: false,
+            state: None,
+            asked: None,
+            failed: false,
+        }
+    }
+
+    fn send(&mut self, message: Value) -> Result<()> {
+        writeln!(self.stdin, "{}", message)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|err| Error::Protocol(format!("failed to write to the bot: {}", err)))
+    }
+
+    fn receive(&mut self) -> Result<Option<Value>> {
+        match self.messages.try_recv() {
+            Ok(message) => message.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(Error::Protocol(String::from("the bot exited"))),
+        }
+    }
+
+    /// The move to play next, None while the bot is thinking or after it broke the protocol,
+    /// which is logged once and leaves the game to the player.
+    pub fn poll(&mut self, game: &Game) -> Option<Move> {
+        if self.failed {
+            return None;
+        }
+
+        match self.step(game) {
+            Ok(mv) => mv,
+            Err(err) => {
+                error::warn(&err);
+                self.failed = true;
+
+                None
+            },
+        }
+    }
+
+    pub fn thinking(&self) -> bool {
+        !self.failed && (!self.ready || self.asked.is_some())
+    }
+
+    fn step(&mut self, game: &Game) -> Result<Option<Move>> {
+        while let Some(message) = self.receive()? {
+            match kind(&message) {
+                "info" => {
+                    self.name = message.get("name").and_then(Value::as_str).map(String::from);
+                    self.send(plain("rules"))?;
+                },
+                "ready" => self.ready = true,
+                "error" => {
+                    let reason = message.get("reason").and_then(Value::as_str).unwrap_or("unknown");
+
+                    return Err(Error::Protocol(format!("the bot refused the rules: {}", reason)));
+                },
+                // the piece locked on its own while the bot was thinking, the next sync starts it over
+                "suggestion" if self.asked != Some(game.stats.pieces) => self.asked = None,
+                "suggestion" => {
+                    self.asked = None;
+
+                    return self.choose(&message, game).map(Some);
+                },
+                // the protocol says to ignore what we don't know
+                _ => {},
+            }
+        }
+
+        if self.ready && self.asked.is_none() && !game.over {
+            self.sync(game)?;
+            self.send(plain("suggest"))?;
+            self.asked = Some(game.stats.pieces);
+        }
+
+        Ok(None)
+    }
+
+    /// Tells the bot about pieces it hasn't seen, or starts it over when the game went another way.
+    fn sync(&mut self, game: &Game) -> Result<()> {
+        let actual = State::from_game(game);
+
+        if let Some(state) = &self.state {
+            let known = state.queue.len();
+
+            if state.lines == actual.lines && state.hold == actual.hold && actual.queue.iter().take(known).eq(state.queue.iter()) {
+                for piece in actual.queue.iter().skip(known) {
+                    self.send(Value::object([("type", "new_piece".into()), ("piece", game.shapes().name(*piece).into())]))?;
+                }
+
+                self.state = Some(actual);
+
+                return Ok(());
+            }
+
+            self.send(plain("stop"))?;
+        }
+
+        self.send(actual.encode(game.shapes()))?;
+        self.state = Some(actual);
+
+        Ok(())
+    }
+
+    /// The bot's favourite move that can be played, the bot is told which one it was.
+    fn choose(&mut self, suggestion: &Value, game: &Game) -> Result<Move> {
+        let moves = suggestion.get("moves").and_then(Value::as_array).unwrap_or_default();
+        let state = self.state.as_ref().ok_or_else(|| Error::Protocol(String::from("suggestion before start")))?;
+
+        let chosen = moves
+            .iter()
+            .filter_map(|mv| Move::decode(mv, game.shapes()))
+            .find_map(|mv| {
+                let cells = mv.cells(game.shapes())?;
+                let mut state = state.clone();
+
+                (state.play(&mv, game.shapes()) && game.fits(&cells) && game.is_resting(&cells)).then_some((mv, state))
+            });
+
+        let (mv, state) = chosen.ok_or_else(|| Error::Protocol(String::from("no legal move in the suggestion")))?;
+
+        self.send(Value::object([("type", "play".into()), ("move", mv.encode(game.shapes()))]))?;
+        self.state = Some(state);
+
+        Ok(mv)
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.send(plain("quit"));
+
+        let Some(child) = &mut self.child else {
+            return;
+        };
+
+        let start = Instant::now();
+
+        while start.elapsed() < QUIT_TIMEOUT {
+            if let Ok(Some(_)) = child.try_wait() {
+                return;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// The built-in bot on the other side of the protocol, to test frontends and the client against.
+/// Reads stdin until `quit`, it never holds.
+pub fn serve(shapes: TShape) -> Result<()> {
+    respond(shapes, io::stdin().lock(), io::stdout())
+}
+
+/// `serve` over any pair of streams.
+fn respond(shapes: TShape, input: impl BufRead, mut output: impl Write) -> Result<()> {
+    let mut send = |message: Value| -> Result<()> {
+        writeln!(output, "{}", message)
+            .and_then(|_| output.flush())
+            .map_err(|err| Error::Protocol(err.to_string()))
+    };
+
+    send(Value::object([
+        ("type", "info".into()),
+        ("name", "tetris reference bot".into()),
+        ("version", env!("CARGO_PKG_VERSION").into()),
+        ("author", "tetris".into()),
+        ("features", Value::Array(Vec::new())),
+    ]))?;
+
+    let mut state: Option<State> = None;
+
+    for line in input.lines() {
+        let line = line.map_err(|err| Error::Protocol(err.to_string()))?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let message = Value::parse(&line)?;
+        let invalid = || Error::Protocol(format!("invalid `{}` message", kind(&message)));
+
+        match kind(&message) {
+            "rules" => send(plain("ready"))?,
+            "start" => state = Some(State::decode(&message, &shapes).ok_or_else(invalid)?),
+            "stop" => state = None,
+            "suggest" => {
+                let moves: Vec<Value> = state.as_ref().and_then(|state| suggest(state, &shapes)).map(|mv| mv.encode(&shapes)).into_iter().collect();
+
+                send(Value::object([("type", "suggestion".into()), ("moves", Value::Array(moves))]))?;
+            },
+            "play" => {
+                let mv = message.get("move").and_then(|mv| Move::decode(mv, &shapes)).ok_or_else(invalid)?;
+
+                if let Some(state) = &mut state {
+                    if !state.play(&mv, &shapes) {
+                        return Err(Error::Protocol(String::from("illegal move played")));
+                    }
+                }
+            },
+            "new_piece" => {
+                let piece = message.get("piece").and_then(Value::as_str).and_then(|name| shapes.find(name)).ok_or_else(invalid)?;
+
+                if let Some(state) = &mut state {
+                    state.queue.push_back(piece);
+                }
+            },
+            "quit" => break,
+            // the protocol says to ignore what we don't know
+            _ => {},
+        }
+    }
+
+    Ok(())
+}
+
+fn suggest(state: &State, shapes: &TShape) -> Option<Move> {
+    let mut game = Game::new(shapes.clone(), Rng::new(0));
+
+    game.load_board(&Board {
+        lines: state.lines.clone(),
+        queue: Vec::from(state.queue.clone()),
+    });
+
+    if state.queue.is_empty() || game.over {
+        return None;
+    }
+
+    let placement = bot::suggest(&game)?;
+    let turns = placement.path.iter().filter(|action| **action == Action::Rotate).count();
+
+    Some(Move::locate(shapes, game.piece_index(), turns % ORIENTATIONS.len(), &placement.cells))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search;
+
+    use std::io::BufReader;
+
+    #[test]
+    fn locate_and_cells_agree() {
+        let shapes = TShape::fixture();
+
+        for piece in 0..shapes.count() {
+            for orientation in 0..ORIENTATIONS.len() {
+                let shape = Move::turned(&shapes, piece, orientation);
+
+                for x in -(COLUMNS as isize)..COLUMNS as isize {
+                    for y in -(ROWS as isize)..ROWS as isize {
+                        let Some(mut cells) = Game::translated(&shape, x, y).filter(|cells| cells.iter().all(|position| position.x < COLUMNS && position.y < ROWS)) else {
+                            continue;
+                        };
+
+                        cells.sort_by_key(|position| (position.y, position.x));
+
+                        let mv = Move::locate(&shapes, piece, orientation, &cells);
+
+                        assert_eq!(mv.cells(&shapes), Some(cells), "{:?}", mv);
+                        assert_eq!(Move::decode(&mv.encode(&shapes), &shapes), Some(mv));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn plays_the_reference_bot() {
+        let shapes = TShape::fixture();
+
+        let (bot_stdin, stdin) = io::pipe().unwrap();
+        let (stdout, bot_stdout) = io::pipe().unwrap();
+        let served = shapes.clone();
+
+        let bot = thread::spawn(move || respond(served, BufReader::new(bot_stdin), bot_stdout));
+
+        let mut client = Client::attach(None, stdin, stdout);
+        let mut game = Game::new(shapes, Rng::new(7));
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while game.stats.pieces < 20 && !game.over {
+            assert!(Instant::now() < deadline, "the bot stopped answering");
+
+            let Some(mv) = client.poll(&game) else {
+                assert!(!client.failed, "the client gave up on the bot");
+
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            };
+
+            // the reference bot never holds
+            assert_eq!(mv.piece, game.piece_index());
+
+            let cells = mv.cells(game.shapes()).expect("move off the board");
+
+            assert!(game.fits(&cells) && game.is_resting(&cells), "illegal move {:?}", mv);
+
+            let mut path = search::placements(&game, game.shape())
+                .into_iter()
+                .find(|placement| placement.cells == cells)
+                .expect("move out of reach")
+                .path;
+
+            while path.last() == Some(&Action::SoftDrop) {
+                path.pop();
+            }
+
+            for action in path.into_iter().chain([Action::HardDrop]) {
+                game.apply(action);
+            }
+        }
+
+        assert_eq!(client.name.as_deref(), Some("tetris reference bot"));
+        assert_eq!(game.stats.pieces, 20);
+
+        drop(client);
+
+        bot.join().unwrap().unwrap();
+    }
+}
@@ -1,51 +1,106 @@
-use crate::game::Position;
+use crate::game::{Position, COLUMNS, ROWS};
+use crate::error::{Error, Result};
+use crate::rng::Rng;
 
-use rand::Rng;
-
-use std::io::prelude::*;
-use std::io::BufReader;
-use std::fs::File;
+use std::fs;
 
+#[derive(Clone)]
 pub struct TShape {
     shapes: Vec<Vec<Position>>,
+    names: Vec<String>,
 }
 
 impl TShape {
-    pub fn load(file: &str) -> Result<TShape, Box<dyn std::error::Error>> {
-        let fd = File::open(file)?;
-        let reader = BufReader::new(fd);
+    pub fn load(file: &str) -> Result<TShape> {
+        let source = fs::read_to_string(file).map_err(|err| Error::io(file, err))?;
+
+        Self::parse(file, &source)
+    }
 
+    /// Every line is one shape made of space separated `x-y` cells, optionally
+    /// preceded by a `name:`, unnamed shapes are called by their 1-based number.
+    pub fn parse(file: &str, source: &str) -> Result<TShape> {
         let mut shapes = Vec::new();
+        let mut names = Vec::new();
 
-        for result in reader.lines() {
-            let line = result?;
+        for (index, line) in source.lines().enumerate() {
+            let mut shape: Vec<Position> = Vec::new();
+            let mut name = None;
+            let mut column = 0;
 
-            let indices = line.split(' ')
-                .filter(|x| x.len() == 3)
-                .collect::<Vec<&str>>();
+            for indice in line.split(' ') {
+                let error = |reason: String| Error::ShapeParse {
+                    path: file.to_string(),
+                    line: index + 1,
+                    column: column + 1,
+                    reason,
+                };
 
-            let mut shape: Vec<Position> = Vec::new();
-            for indice in &indices {
-                let position = indice.split('-').collect::<Vec<&str>>();
+                if let Some(label) = indice.strip_suffix(':').filter(|_| shape.is_empty() && name.is_none()) {
+                    name = Some(label.to_string());
+                } else if !indice.is_empty() {
+                    let (x, y) = indice.split_once('-')
+                        .ok_or_else(|| error(format!("expected `x-y`, found `{}`", indice)))?;
+
+                    let x: usize = x.parse().map_err(|_| error(format!("invalid column `{}`", x)))?;
+                    let y: usize = y.parse().map_err(|_| error(format!("invalid row `{}`", y)))?;
 
-                shape.push(Position {
-                    x: position[0].parse()?,
-                    y: position[1].parse()?,
-                });
+                    if x >= COLUMNS || y >= ROWS {
+                        return Err(error(format!("cell {}-{} is outside the {}x{} board", x, y, COLUMNS, ROWS)));
+                    }
+
+                    shape.push(Position { x, y });
+                }
+
+                column += indice.len() + 1;
             }
 
-            shapes.push(shape);
+            if !shape.is_empty() {
+                shapes.push(shape);
+                names.push(name.unwrap_or_else(|| shapes.len().to_string()));
+            }
+        }
+
+        if shapes.is_empty() {
+            return Err(Error::ShapeParse {
+                path: file.to_string(),
+                line: 1,
+                column: 1,
+                reason: String::from("no shapes defined"),
+            });
         }
 
         Ok(TShape {
             shapes,
+            names,
         })
     }
 
-    pub fn rand_shape(&self) -> Vec<Position> {
-        let mut rng = rand::thread_rng();
+    pub fn rand_index(&self, rng: &mut Rng) -> usize {
+        rng.below(self.shapes.len())
+    }
 
-        self.shapes[rng.gen_range(0..self.shapes.len())].clone()
+    pub fn name(&self, index: usize) -> &str {
+        &self.names[index]
+    }
+
+    /// Looks a shape up by name, ignoring case.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|shape| shape.eq_ignore_ascii_case(name))
+    }
+
+    pub fn count(&self) -> usize {
+        self.shapes.len()
+    }
+
+    /// The shape in its spawn position.
+    pub fn get(&self, index: usize) -> Vec<Position> {
+        self.shapes[index].clone()
     }
-}
 
+    /// Small L, O, I and S shapes for the tests to deal from.
+    #[cfg(test)]
+    pub fn fixture() -> TShape {
+        Self::parse("shapes", "L: 2-8 2-9 3-9\nO: 3-9 2-9 2-8 3-8\nI: 3-9 2-9\nS: 1-9 2-9 2-8 3-8\n").unwrap()
+    }
+}
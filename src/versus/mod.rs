@@ -0,0 +1,442 @@
+use crate::bot::{Bot, Speed};
+use crate::game::{Event, Game, COLUMNS};
+use crate::rng::Rng;
+
+use std::collections::VecDeque;
+
+/// Garbage rows sent for clearing 0 to 4 lines at once, and for spins clearing 0 to 3.
+/// The board is narrow and the shapes small, so anything under a double sends nothing.
+const LINE_ATTACK: [u32; 5] = [0, 0, 1, 2, 4];
+const SPIN_ATTACK: [u32; 4] = [0, 2, 4, 6];
+
+/// Extra rows by combo count, the last one holds for longer combos.
+const COMBO_ATTACK: [u32; 10] = [0, 0, 1, 1, 1, 2, 2, 3, 3, 4];
+
+/// Extra row for a spin clear right after another one.
+const BACK_TO_BACK_ATTACK: u32 = 1;
+const PERFECT_CLEAR_ATTACK: u32 = 4;
+
+/// Most garbage rows that rise at once, the rest wait for the next piece.
+const GARBAGE_CAP: u32 = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Rounds {
+    One,
+    #[default]
+    Three,
+    Five,
+    Seven,
+}
+
+impl Rounds {
+    pub const ALL: [Rounds; 4] = [Rounds::One, Rounds::Three, Rounds::Five, Rounds::Seven];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rounds::One => "Best of 1",
+            Rounds::Three => "Best of 3",
+            Rounds::Five => "Best of 5",
+            Rounds::Seven => "Best of 7",
+        }
+    }
+
+    pub fn next(&self) -> Rounds {
+        let index = Rounds::ALL.iter().position(|rounds| rounds == self).unwrap_or(0);
+
+        Rounds::ALL[(index + 1) % Rounds::ALL.len()]
+    }
+
+    pub fn from_index(index: u8) -> Option<Rounds> {
+        Rounds::ALL.get(index as usize).copied()
+    }
+
+    pub fn index(&self) -> u8 {
+        Rounds::ALL.iter().position(|rounds| rounds == self).unwrap_or(0) as u8
+    }
+
+    /// Round wins that take the match.
+    pub fn wins_needed(&self) -> u32 {
+        self.index() as u32 + 1
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Rival {
+    #[default]
+    Human,
+    Bot,
+}
+
+impl Rival {
+    pub const ALL: [Rival; 2] = [Rival::Human, Rival::Bot];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rival::Human => "Human",
+            Rival::Bot => "Bot",
+        }
+    }
+
+    pub fn next(&self) -> Rival {
+        let index = Rival::ALL.iter().position(|rival| rival == self).unwrap_or(0);
+
+        Rival::ALL[(index + 1) % Rival::ALL.len()]
+    }
+
+    pub fn from_index(index: u8) -> Option<Rival> {
+        Rival::ALL.get(index as usize).copied()
+    }
+
+    pub fn index(&self) -> u8 {
+        Rival::ALL.iter().position(|rival| rival == self).unwrap_or(0) as u8
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct VersusSettings {
+    pub rounds: Rounds,
+    /// Who plays the right board.
+    pub rival: Rival,
+}
+
+/// Rows on their way to a board, they all share one hole.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Garbage {
+    rows: u32,
+    hole: usize,
+}
+
+/// What one locked piece did, read back from the events it caused.
+#[derive(Default)]
+struct Lock {
+    lines: u32,
+    spin: bool,
+    combo: u32,
+}
+
+impl Lock {
+    fn read(events: &[Event]) -> Vec<Lock> {
+        let mut locks: Vec<Lock> = Vec::new();
+
+        for event in events {
+            match (event, locks.last_mut()) {
+                (Event::Lock, _) => locks.push(Lock { combo: 1, ..Lock::default() }),
+                (Event::Clear(lines), Some(lock)) => lock.lines = *lines,
+                (Event::Spin(_), Some(lock)) => lock.spin = true,
+                (Event::Combo(combo), Some(lock)) => lock.combo = *combo,
+                _ => {},
+            }
+        }
+
+        locks
+    }
+
+    /// Rows sent before cancelling, updates the back to back streak.
+    fn attack(&self, back_to_back: &mut bool, perfect: bool) -> u32 {
+        let mut attack = if self.spin {
+            SPIN_ATTACK[(self.lines as usize).min(SPIN_ATTACK.len() - 1)]
+        } else {
+            LINE_ATTACK[(self.lines as usize).min(LINE_ATTACK.len() - 1)]
+        };
+
+        attack += COMBO_ATTACK[(self.combo as usize).min(COMBO_ATTACK.len() - 1)];
+
+        let difficult = self.spin || self.lines >= 4;
+
+        if difficult && *back_to_back {
+            attack += BACK_TO_BACK_ATTACK;
+        }
+
+        *back_to_back = difficult;
+
+        if perfect {
+            attack += PERFECT_CLEAR_ATTACK;
+        }
+
+        attack
+    }
+}
+
+/// One player's side of the match.
+#[derive(Default)]
+pub struct Side {
+    incoming: VecDeque<Garbage>,
+    back_to_back: bool,
+    pub wins: u32,
+}
+
+impl Side {
+    /// Rows waiting to rise.
+    pub fn pending(&self) -> u32 {
+        self.incoming.iter().map(|garbage| garbage.rows).sum()
+    }
+
+    /// What the attack didn't cancel goes on to the other side.
+    fn cancel(&mut self, mut attack: u32) -> u32 {
+        while let Some(garbage) = self.incoming.front_mut().filter(|_| attack > 0) {
+            let cancelled = garbage.rows.min(attack);
+
+            garbage.rows -= cancelled;
+            attack -= cancelled;
+
+            if garbage.rows == 0 {
+                self.incoming.pop_front();
+            }
+        }
+
+        attack
+    }
+
+    fn raise(&mut self, game: &mut Game) {
+        let mut raised = 0;
+
+        while let Some(garbage) = self.incoming.front_mut().filter(|_| raised < GARBAGE_CAP) {
+            let rows = garbage.rows.min(GARBAGE_CAP - raised);
+
+            game.insert_garbage(rows, garbage.hole);
+            garbage.rows -= rows;
+            raised += rows;
+
+            if garbage.rows == 0 {
+                self.incoming.pop_front();
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Round {
+    Won(usize),
+    /// Both topped out on the same frame.
+    Draw,
+}
+
+/// A match between the renderer's game on the left and a rival on the right, both dealt the same
+/// pieces. Line clears send garbage across, which first cancels any coming the other way.
+pub struct Versus {
+    pub rival: Game,
+    /// Plays the rival when set.
+    pub bot: Option<Bot>,
+    pub sides: [Side; 2],
+    pub settings: VersusSettings,
+    /// How the last round ended, None while it's being played.
+    pub round: Option<Round>,
+    names: [String; 2],
+    /// Deals the rounds and picks the garbage holes.
+    rng: Rng,
+}
+
+impl Versus {
+    /// Deals `player` a fresh game for the first round.
+    pub fn new(player: &mut Game, settings: VersusSettings, speed: Speed) -> Versus {
+        let rival = if settings.rival == Rival::Bot { "Bot" } else { "Player 2" };
+
+        Self::with_rng(player, settings, Rng::from_entropy(), [String::from("Player 1"), rival.to_string()], speed)
+    }
+
+    /// Everything random comes from `seed`, so two machines fed the same inputs on the same ticks
+    /// play the same match. Both sides are human then.
+    pub fn seeded(player: &mut Game, rounds: Rounds, seed: u64, names: [String; 2]) -> Versus {
+        let settings = VersusSettings {
+            rounds,
+            rival: Rival::Human,
+        };
+
+        Self::with_rng(player, settings, Rng::new(seed), names, Speed::default())
+    }
+
+    fn with_rng(player: &mut Game, settings: VersusSettings, mut rng: Rng, names: [String; 2], speed: Speed) -> Versus {
+        Versus {
+            rival: Self::deal(&mut rng, player),
+            bot: (settings.rival == Rival::Bot).then(|| Bot::new(speed)),
+            sides: [Side::default(), Side::default()],
+            settings,
+            round: None,
+            names,
+            rng,
+        }
+    }
+
+    /// Starts `player` over and returns a rival game that gets the same pieces.
+    fn deal(rng: &mut Rng, player: &mut Game) -> Game {
+        let seed = rng.next_u64();
+
+        *player = Game::new(player.shapes().clone(), Rng::new(seed));
+
+        Game::new(player.shapes().clone(), Rng::new(seed))
+    }
+
+    pub fn name(&self, side: usize) -> &str {
+        &self.names[side]
+    }
+
+    /// Side that won the match, once one has.
+    pub fn winner(&self) -> Option<usize> {
+        self.sides.iter().position(|side| side.wins >= self.settings.rounds.wins_needed())
+    }
+
+    /// Sends garbage for what happened on both boards this frame and ends the round once someone
+    /// tops out. `player` is the left board.
+    pub fn update(&mut self, player: &mut Game, events: [&[Event]; 2]) {
+        if self.round.is_some() {
+            return;
+        }
+
+        for (index, events) in events.into_iter().enumerate() {
+            let game = if index == 0 { &mut *player } else { &mut self.rival };
+
+            for lock in Lock::read(events) {
+                if lock.lines == 0 {
+                    // garbage rises when a piece locks without clearing
+                    self.sides[index].raise(game);
+                    continue;
+                }
+
+                let side = &mut self.sides[index];
+                let attack = lock.attack(&mut side.back_to_back, game.stack_height() == 0);
+                let attack = side.cancel(attack);
+
+                if attack > 0 {
+                    self.sides[1 - index].incoming.push_back(Garbage {
+                        rows: attack,
+                        hole: self.rng.below(COLUMNS),
+                    });
+                }
+            }
+        }
+
+        self.round = match (player.over, self.rival.over) {
+            (true, true) => Some(Round::Draw),
+            (true, false) => Some(Round::Won(1)),
+            (false, true) => Some(Round::Won(0)),
+            (false, false) => None,
+        };
+
+        if let Some(Round::Won(side)) = self.round {
+            self.sides[side].wins += 1;
+        }
+    }
+
+    /// Fresh boards for both, the wins carry over.
+    pub fn next_round(&mut self, player: &mut Game) {
+        self.rival = Self::deal(&mut self.rng, player);
+
+        for side in &mut self.sides {
+            side.incoming.clear();
+            side.back_to_back = false;
+        }
+
+        if let Some(bot) = &mut self.bot {
+            bot.reset();
+        }
+
+        self.round = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TShape;
+
+    const TETRIS: &[Event] = &[Event::Lock, Event::Clear(4)];
+    const DOUBLE: &[Event] = &[Event::Lock, Event::Clear(2)];
+    const DROP: &[Event] = &[Event::Lock];
+
+    /// A match with a garbage row under both boards, so no clear is a perfect one.
+    fn started() -> (Game, Versus) {
+        let mut player = Game::new(TShape::fixture(), Rng::new(1));
+        let mut versus = Versus::seeded(&mut player, Rounds::Three, 1, [String::from("Left"), String::from("Right")]);
+
+        player.insert_garbage(1, 0);
+        versus.rival.insert_garbage(1, 0);
+
+        (player, versus)
+    }
+
+    fn attacks(events: &[Event]) -> Vec<u32> {
+        let mut back_to_back = false;
+
+        Lock::read(events).iter().map(|lock| lock.attack(&mut back_to_back, false)).collect()
+    }
+
+    #[test]
+    fn locks_are_read_from_their_events() {
+        let locks = Lock::read(&[Event::Move, Event::Lock, Event::Clear(2), Event::Combo(3), Event::Lock, Event::Spin(1), Event::Clear(1)]);
+
+        assert_eq!(locks.len(), 2);
+        assert_eq!((locks[0].lines, locks[0].spin, locks[0].combo), (2, false, 3));
+        assert_eq!((locks[1].lines, locks[1].spin, locks[1].combo), (1, true, 1));
+    }
+
+    #[test]
+    fn attack_adds_combo_and_back_to_back() {
+        assert_eq!(attacks(&[Event::Lock, Event::Clear(1), Event::Lock, Event::Clear(2), Event::Lock, Event::Spin(2), Event::Clear(2)]), [0, 1, 4]);
+        assert_eq!(attacks(&[Event::Lock, Event::Clear(2), Event::Combo(5)]), [3]);
+
+        // a double between two tetrises breaks the streak
+        assert_eq!(attacks(&[TETRIS, TETRIS, DOUBLE, TETRIS].concat()), [4, 5, 1, 4]);
+
+        let mut back_to_back = false;
+
+        assert_eq!(Lock::read(DOUBLE)[0].attack(&mut back_to_back, true), 1 + PERFECT_CLEAR_ATTACK);
+    }
+
+    #[test]
+    fn cancel_eats_the_oldest_garbage_first() {
+        let mut side = Side::default();
+
+        side.incoming.extend([Garbage { rows: 2, hole: 0 }, Garbage { rows: 3, hole: 1 }]);
+
+        assert_eq!(side.cancel(4), 0);
+        assert_eq!(side.incoming, [Garbage { rows: 1, hole: 1 }]);
+        assert_eq!(side.cancel(3), 2);
+        assert_eq!(side.pending(), 0);
+    }
+
+    #[test]
+    fn clears_cancel_before_they_send() {
+        let (mut player, mut versus) = started();
+
+        versus.update(&mut player, [TETRIS, &[]]);
+
+        assert_eq!([versus.sides[0].pending(), versus.sides[1].pending()], [0, 4]);
+
+        versus.update(&mut player, [&[], DOUBLE]);
+
+        assert_eq!([versus.sides[0].pending(), versus.sides[1].pending()], [0, 3]);
+
+        versus.update(&mut player, [&[], TETRIS]);
+
+        assert_eq!([versus.sides[0].pending(), versus.sides[1].pending()], [1, 0]);
+    }
+
+    #[test]
+    fn at_most_four_rows_rise_per_piece() {
+        let (mut player, mut versus) = started();
+
+        versus.update(&mut player, [&[TETRIS, TETRIS].concat(), &[]]);
+
+        assert_eq!(versus.sides[1].pending(), 9);
+
+        versus.update(&mut player, [&[], DROP]);
+
+        assert_eq!(versus.sides[1].pending(), 5);
+        assert_eq!(versus.rival.stack_height(), 5);
+
+        versus.update(&mut player, [&[], DROP]);
+
+        assert_eq!(versus.sides[1].pending(), 1);
+        assert_eq!(versus.rival.stack_height(), 9);
+    }
+
+    #[test]
+    fn perfect_clears_send_extra() {
+        let mut player = Game::new(TShape::fixture(), Rng::new(1));
+        let mut versus = Versus::seeded(&mut player, Rounds::Three, 1, [String::from("Left"), String::from("Right")]);
+
+        versus.update(&mut player, [DOUBLE, &[]]);
+
+        assert_eq!(versus.sides[1].pending(), 1 + PERFECT_CLEAR_ATTACK);
+    }
+}